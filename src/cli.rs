@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::fs::{create_dir_all, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::{AdapterErr, ErrCode, ADAPTER_NAME};
+use crate::env::{config_key, AdapterConfig, Env, CONF_PATH};
+use crate::model::{AOE_ID_START, DFF_ID_START, aoes_to_south, dffs_to_south, points_to_south, transports_to_south, validate_aoes, validate_dffs, validate_points, validate_transports};
+use crate::model::north::{MyAoes, MyDffModels, MyPoints, MyTransports, ValidateErr, ValidateResult, ValidateTarget};
+use crate::model::south::{AoeModel, DffModel, Measurement, Transport};
+use crate::parser::{dump_mappings, export_db, import_db, MappingDump};
+use crate::runner::run_adapter;
+use crate::utils::memsmqtt::do_meter_data_query;
+use crate::utils::plccmqtt::build_dev_mapping;
+
+const USAGE: &str = "用法: adapter [--config <配置文件>] [--<配置项> <值>]... [命令]
+
+命令:
+  serve                          启动服务，不指定命令时默认执行
+  config check                   校验配置并输出生效的配置
+  validate <目录>                离线解析并转换目录中的配置JSON，输出校验结果
+  convert <目录> --out <目录>    离线转换配置，写出南向测点、通道、策略和报表JSON
+  mapping dump [--out <文件>]    输出数据库中的测点、设备、策略和报表映射
+  db export [--out <文件>]       将数据库导出为JSON Lines
+  db import <文件>               导入db export导出的文件，需要先停止服务
+  meter-csv [--out <文件>]       查询一次电表冻结数据并输出CSV
+
+选项:
+  -c, --config <文件>            配置文件路径，默认为程序所在目录下的adapter，扩展名为toml时按TOML解析
+  --<配置项> <值>                覆盖配置项，如--http-server-port 8080或--mqttServer=host:1883
+  -o, --out <路径>               输出路径，默认输出到标准输出
+  -h, --help                     显示帮助
+
+配置优先级从低到高为默认值、配置文件、ADAPTER_*环境变量（如ADAPTER_HTTP_SERVER_PORT）和命令行参数
+";
+
+/// 转换后的南向配置，没有对应的北向配置文件时为空
+#[derive(Default)]
+struct SouthConfig {
+    points: Option<Vec<Measurement>>,
+    transports: Option<Vec<Transport>>,
+    aoes: Option<Vec<AoeModel>>,
+    dffs: Option<Vec<DffModel>>,
+}
+
+/// 解析命令行参数并执行，参数不含程序名
+pub async fn run_cli(args: Vec<String>) -> ExitCode {
+    let mut out = None;
+    let mut config_args = vec![];
+    let mut commands = vec![];
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        if arg == "-h" || arg == "--help" {
+            print!("{USAGE}");
+            return ExitCode::SUCCESS;
+        }
+        let Some(name) = arg.strip_prefix("--").or_else(|| arg.strip_prefix('-').filter(|n| *n == "c" || *n == "o")) else {
+            commands.push(arg);
+            continue;
+        };
+        let (name, value) = match name.split_once('=') {
+            Some((n, v)) => (n.to_string(), Some(v.to_string())),
+            None => (name.to_string(), None),
+        };
+        let key = match name.as_str() {
+            "c" | "config" => CONF_PATH.to_string(),
+            "o" | "out" => String::new(),
+            _ => match config_key(&name) {
+                Some(key) => key,
+                None => return usage_exit(&format!("未知的参数：{arg}")),
+            },
+        };
+        let Some(value) = value.or_else(|| iter.next()) else {
+            return usage_exit(&format!("参数{arg}缺少值"));
+        };
+        if key.is_empty() {
+            out = Some(value);
+        } else {
+            config_args.push((key, value));
+        }
+    }
+    Env::set_cli_args(config_args);
+    let commands = commands.iter().map(|s| s.as_str()).collect::<Vec<&str>>();
+    if !matches!(commands.as_slice(), [] | ["serve"] | ["config", "check"]) {
+        init_env();
+    }
+    let result = match commands.as_slice() {
+        [] | ["serve"] => run_adapter().await.map_err(|e| AdapterErr {
+            code: ErrCode::InternalErr,
+            msg: format!("服务异常退出：{e}"),
+        }),
+        ["config", "check"] => config_check(out.as_deref()),
+        ["validate", dir] => validate(dir),
+        ["convert", dir] => match &out {
+            Some(out) => convert(dir, out),
+            None => return usage_exit("convert需要指定--out"),
+        },
+        ["mapping", "dump"] => mapping_dump(out.as_deref()),
+        ["db", "export"] => db_export(out.as_deref()),
+        ["db", "import", file] => db_import(file),
+        ["meter-csv"] => meter_csv(out.as_deref()).await,
+        _ => return usage_exit(&format!("未知的命令：{}", commands.join(" "))),
+    };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e.msg);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// 离线命令只提示配置错误，出错的配置项使用默认值
+fn init_env() {
+    let loaded = AdapterConfig::load();
+    for e in loaded.errors.iter().chain(loaded.warnings.iter()) {
+        eprintln!("配置警告：{e}");
+    }
+    Env::update(ADAPTER_NAME, Env { config: loaded.config });
+}
+
+fn config_check(out: Option<&str>) -> Result<(), AdapterErr> {
+    let loaded = AdapterConfig::load();
+    for w in &loaded.warnings {
+        eprintln!("配置警告：{w}");
+    }
+    for e in &loaded.errors {
+        eprintln!("配置错误：{e}");
+    }
+    write_json(out, &loaded.config.to_values(false))?;
+    if loaded.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(AdapterErr {
+            code: ErrCode::InternalErr,
+            msg: format!("配置校验失败，共{}个错误", loaded.errors.len()),
+        })
+    }
+}
+
+fn usage_exit(msg: &str) -> ExitCode {
+    eprintln!("{msg}\n\n{USAGE}");
+    ExitCode::from(2)
+}
+
+fn io_err(path: &str, e: io::Error) -> AdapterErr {
+    AdapterErr {
+        code: ErrCode::IoErr,
+        msg: format!("读写{path}失败：{e}"),
+    }
+}
+
+// 没有指定输出文件时写到标准输出
+fn open_output(out: Option<&str>) -> Result<Box<dyn Write>, AdapterErr> {
+    match out {
+        Some(path) => {
+            let file = File::create(path).map_err(|e| io_err(path, e))?;
+            Ok(Box::new(BufWriter::new(file)))
+        }
+        None => Ok(Box::new(io::stdout().lock())),
+    }
+}
+
+fn write_json<T: Serialize>(out: Option<&str>, value: &T) -> Result<(), AdapterErr> {
+    let mut writer = open_output(out)?;
+    let content = serde_json::to_string_pretty(value).map_err(|e| AdapterErr {
+        code: ErrCode::InternalErr,
+        msg: format!("序列化失败：{e}"),
+    })?;
+    writeln!(writer, "{content}")
+        .and_then(|_| writer.flush())
+        .map_err(|e| io_err(out.unwrap_or("stdout"), e))
+}
+
+fn read_json<T: DeserializeOwned>(file_name: &str, not_found: ErrCode, deserialize_err: ErrCode, name: &str) -> Result<T, AdapterErr> {
+    let file = File::open(file_name).map_err(|_| AdapterErr {
+        code: not_found,
+        msg: format!("{name}JSON文件不存在：{file_name}"),
+    })?;
+    serde_json::from_reader(BufReader::new(file)).map_err(|err| AdapterErr {
+        code: deserialize_err,
+        msg: format!("{name}JSON反序列化失败：{err}"),
+    })
+}
+
+// 沿用数据库中已有的映射分配南向id，数据库不存在时按新配置分配
+fn load_mappings() -> MappingDump {
+    let env = Env::get_env(ADAPTER_NAME);
+    dump_mappings(&env.get_db_dir()).unwrap_or_else(|e| {
+        eprintln!("未读取到已有映射，按新配置分配id：{}", e.msg);
+        MappingDump::default()
+    })
+}
+
+/// 离线转换目录中的完整配置，测点和通道文件必须存在，策略和报表文件可选
+fn convert_dir(dir: &str, mappings: &MappingDump) -> (ValidateResult, SouthConfig) {
+    let env = Env::get_env(ADAPTER_NAME);
+    let mut errors = vec![];
+    let mut south = SouthConfig::default();
+    let points = match read_json::<MyPoints>(&format!("{dir}/{}", env.get_point_dir()), ErrCode::PointJsonNotFound, ErrCode::PointJsonDeserializeErr, "测点") {
+        Ok(points) => points,
+        Err(e) => return (ValidateResult::new(vec![ValidateErr::new(ValidateTarget::Point, None, e)]), south),
+    };
+    // 测点
+    let (mut points_mapping, point_errors) = validate_points(&points);
+    let mut point_param = HashMap::new();
+    let mut point_discrete = HashMap::new();
+    if point_errors.is_empty() {
+        let old_point_mapping = mappings.points.clone().into_iter().collect::<HashMap<String, u64>>();
+        match points_to_south(points, &old_point_mapping) {
+            Ok((measurements, mapping, param, discrete, _)) => {
+                south.points = Some(measurements);
+                points_mapping = mapping;
+                point_param = param;
+                point_discrete = discrete;
+            }
+            Err(e) => errors.push(ValidateErr::new(ValidateTarget::Point, None, e)),
+        }
+    }
+    errors.extend(point_errors);
+    // 通道，测点属性使用数据库中缓存的数据中心设备信息，没有缓存时不校验属性也不转换
+    match read_json::<MyTransports>(&format!("{dir}/{}", env.get_transport_dir()), ErrCode::TransportJsonNotFound, ErrCode::TransportJsonDeserializeErr, "通道") {
+        Ok(transports) => {
+            let dev_mapping = (!mappings.devs.is_empty()).then(|| build_dev_mapping(&mappings.devs));
+            let transport_errors = validate_transports(&transports, &points_mapping, dev_mapping.as_ref());
+            if let (true, Some(_), Some(dev_mapping)) = (transport_errors.is_empty(), &south.points, dev_mapping) {
+                match transports_to_south(transports, &points_mapping, &dev_mapping, &point_param, &point_discrete) {
+                    Ok((transports, _)) => south.transports = Some(transports),
+                    Err(e) => errors.push(ValidateErr::new(ValidateTarget::Transport, None, e)),
+                }
+            }
+            errors.extend(transport_errors);
+        }
+        Err(e) => errors.push(ValidateErr::new(ValidateTarget::Transport, None, e)),
+    }
+    // 策略
+    let aoe_file = format!("{dir}/{}", env.get_aoe_dir());
+    if Path::new(&aoe_file).is_file() {
+        match read_json::<MyAoes>(&aoe_file, ErrCode::AoeJsonNotFound, ErrCode::AoeJsonDeserializeErr, "策略") {
+            Ok(aoes) => {
+                let aoe_errors = validate_aoes(&aoes, &points_mapping);
+                if aoe_errors.is_empty() {
+                    let old_aoe_mapping = mappings.aoes.clone().into_iter().collect::<HashMap<u64, u64>>();
+                    match aoes_to_south(aoes, &points_mapping, &old_aoe_mapping, mappings.aoe_last_id.max(AOE_ID_START - 1)) {
+                        Ok((aoes, _, _)) => south.aoes = Some(aoes),
+                        Err(e) => errors.push(ValidateErr::new(ValidateTarget::Aoe, None, e)),
+                    }
+                }
+                errors.extend(aoe_errors);
+            }
+            Err(e) => errors.push(ValidateErr::new(ValidateTarget::Aoe, None, e)),
+        }
+    }
+    // 报表
+    let dff_file = format!("{dir}/{}", env.get_dff_dir());
+    if Path::new(&dff_file).is_file() {
+        match read_json::<MyDffModels>(&dff_file, ErrCode::DffJsonNotFound, ErrCode::DffJsonDeserializeErr, "报表") {
+            Ok(dffs) => {
+                let dff_errors = validate_dffs(&dffs, &points_mapping);
+                if dff_errors.is_empty() {
+                    let old_dff_mapping = mappings.dffs.clone().into_iter().collect::<HashMap<u64, u64>>();
+                    match dffs_to_south(dffs, &points_mapping, &old_dff_mapping, mappings.dff_last_id.max(DFF_ID_START - 1)) {
+                        Ok((dffs, _, _)) => south.dffs = Some(dffs),
+                        Err(e) => errors.push(ValidateErr::new(ValidateTarget::Dff, None, e)),
+                    }
+                }
+                errors.extend(dff_errors);
+            }
+            Err(e) => errors.push(ValidateErr::new(ValidateTarget::Dff, None, e)),
+        }
+    }
+    (ValidateResult::new(errors), south)
+}
+
+fn validate(dir: &str) -> Result<(), AdapterErr> {
+    let (result, _) = convert_dir(dir, &load_mappings());
+    write_json(None, &result)?;
+    if result.code == ErrCode::Success {
+        Ok(())
+    } else {
+        Err(AdapterErr {
+            code: result.code,
+            msg: format!("校验失败，共{}个错误", result.errors.len()),
+        })
+    }
+}
+
+fn convert(dir: &str, out_dir: &str) -> Result<(), AdapterErr> {
+    let (result, south) = convert_dir(dir, &load_mappings());
+    if result.code != ErrCode::Success {
+        write_json(None, &result)?;
+        return Err(AdapterErr {
+            code: result.code,
+            msg: format!("转换失败，共{}个错误", result.errors.len()),
+        });
+    }
+    create_dir_all(out_dir).map_err(|e| io_err(out_dir, e))?;
+    let env = Env::get_env(ADAPTER_NAME);
+    if let Some(points) = &south.points {
+        write_json(Some(&format!("{out_dir}/{}", env.get_point_dir())), points)?;
+    }
+    match &south.transports {
+        Some(transports) => write_json(Some(&format!("{out_dir}/{}", env.get_transport_dir())), transports)?,
+        None => eprintln!("数据库中没有数据中心设备信息，未生成通道，请先通过服务更新一次PLCC配置"),
+    }
+    if let Some(aoes) = &south.aoes {
+        write_json(Some(&format!("{out_dir}/{}", env.get_aoe_dir())), aoes)?;
+    }
+    if let Some(dffs) = &south.dffs {
+        write_json(Some(&format!("{out_dir}/{}", env.get_dff_dir())), dffs)?;
+    }
+    eprintln!("已写入{out_dir}");
+    Ok(())
+}
+
+fn mapping_dump(out: Option<&str>) -> Result<(), AdapterErr> {
+    let env = Env::get_env(ADAPTER_NAME);
+    write_json(out, &dump_mappings(&env.get_db_dir())?)
+}
+
+fn db_export(out: Option<&str>) -> Result<(), AdapterErr> {
+    let env = Env::get_env(ADAPTER_NAME);
+    let mut writer = open_output(out)?;
+    let count = export_db(&env.get_db_dir(), &mut writer)?;
+    writer.flush().map_err(|e| io_err(out.unwrap_or("stdout"), e))?;
+    eprintln!("共导出{count}条记录");
+    Ok(())
+}
+
+fn db_import(file_name: &str) -> Result<(), AdapterErr> {
+    let env = Env::get_env(ADAPTER_NAME);
+    let file = File::open(file_name).map_err(|e| io_err(file_name, e))?;
+    let count = import_db(&env.get_db_dir(), BufReader::new(file))?;
+    eprintln!("共导入{count}条记录");
+    Ok(())
+}
+
+async fn meter_csv(out: Option<&str>) -> Result<(), AdapterErr> {
+    let csv = do_meter_data_query().await?;
+    let mut writer = open_output(out)?;
+    writer.write_all(csv.as_bytes())
+        .and_then(|_| writer.flush())
+        .map_err(|e| io_err(out.unwrap_or("stdout"), e))
+}
@@ -529,6 +529,15 @@ pub fn parse_path_values<T: std::str::FromStr>(path: &str, pat: char) -> Vec<T>
     vec
 }
 
+/// 各列族的估算数据大小，字节
+pub fn query_cf_sizes(inner_db: &DB, tree_names: &[&str]) -> Vec<(String, u64)> {
+    tree_names.iter().filter_map(|name| {
+        let tree = inner_db.cf_handle(name)?;
+        let size = inner_db.property_int_value_cf(&tree, "rocksdb.estimate-live-data-size").ok()??;
+        Some((name.to_string(), size))
+    }).collect()
+}
+
 #[test]
 fn test_parse_path_values() {
     let path = "100001";
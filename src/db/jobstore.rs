@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+use std::sync::{Mutex, RwLock};
+
+use chrono::Local;
+use once_cell::sync::Lazy;
+use rocksdb::DB;
+use serde::Serialize;
+
+use crate::{ADAPTER_NAME, AdapterErr, ApiResponse, ErrCode};
+use crate::db::dbutils::*;
+use crate::db::mydb;
+use crate::env::Env;
+use crate::model::job::{Job, JobKind, JobStatus};
+use crate::utils::events::{publish, AdapterEvent};
+use crate::utils::metrics::{inc_counter, observe, JOBS_FINISHED, JOB_STAGE_DURATION};
+
+const JOB_TREE: &str = "job";
+// 内存和数据库中最多保留的任务数量
+const MAX_JOBS: usize = 100;
+
+/// 配置更新任务的状态，内存中保存最近的任务，同时写入数据库目录旁的独立RocksDB
+pub static JOBS: Lazy<JobStore> = Lazy::new(|| {
+    let env = Env::get_env(ADAPTER_NAME);
+    JobStore::open(&format!("{}_job", env.get_db_dir()))
+});
+
+pub struct JobStore {
+    path: String,
+    inner_db: Option<DB>,
+    jobs: RwLock<BTreeMap<u64, Job>>,
+    // 当前阶段的开始时间，毫秒
+    stage_since: Mutex<i64>,
+}
+
+impl JobStore {
+    fn open(file_path: &str) -> JobStore {
+        let mut inner_db = None;
+        // 保证不会重复打开错误的db，导致文件系统崩溃
+        if !mydb::is_error_db_path(file_path) {
+            let mut opts = rocksdb::Options::default();
+            opts.create_if_missing(true);
+            opts.create_missing_column_families(true);
+            match DB::open_cf(&opts, file_path, [JOB_TREE]) {
+                Ok(db) => inner_db = Some(db),
+                Err(_) => {
+                    mydb::add_error_db_path(file_path);
+                    log::error!("open job db {:?} error", file_path);
+                }
+            }
+        }
+        let mut jobs = BTreeMap::new();
+        if let Some(db) = &inner_db {
+            let now = Local::now().timestamp_millis();
+            for mut job in query_values_cbor_with_tree_name::<Job>(db, JOB_TREE) {
+                // 重启前未完成的任务已经中断
+                if !job.status.is_finished() {
+                    job.status = JobStatus::Failed;
+                    job.code = Some(ErrCode::InternalErr);
+                    job.msg = Some("服务重启，任务中断".to_string());
+                    job.finished_at = Some(now);
+                    save_job(db, &job);
+                }
+                jobs.insert(job.id, job);
+            }
+        }
+        JobStore { path: file_path.to_string(), inner_db, jobs: RwLock::new(jobs), stage_since: Mutex::new(0) }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.inner_db.is_some()
+    }
+
+    /// 将内存中的数据写入磁盘，退出前调用
+    pub fn flush(&self) {
+        if let Some(Err(e)) = self.inner_db.as_ref().map(|db| db.flush()) {
+            log::error!("flush job db error: {e}");
+        }
+    }
+
+    pub fn cf_sizes(&self) -> Vec<(String, u64)> {
+        self.inner_db.as_ref().map(|db| query_cf_sizes(db, &[JOB_TREE])).unwrap_or_default()
+    }
+
+    /// 创建任务，已有更新任务未完成时拒绝
+    pub fn submit(&self, kind: JobKind) -> Result<Job, AdapterErr> {
+        let mut jobs = self.jobs.write().unwrap();
+        if let Some(job) = jobs.values().find(|j| !j.status.is_finished()) {
+            return Err(AdapterErr {
+                code: ErrCode::JobRunning,
+                msg: format!("任务{}正在执行，请等待完成后再提交", job.id),
+            });
+        }
+        let id = jobs.keys().next_back().map(|id| id + 1).unwrap_or(1);
+        let job = Job {
+            id,
+            kind,
+            status: JobStatus::Queued,
+            stage: None,
+            code: None,
+            msg: None,
+            details: None,
+            created_at: Local::now().timestamp_millis(),
+            started_at: None,
+            finished_at: None,
+        };
+        jobs.insert(id, job.clone());
+        self.persist(&job);
+        // 清理最早的已完成任务
+        while jobs.len() > MAX_JOBS {
+            let Some((first, _)) = jobs.iter().find(|(_, j)| j.status.is_finished()) else {
+                break;
+            };
+            let first = *first;
+            jobs.remove(&first);
+            if let Some(db) = &self.inner_db {
+                delete_item_by_key_with_tree_name(db, JOB_TREE, &first.to_be_bytes());
+            }
+        }
+        Ok(job)
+    }
+
+    pub fn start(&self, id: u64) {
+        *self.stage_since.lock().unwrap() = Local::now().timestamp_millis();
+        self.update(id, |job| {
+            job.status = JobStatus::Running;
+            job.started_at = Some(Local::now().timestamp_millis());
+        });
+    }
+
+    /// 记录正在执行的任务所处的阶段，没有正在执行的任务时忽略
+    pub fn set_stage<S: Serialize>(&self, stage: S) {
+        let Some(stage) = serde_json::to_value(stage).ok().and_then(|v| v.as_str().map(|s| s.to_string())) else {
+            return;
+        };
+        let running = self.jobs.read().unwrap().values()
+            .find(|j| j.status == JobStatus::Running)
+            .map(|j| j.id);
+        if let Some(id) = running {
+            self.update(id, |job| {
+                self.observe_stage(job);
+                job.stage = Some(stage);
+            });
+        }
+    }
+
+    pub fn finish(&self, id: u64, resp: ApiResponse) {
+        self.update(id, |job| {
+            if job.status == JobStatus::Running {
+                self.observe_stage(job);
+            }
+            job.status = if resp.code == ErrCode::Success { JobStatus::Success } else { JobStatus::Failed };
+            // 失败时保留出错的阶段
+            if resp.stage.is_some() || job.status == JobStatus::Success {
+                job.stage = resp.stage;
+            }
+            job.code = Some(resp.code);
+            job.msg = Some(resp.msg);
+            job.details = resp.details;
+            job.finished_at = Some(Local::now().timestamp_millis());
+            inc_counter(JOBS_FINISHED, &[("kind", &job_label(job.kind)), ("status", &job_label(job.status))]);
+        });
+    }
+
+    // 记录上一阶段的耗时，并开始计时下一阶段
+    fn observe_stage(&self, job: &Job) {
+        let now = Local::now().timestamp_millis();
+        let since = std::mem::replace(&mut *self.stage_since.lock().unwrap(), now);
+        if let Some(stage) = &job.stage {
+            let secs = (now - since).max(0) as f64 / 1000.0;
+            observe(JOB_STAGE_DURATION, &[("kind", &job_label(job.kind)), ("stage", stage)], secs);
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<Job> {
+        self.jobs.read().unwrap().get(&id).cloned()
+    }
+
+    /// 按创建时间倒序返回
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs.read().unwrap().values().rev().cloned().collect()
+    }
+
+    fn update(&self, id: u64, f: impl FnOnce(&mut Job)) {
+        let job = {
+            let mut jobs = self.jobs.write().unwrap();
+            let Some(job) = jobs.get_mut(&id) else {
+                return;
+            };
+            f(job);
+            job.clone()
+        };
+        self.persist(&job);
+    }
+
+    fn persist(&self, job: &Job) {
+        if let Some(db) = &self.inner_db {
+            save_job(db, job);
+        }
+        publish(AdapterEvent::Job { job: job.clone() });
+    }
+}
+
+// 带参数的任务类型序列化为对象，取其变体名
+fn job_label<S: Serialize>(value: S) -> String {
+    match serde_json::to_value(value) {
+        Ok(serde_json::Value::String(s)) => s,
+        Ok(serde_json::Value::Object(map)) => map.keys().next().cloned().unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+fn save_job(db: &DB, job: &Job) {
+    if !save_item_cbor_to_db_with_tree_name(db, JOB_TREE, job, |j| j.id.to_be_bytes().to_vec()) {
+        log::error!("保存任务{}状态失败", job.id);
+    }
+}
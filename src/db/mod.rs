@@ -1,2 +1,4 @@
 pub mod mydb;
-pub mod dbutils;
\ No newline at end of file
+pub mod dbutils;
+pub mod outbox;
+pub mod jobstore;
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use chrono::Local;
+use once_cell::sync::Lazy;
+use rocksdb::{WriteBatch, DB};
+use serde::{Deserialize, Serialize};
+
+use crate::{ADAPTER_NAME, AdapterErr, ErrCode};
+use crate::db::dbutils::*;
+use crate::db::mydb;
+use crate::env::Env;
+
+const MESSAGE_TREE: &str = "message";
+const WATERMARK_TREE: &str = "watermark";
+pub const WATERMARK_AOE: &str = "aoe";
+pub const WATERMARK_DFF: &str = "dff";
+// 重试间隔从5秒开始翻倍，最长5分钟
+const RETRY_BASE_SECS: i64 = 5;
+const RETRY_MAX_SECS: i64 = 300;
+
+/// 策略和报表结果上传的待发送队列，保存在数据库目录旁的独立RocksDB中
+pub static OUTBOX: Lazy<Option<Outbox>> = Lazy::new(|| {
+    let env = Env::get_env(ADAPTER_NAME);
+    Outbox::open(&format!("{}_outbox", env.get_db_dir()))
+});
+
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone, Copy)]
+pub enum OutboxKind {
+    AoeUpdate,
+    AoeSet,
+    DffUpdate,
+    DffSet,
+}
+
+impl OutboxKind {
+    pub fn is_aoe(&self) -> bool {
+        matches!(self, OutboxKind::AoeUpdate | OutboxKind::AoeSet)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OutboxMessage {
+    pub id: u64,
+    pub kind: OutboxKind,
+    pub topic: String,
+    pub payload: String,
+    /// 入队时间，毫秒
+    pub created_at: i64,
+    /// 已失败的次数
+    pub attempts: u32,
+    /// 下次允许发送的时间，毫秒
+    pub next_retry_at: i64,
+}
+
+pub struct Outbox {
+    inner_db: DB,
+    // 保证消息id递增
+    last_id: Mutex<u64>,
+}
+
+impl Outbox {
+    pub fn open(file_path: &str) -> Option<Outbox> {
+        // 保证不会重复打开错误的db，导致文件系统崩溃
+        if mydb::is_error_db_path(file_path) {
+            return None;
+        }
+        let mut opts = rocksdb::Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+        if let Ok(inner_db) = DB::open_cf(&opts, file_path, [MESSAGE_TREE, WATERMARK_TREE]) {
+            let last_id = query_end_key_with_tree_name_as_u64(&inner_db, MESSAGE_TREE).unwrap_or(0);
+            Some(Outbox { inner_db, last_id: Mutex::new(last_id) })
+        } else {
+            mydb::add_error_db_path(file_path);
+            log::error!("open outbox db {:?} error", file_path);
+            None
+        }
+    }
+
+    /// 消息和上传进度在同一批次中写入，保证重启后既不丢失也不重复
+    pub fn enqueue(&self, messages: Vec<(OutboxKind, String, String)>, watermark_key: &str, watermark: &HashMap<u64, u64>) -> Result<(), AdapterErr> {
+        let (Some(message_tree), Some(watermark_tree)) = (self.inner_db.cf_handle(MESSAGE_TREE), self.inner_db.cf_handle(WATERMARK_TREE)) else {
+            return Err(outbox_err("待发送队列的表不存在".to_string()));
+        };
+        let now = Local::now().timestamp_millis();
+        let mut last_id = self.last_id.lock().unwrap();
+        let mut batch = WriteBatch::default();
+        let mut id = *last_id;
+        for (kind, topic, payload) in messages {
+            id += 1;
+            let message = OutboxMessage {
+                id,
+                kind,
+                topic,
+                payload,
+                created_at: now,
+                attempts: 0,
+                next_retry_at: now,
+            };
+            let value = serde_cbor::to_vec(&message).map_err(|e| outbox_err(format!("消息序列化失败：{e}")))?;
+            batch.put_cf(&message_tree, id.to_be_bytes(), value);
+        }
+        let value = serde_cbor::to_vec(watermark).map_err(|e| outbox_err(format!("上传进度序列化失败：{e}")))?;
+        batch.put_cf(&watermark_tree, watermark_key.as_bytes(), value);
+        self.inner_db.write(batch).map_err(|e| outbox_err(format!("写入待发送队列失败：{e}")))?;
+        *last_id = id;
+        Ok(())
+    }
+
+    /// 按入队顺序返回已到重试时间的消息，遇到未到重试时间的消息即停止，保证按顺序发送
+    pub fn due_messages(&self, is_aoe: bool) -> Vec<OutboxMessage> {
+        let now = Local::now().timestamp_millis();
+        query_values_cbor_with_tree_name::<OutboxMessage>(&self.inner_db, MESSAGE_TREE)
+            .into_iter()
+            .filter(|m| m.kind.is_aoe() == is_aoe)
+            .take_while(|m| m.next_retry_at <= now)
+            .collect()
+    }
+
+    pub fn remove(&self, id: u64) -> bool {
+        delete_item_by_key_with_tree_name(&self.inner_db, MESSAGE_TREE, &id.to_be_bytes())
+    }
+
+    pub fn mark_failed(&self, mut message: OutboxMessage) {
+        let delay = (RETRY_BASE_SECS << message.attempts.min(6)).min(RETRY_MAX_SECS);
+        message.attempts += 1;
+        message.next_retry_at = Local::now().timestamp_millis() + delay * 1000;
+        if !save_item_cbor_to_db_with_tree_name(&self.inner_db, MESSAGE_TREE, message, |m| m.id.to_be_bytes().to_vec()) {
+            log::error!("更新待发送消息的重试时间失败");
+        }
+    }
+
+    /// 将内存中的数据写入磁盘，退出前调用
+    pub fn flush(&self) {
+        if let Err(e) = self.inner_db.flush() {
+            log::error!("flush outbox db error: {e}");
+        }
+    }
+
+    pub fn cf_sizes(&self) -> Vec<(String, u64)> {
+        query_cf_sizes(&self.inner_db, &[MESSAGE_TREE, WATERMARK_TREE])
+    }
+
+    pub fn query_watermark(&self, watermark_key: &str) -> HashMap<u64, u64> {
+        query_value_cbor_by_key_with_tree_name(&self.inner_db, WATERMARK_TREE, watermark_key).unwrap_or_default()
+    }
+}
+
+fn outbox_err(msg: String) -> AdapterErr {
+    AdapterErr {
+        code: ErrCode::InternalErr,
+        msg,
+    }
+}
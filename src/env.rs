@@ -1,10 +1,13 @@
-use std::{collections::HashMap, fmt::Debug};
+use std::collections::{BTreeMap, HashMap};
 use std::env;
-use std::io::{BufRead, BufReader};
+use std::fmt::{self, Display, Formatter};
 use std::path::{Path, PathBuf};
-use std::sync::{LazyLock, Mutex};
+use std::str::FromStr;
+use std::sync::{LazyLock, Mutex, OnceLock};
 
 use log::{error, info, warn};
+use serde::Serialize;
+use toml::de::{DeTable, DeValue};
 use crate::utils::log_init::LogConfig;
 
 // const DEFAULT_CONFIG_PATH : &str = "config";
@@ -17,9 +20,25 @@ pub const IS_USE_MEMS: &str = "isUseMems";
 pub const MEMS_SERVER: &str = "memsServer";
 pub const MEMS_USER: &str = "memsUser";
 pub const MEMS_PWD: &str = "memsPwd";
+// 调用PLCC和MEMS接口的超时时间（秒）、重试次数和登录token的有效期（秒）
+pub const HTTP_CONNECT_TIMEOUT: &str = "httpConnectTimeout";
+pub const HTTP_REQUEST_TIMEOUT: &str = "httpRequestTimeout";
+pub const HTTP_RETRY_NUM: &str = "httpRetryNum";
+pub const HTTP_TOKEN_TTL: &str = "httpTokenTtl";
 pub const MQTT_SERVER: &str = "mqttServer";
 pub const HTTP_SERVER_PORT: &str = "httpServerPort";
 pub const MQTT_TIMEOUT: &str = "mqttTimeout";
+// 数据中心MQTT的服务质量，控制类主题（以逗号分隔的主题后缀）单独设置，保留消息的主题同样以后缀匹配
+pub const MQTT_QOS: &str = "mqttQos";
+pub const MQTT_CONTROL_QOS: &str = "mqttControlQos";
+pub const MQTT_CONTROL_TOPICS: &str = "mqttControlTopics";
+pub const MQTT_RETAIN_TOPICS: &str = "mqttRetainTopics";
+// 数据中心MQTT开启SSL时使用的CA证书，客户端证书使用sslCertFilePath和sslKeyFilePath
+pub const MQTT_SSL_CA_FILE_PATH: &str = "mqttSslCaFilePath";
+// 策略和报表结果的上传间隔（秒），以及策略结果是否从上次上传进度开始补传，最多补传的天数
+pub const RESULT_UPLOAD_INTERVAL: &str = "resultUploadInterval";
+pub const AOE_CATCH_UP: &str = "aoeCatchUp";
+pub const AOE_CATCH_UP_MAX_DAYS: &str = "aoeCatchUpMaxDays";
 pub const POINT_FILE_DIR: &str = "pointFileDir";
 pub const TRANSPORT_DIR: &str = "transportFileDir";
 pub const AOE_DIR: &str = "aoeFileDir";
@@ -75,14 +94,17 @@ pub const DATABASE_URL: &str = "databaseUrl";
 pub const METER_SUM_NO: &str = "meterSumNo";
 pub const METER_DIR: &str = "meterFileDir";
 
-const CONFIG_ARGS: [&str; 52] = [CONF_PATH, BEE_ID, MQTT_SERVER, MQTT_AUTH, HTTP_SERVER_PORT,
+const CONFIG_ARGS: [&str; 64] = [CONF_PATH, BEE_ID, MQTT_SERVER, MQTT_AUTH, HTTP_SERVER_PORT,
     MQTT_CLIENT_BUF_SIZE, MQTT_MV_LIMIT, SOCKET_BUF_SIZE_NORTH, SOCKET_BUF_SIZE_SOUTH, EXE_ROOT_DIR,
     POINT_FILE_DIR, TRANSPORT_DIR, JSON_DIR, RESULT_DIR, AOE_DIR, WEB_DIR, DB_DIR, LOG_DIR,
     APP_NAME, APP_MODEL, MQTT_PACKAGE_MAX_SIZE, IS_LOCAL_FRONTEND, IS_DB, IS_HIS_DB, IS_FAKE_DELETE,
     DB_DIR_SIZE_LIMIT, IS_LOCAL_MQTT, LOCAL_MQTT_PORT, LOG_LEVEL, LOG_SAVE_TIME, LOG_SAVE_SIZE,
     LOG_HIS_FILE_NUM, MQTT_TIMEOUT, DATABASE_URL, PLCC_SERVER, METER_SUM_NO, METER_DIR, DFF_DIR,
     PLCC_USER, PLCC_PWD, EIG_HOME, IS_USE_SSL, SSL_CERT_FILE_PATH, SSL_KEY_FILE_PATH,
-    MEMS_SERVER, MEMS_USER, MEMS_PWD, IS_USE_MEMS, PLCC_BEE_ID, MEMS_BEE_ID, PLCC_MQTT_PORT, MEMS_MQTT_PORT];
+    MEMS_SERVER, MEMS_USER, MEMS_PWD, IS_USE_MEMS, PLCC_BEE_ID, MEMS_BEE_ID, PLCC_MQTT_PORT, MEMS_MQTT_PORT,
+    HTTP_CONNECT_TIMEOUT, HTTP_REQUEST_TIMEOUT, HTTP_RETRY_NUM, HTTP_TOKEN_TTL,
+    MQTT_QOS, MQTT_CONTROL_QOS, MQTT_CONTROL_TOPICS, MQTT_RETAIN_TOPICS, MQTT_SSL_CA_FILE_PATH,
+    RESULT_UPLOAD_INTERVAL, AOE_CATCH_UP, AOE_CATCH_UP_MAX_DAYS];
 
 pub const PING_GET: u8 = 1;
 pub const CONFIG_GET: u8 = 2;
@@ -90,93 +112,538 @@ pub const CONFIG_PUT: u8 = 3;
 
 pub const POINTS_LIMIT_NUMBER: usize = 1000;
 
+// 配置文件中以此为扩展名时按TOML格式解析
+const TOML_EXTENSION: &str = "toml";
+// 以此为前缀的环境变量覆盖配置项，如ADAPTER_HTTP_SERVER_PORT
+const ENV_PREFIX: &str = "ADAPTER_";
+// 输出时需要隐藏的配置项
+const SENSITIVE_ARGS: [&str; 3] = [PLCC_PWD, MEMS_PWD, MQTT_AUTH];
+const LOG_LEVELS: [&str; 6] = ["trace", "debug", "info", "warn", "error", "off"];
+
 pub static ENV: LazyLock<Mutex<HashMap<String, Env>>> = LazyLock::new(||Mutex::new(HashMap::new()));
+// 命令行指定的配置项，优先级最高
+static CLI_ARGS: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+/// 配置项的来源，优先级从低到高
+#[derive(Debug, Clone, PartialEq)]
+pub enum ConfigSource {
+    Default,
+    File { path: String, line: usize },
+    Env(String),
+    Cli,
+}
+
+impl Display for ConfigSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => write!(f, "默认值"),
+            ConfigSource::File { path, line } => write!(f, "配置文件{path}第{line}行"),
+            ConfigSource::Env(name) => write!(f, "环境变量{name}"),
+            ConfigSource::Cli => write!(f, "命令行参数"),
+        }
+    }
+}
+
+/// 配置错误，key为空时为文件级错误
+#[derive(Serialize, Debug, Clone)]
+pub struct ConfigErr {
+    pub key: Option<String>,
+    pub value: Option<String>,
+    pub source: String,
+    pub msg: String,
+}
+
+impl ConfigErr {
+    fn new(key: Option<&str>, value: Option<&str>, source: &ConfigSource, msg: String) -> Self {
+        let value = match (key, value) {
+            (Some(k), Some(v)) => Some(mask_value(k, v)),
+            (_, v) => v.map(|v| v.to_string()),
+        };
+        ConfigErr { key: key.map(|k| k.to_string()), value, source: source.to_string(), msg }
+    }
+}
+
+impl Display for ConfigErr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match (&self.key, &self.value) {
+            (Some(key), Some(value)) => write!(f, "{key} = {value:?}（{}）：{}", self.source, self.msg),
+            (Some(key), None) => write!(f, "{key}（{}）：{}", self.source, self.msg),
+            _ => write!(f, "{}：{}", self.source, self.msg),
+        }
+    }
+}
+
+/// 加载配置的结果，errors中的配置项已替换为默认值，warnings为未识别的配置项等不影响运行的问题
+#[derive(Debug, Clone)]
+pub struct LoadedConfig {
+    pub config: AdapterConfig,
+    pub errors: Vec<ConfigErr>,
+    pub warnings: Vec<ConfigErr>,
+}
+
+/// 重新加载后发生变化的配置项，敏感值已隐藏
+#[derive(Serialize, Debug, Clone)]
+pub struct ConfigChange {
+    pub key: String,
+    pub old: String,
+    pub new: String,
+}
+
+/// 类型化的配置，依次由默认值、配置文件、ADAPTER_*环境变量和命令行参数覆盖得到，序列化后的字段名与配置项名称一致
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdapterConfig {
+    pub config_file_path: String,
+    pub exe_root_dir: String,
+    pub eig_home: String,
+    pub app_name: String,
+    pub app_model: String,
+    pub bee_id: String,
+    pub plcc_bee_id: String,
+    pub mems_bee_id: String,
+    pub http_server_port: u16,
+    // PLCC和MEMS
+    pub plcc_server: String,
+    pub plcc_user: String,
+    pub plcc_pwd: String,
+    pub plcc_mqtt_port: u16,
+    pub is_use_mems: bool,
+    pub mems_server: String,
+    pub mems_user: String,
+    pub mems_pwd: String,
+    pub mems_mqtt_port: u16,
+    pub http_connect_timeout: u64,
+    pub http_request_timeout: u64,
+    pub http_retry_num: usize,
+    pub http_token_ttl: u64,
+    // 数据中心MQTT，mqttServer为host:port
+    pub mqtt_server: String,
+    pub mqtt_credential: String,
+    pub mqtt_timeout: u64,
+    pub mqtt_qos: u8,
+    pub mqtt_control_qos: u8,
+    pub mqtt_control_topics: Vec<String>,
+    pub mqtt_retain_topics: Vec<String>,
+    pub mqtt_ssl_ca_file_path: String,
+    pub mqtt_client_buf_size: usize,
+    pub mqtt_mv_limit: usize,
+    pub mqtt_package_max_size: String,
+    pub is_local_mqtt: bool,
+    pub local_mqtt_port: u16,
+    pub is_use_ssl: bool,
+    pub ssl_cert_file_path: String,
+    pub ssl_key_file_path: String,
+    pub socket_buf_size_north: String,
+    pub socket_buf_size_south: String,
+    // 结果上传
+    pub result_upload_interval: u64,
+    pub aoe_catch_up: bool,
+    pub aoe_catch_up_max_days: i64,
+    // 文件和目录
+    pub point_file_dir: String,
+    pub transport_file_dir: String,
+    pub aoe_file_dir: String,
+    pub dff_file_dir: String,
+    pub json_file_dir: String,
+    pub result_file_dir: String,
+    pub web_file_dir: String,
+    pub db_file_dir: String,
+    #[serde(rename = "LogDir")]
+    pub log_dir: String,
+    pub meter_file_dir: String,
+    pub meter_sum_no: String,
+    pub is_local_frontend: bool,
+    pub is_db: bool,
+    pub is_his_db: bool,
+    pub is_fake_delete: bool,
+    pub db_dir_size_limit: String,
+    pub database_url: String,
+    // 日志
+    pub log_level: String,
+    pub log_save_time: String,
+    pub log_save_size: String,
+    pub log_his_file_num: u32,
+}
+
+impl AdapterConfig {
+    /// 按优先级叠加各层配置并校验
+    pub fn load() -> LoadedConfig {
+        let mut values = HashMap::with_capacity(CONFIG_ARGS.len());
+        let mut errors = vec![];
+        let mut warnings = vec![];
+        for (k, v) in default_values() {
+            values.insert(k.to_string(), (v, ConfigSource::Default));
+        }
+        let env_args = read_env_args(&mut warnings);
+        let cli_args = CLI_ARGS.get().cloned().unwrap_or_default();
+        // 配置文件路径本身可以由环境变量和命令行指定
+        let config_path = cli_args.iter().rev().find(|(k, _)| k == CONF_PATH).map(|(_, v)| v.clone())
+            .or_else(|| env_args.iter().rev().find(|(k, _, _)| k == CONF_PATH).map(|(_, v, _)| v.clone()))
+            .unwrap_or_else(|| Env::get_exe_root() + "/adapter");
+        if Path::new(&config_path).exists() {
+            info!("Load config file from {config_path}");
+            read_file(&config_path, &mut values, &mut errors, &mut warnings);
+        } else {
+            info!("no config file found at {config_path}, use default config");
+        }
+        for (k, v, source) in env_args {
+            values.insert(k, (v, source));
+        }
+        for (k, v) in cli_args {
+            values.insert(k, (v, ConfigSource::Cli));
+        }
+        values.insert(CONF_PATH.to_string(), (config_path, ConfigSource::Default));
+        let (config, value_errors) = AdapterConfig::from_values(&values);
+        errors.extend(value_errors);
+        LoadedConfig { config, errors, warnings }
+    }
+
+    fn from_values(values: &HashMap<String, (String, ConfigSource)>) -> (AdapterConfig, Vec<ConfigErr>) {
+        let mut p = FieldParser { values, errors: vec![] };
+        let config = AdapterConfig {
+            config_file_path: p.string(CONF_PATH),
+            exe_root_dir: p.string(EXE_ROOT_DIR),
+            eig_home: p.string(EIG_HOME),
+            app_name: p.non_empty(APP_NAME),
+            app_model: p.non_empty(APP_MODEL),
+            bee_id: p.string(BEE_ID),
+            plcc_bee_id: p.string(PLCC_BEE_ID),
+            mems_bee_id: p.string(MEMS_BEE_ID),
+            http_server_port: p.port(HTTP_SERVER_PORT),
+            plcc_server: p.url(PLCC_SERVER),
+            plcc_user: p.string(PLCC_USER),
+            plcc_pwd: p.string(PLCC_PWD),
+            plcc_mqtt_port: p.port(PLCC_MQTT_PORT),
+            is_use_mems: p.bool(IS_USE_MEMS),
+            mems_server: p.url(MEMS_SERVER),
+            mems_user: p.string(MEMS_USER),
+            mems_pwd: p.string(MEMS_PWD),
+            mems_mqtt_port: p.port(MEMS_MQTT_PORT),
+            http_connect_timeout: p.positive(HTTP_CONNECT_TIMEOUT),
+            http_request_timeout: p.positive(HTTP_REQUEST_TIMEOUT),
+            http_retry_num: p.parse(HTTP_RETRY_NUM, "非负整数"),
+            http_token_ttl: p.positive(HTTP_TOKEN_TTL),
+            mqtt_server: p.address(MQTT_SERVER),
+            mqtt_credential: p.string(MQTT_AUTH),
+            mqtt_timeout: p.positive(MQTT_TIMEOUT),
+            mqtt_qos: p.qos(MQTT_QOS),
+            mqtt_control_qos: p.qos(MQTT_CONTROL_QOS),
+            mqtt_control_topics: split_list(p.raw(MQTT_CONTROL_TOPICS)),
+            mqtt_retain_topics: split_list(p.raw(MQTT_RETAIN_TOPICS)),
+            mqtt_ssl_ca_file_path: p.string(MQTT_SSL_CA_FILE_PATH),
+            mqtt_client_buf_size: p.parse(MQTT_CLIENT_BUF_SIZE, "非负整数"),
+            mqtt_mv_limit: p.parse(MQTT_MV_LIMIT, "非负整数"),
+            mqtt_package_max_size: p.string(MQTT_PACKAGE_MAX_SIZE),
+            is_local_mqtt: p.bool(IS_LOCAL_MQTT),
+            local_mqtt_port: p.port(LOCAL_MQTT_PORT),
+            is_use_ssl: p.bool(IS_USE_SSL),
+            ssl_cert_file_path: p.string(SSL_CERT_FILE_PATH),
+            ssl_key_file_path: p.string(SSL_KEY_FILE_PATH),
+            socket_buf_size_north: p.string(SOCKET_BUF_SIZE_NORTH),
+            socket_buf_size_south: p.string(SOCKET_BUF_SIZE_SOUTH),
+            result_upload_interval: p.positive(RESULT_UPLOAD_INTERVAL),
+            aoe_catch_up: p.bool(AOE_CATCH_UP),
+            aoe_catch_up_max_days: p.parse(AOE_CATCH_UP_MAX_DAYS, "整数"),
+            point_file_dir: p.non_empty(POINT_FILE_DIR),
+            transport_file_dir: p.non_empty(TRANSPORT_DIR),
+            aoe_file_dir: p.string(AOE_DIR),
+            dff_file_dir: p.string(DFF_DIR),
+            json_file_dir: p.non_empty(JSON_DIR),
+            result_file_dir: p.non_empty(RESULT_DIR),
+            web_file_dir: p.string(WEB_DIR),
+            db_file_dir: p.non_empty(DB_DIR),
+            log_dir: p.non_empty(LOG_DIR),
+            meter_file_dir: p.string(METER_DIR),
+            meter_sum_no: p.string(METER_SUM_NO),
+            is_local_frontend: p.bool(IS_LOCAL_FRONTEND),
+            is_db: p.bool(IS_DB),
+            is_his_db: p.bool(IS_HIS_DB),
+            is_fake_delete: p.bool(IS_FAKE_DELETE),
+            db_dir_size_limit: p.string(DB_DIR_SIZE_LIMIT),
+            database_url: p.string(DATABASE_URL),
+            log_level: p.log_level(LOG_LEVEL),
+            log_save_time: p.string(LOG_SAVE_TIME),
+            log_save_size: p.string(LOG_SAVE_SIZE),
+            log_his_file_num: p.parse(LOG_HIS_FILE_NUM, "非负整数"),
+        };
+        // 配置项之间的依赖
+        if config.is_use_mems {
+            for key in [MEMS_SERVER, AOE_DIR, DFF_DIR] {
+                p.require(key, "启用MEMS时不能为空");
+            }
+        }
+        // 与数据中心MQTT的连接参数一致：CA证书必填，客户端证书和私钥用于双向认证，需同时配置
+        if config.is_use_ssl {
+            p.require(MQTT_SSL_CA_FILE_PATH, "启用SSL时不能为空");
+            if config.ssl_cert_file_path.trim().is_empty() != config.ssl_key_file_path.trim().is_empty() {
+                for key in [SSL_CERT_FILE_PATH, SSL_KEY_FILE_PATH] {
+                    p.require(key, "客户端证书和私钥需同时配置");
+                }
+            }
+        }
+        (config, p.errors)
+    }
+
+    /// 转为配置项名称到值的映射，sensitive为false时隐藏密码
+    pub fn to_values(&self, sensitive: bool) -> BTreeMap<String, String> {
+        let Ok(serde_json::Value::Object(map)) = serde_json::to_value(self) else {
+            return BTreeMap::new();
+        };
+        map.into_iter().map(|(k, v)| {
+            let v = match v {
+                serde_json::Value::String(s) => s,
+                serde_json::Value::Array(items) => items.iter()
+                    .map(|i| i.as_str().map(|s| s.to_string()).unwrap_or_else(|| i.to_string()))
+                    .collect::<Vec<String>>()
+                    .join(","),
+                v => v.to_string(),
+            };
+            let v = if sensitive { v } else { mask_value(&k, &v) };
+            (k, v)
+        }).collect()
+    }
+}
+
+// 逐项解析配置，出错时记录错误并使用默认值
+struct FieldParser<'a> {
+    values: &'a HashMap<String, (String, ConfigSource)>,
+    errors: Vec<ConfigErr>,
+}
+
+impl FieldParser<'_> {
+    fn raw(&self, key: &str) -> &str {
+        self.values.get(key).map(|(v, _)| v.as_str()).unwrap_or_default()
+    }
+
+    fn error(&mut self, key: &str, msg: String) {
+        let (value, source) = match self.values.get(key) {
+            Some((v, s)) => (Some(v.as_str()), s.clone()),
+            None => (None, ConfigSource::Default),
+        };
+        self.errors.push(ConfigErr::new(Some(key), value, &source, msg));
+    }
+
+    fn default_value(key: &str) -> String {
+        default_values().into_iter().find(|(k, _)| *k == key).map(|(_, v)| v).unwrap_or_default()
+    }
+
+    fn string(&mut self, key: &str) -> String {
+        self.raw(key).trim().to_string()
+    }
+
+    fn non_empty(&mut self, key: &str) -> String {
+        let s = self.string(key);
+        if s.is_empty() {
+            self.error(key, "不能为空".to_string());
+            return Self::default_value(key);
+        }
+        s
+    }
 
-#[derive(Debug, Clone, Default)]
+    fn require(&mut self, key: &str, msg: &str) {
+        if self.raw(key).trim().is_empty() {
+            self.error(key, msg.to_string());
+        }
+    }
+
+    fn parse<T: FromStr + Default>(&mut self, key: &str, expected: &str) -> T {
+        match self.raw(key).trim().parse::<T>() {
+            Ok(v) => v,
+            Err(_) => {
+                self.error(key, format!("应为{expected}"));
+                Self::default_value(key).parse().unwrap_or_default()
+            }
+        }
+    }
+
+    fn bool(&mut self, key: &str) -> bool {
+        match self.raw(key).trim().to_ascii_lowercase().as_str() {
+            "true" => true,
+            "false" => false,
+            _ => {
+                self.error(key, "应为true或false".to_string());
+                Self::default_value(key) == "true"
+            }
+        }
+    }
+
+    fn port(&mut self, key: &str) -> u16 {
+        match self.raw(key).trim().parse::<u16>() {
+            Ok(v) if v > 0 => v,
+            _ => {
+                self.error(key, "应为1到65535之间的端口号".to_string());
+                Self::default_value(key).parse().unwrap_or_default()
+            }
+        }
+    }
+
+    fn positive(&mut self, key: &str) -> u64 {
+        match self.raw(key).trim().parse::<u64>() {
+            Ok(v) if v > 0 => v,
+            _ => {
+                self.error(key, "应为正整数".to_string());
+                Self::default_value(key).parse().unwrap_or(1)
+            }
+        }
+    }
+
+    fn qos(&mut self, key: &str) -> u8 {
+        match self.raw(key).trim().parse::<u8>() {
+            Ok(v) if v <= 2 => v,
+            _ => {
+                self.error(key, "应为0、1或2".to_string());
+                Self::default_value(key).parse().unwrap_or_default()
+            }
+        }
+    }
+
+    // 允许为空，不为空时必须是http或https地址
+    fn url(&mut self, key: &str) -> String {
+        let s = self.string(key);
+        if !s.is_empty() && !s.starts_with("http://") && !s.starts_with("https://") {
+            self.error(key, "应以http://或https://开头".to_string());
+            return Self::default_value(key);
+        }
+        s
+    }
+
+    // host:port格式
+    fn address(&mut self, key: &str) -> String {
+        let s = self.string(key);
+        match s.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok_and(|p| p > 0) => s,
+            _ => {
+                self.error(key, "应为host:port格式".to_string());
+                Self::default_value(key)
+            }
+        }
+    }
+
+    fn log_level(&mut self, key: &str) -> String {
+        let s = self.string(key).to_ascii_lowercase();
+        if !LOG_LEVELS.contains(&s.as_str()) {
+            self.error(key, format!("应为{}之一", LOG_LEVELS.join("、")));
+            return Self::default_value(key);
+        }
+        s
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Env {
-    pub properties: HashMap<String, String>,
+    pub config: AdapterConfig,
 }
 
 impl Env {
 
-    // 避免多次初始化，请使用get_env()方法获得env
+    // 避免多次初始化，请使用get_env()方法获得env，配置有误时记录错误并使用默认值
     pub fn init(app_name: &str) -> Env {
-        let env = Env::init_with_args(app_name);
-        (*ENV.lock().unwrap()).insert(app_name.to_string(), env.clone());
+        let loaded = AdapterConfig::load();
+        for e in loaded.errors.iter().chain(loaded.warnings.iter()) {
+            warn!("!!Config error: {e}");
+        }
+        let env = Env { config: loaded.config };
+        Env::update(app_name, env.clone());
         env
     }
 
-    #[allow(clippy::new_without_default)]
-    pub fn new() -> Self {
-        Env::default()
+    /// 指定命令行中的配置项，需要在init之前调用
+    pub fn set_cli_args(args: Vec<(String, String)>) {
+        if CLI_ARGS.set(args).is_err() {
+            warn!("cli args has already been set");
+        }
     }
 
     pub fn get_app_name(&self) -> String {
-        let app_name = self.properties.get(APP_NAME).unwrap();
-        app_name.clone()
+        self.config.app_name.clone()
     }
 
     pub fn get_app_model(&self) -> String {
-        let app_model = self.properties.get(APP_MODEL).unwrap();
-        app_model.clone()
+        self.config.app_model.clone()
     }
 
     pub fn get_mqtt_server(&self) -> String {
         if self.get_is_local_mqtt() {
             return "127.0.0.1".to_string();
         }
-        let s = self.properties.get(MQTT_SERVER).unwrap().clone();
-        let mut r = s.split(':');
-        r.next().unwrap().to_string()
+        let s = &self.config.mqtt_server;
+        s.rsplit_once(':').map(|(host, _)| host).unwrap_or(s).to_string()
     }
 
     pub fn get_mqtt_server_port(&self) -> u16 {
         if self.get_is_local_mqtt() {
             return self.get_local_mqtt_port();
         }
-        let s = self.properties.get(MQTT_SERVER).unwrap().clone();
-        let r = s.split(':');
-        r.last().unwrap().parse::<u16>().unwrap()
+        self.config.mqtt_server.rsplit_once(':')
+            .and_then(|(_, port)| port.parse::<u16>().ok())
+            .unwrap_or(1883)
     }
 
     pub fn get_mqtt_user(&self) -> String {
-        let s = self.properties.get(MQTT_AUTH).unwrap().clone();
-        let mut r = s.split(':');
-        if let Some(user_name) = r.next() {
-            user_name.to_string()
-        } else {
-            "".to_string()
-        }
+        self.get_mqtt_credential().map(|(user, _)| user).unwrap_or_default()
     }
 
     pub fn get_mqtt_password(&self) -> String {
-        let s = self.properties.get(MQTT_AUTH).unwrap().clone();
-        let r = s.split(':');
-        if let Some(password) = r.last() {
-            password.to_string()
-        } else {
-            "".to_string()
+        self.get_mqtt_credential().map(|(_, password)| password).unwrap_or_default()
+    }
+
+    /// 用户名和密码以冒号分隔，未配置时返回None
+    pub fn get_mqtt_credential(&self) -> Option<(String, String)> {
+        let s = self.config.mqtt_credential.trim();
+        if s.is_empty() {
+            return None;
         }
+        match s.split_once(':') {
+            Some((user, password)) => Some((user.to_string(), password.to_string())),
+            None => Some((s.to_string(), String::new())),
+        }
+    }
+
+    pub fn get_mqtt_qos(&self) -> u8 {
+        self.config.mqtt_qos
+    }
+
+    pub fn get_mqtt_control_qos(&self) -> u8 {
+        self.config.mqtt_control_qos
+    }
+
+    pub fn get_mqtt_control_topics(&self) -> Vec<String> {
+        self.config.mqtt_control_topics.clone()
+    }
+
+    pub fn get_mqtt_retain_topics(&self) -> Vec<String> {
+        self.config.mqtt_retain_topics.clone()
+    }
+
+    pub fn get_mqtt_ssl_ca_file_path(&self) -> String {
+        self.config.mqtt_ssl_ca_file_path.clone()
+    }
+
+    pub fn get_result_upload_interval(&self) -> u64 {
+        self.config.result_upload_interval
+    }
+
+    pub fn get_aoe_catch_up(&self) -> bool {
+        self.config.aoe_catch_up
+    }
+
+    pub fn get_aoe_catch_up_max_days(&self) -> i64 {
+        self.config.aoe_catch_up_max_days
     }
 
     pub fn get_http_server_port(&self) -> u16 {
-        self.properties
-            .get(HTTP_SERVER_PORT)
-            .unwrap()
-            .parse::<u16>()
-            .unwrap()
+        self.config.http_server_port
     }
 
     pub fn get_exe_root_dir(&self) -> String {
-        self.properties.get(EXE_ROOT_DIR).unwrap().clone()
+        self.config.exe_root_dir.clone()
     }
 
     pub fn get_eig_home(&self) -> String {
-        let s = self.properties.get(EIG_HOME).unwrap();
-        if s.is_empty() {
+        if self.config.eig_home.is_empty() {
             self.get_exe_root_dir()
         } else {
-            s.clone()
+            self.config.eig_home.clone()
         }
     }
 
@@ -191,306 +658,180 @@ impl Env {
     }
 
     pub fn get_conf_path(&self) -> String {
-        let path = self.properties.get(CONF_PATH).unwrap();
-        self.transform_path_to_absolute(path.as_str())
+        self.transform_path_to_absolute(&self.config.config_file_path)
     }
 
     pub fn get_point_dir(&self) -> String {
-        if let Some(s) = self.get_property(POINT_FILE_DIR) {
-            return s.to_string();
-        }
-        String::new()
+        self.config.point_file_dir.clone()
     }
 
     pub fn get_transport_dir(&self) -> String {
-        if let Some(s) = self.get_property(TRANSPORT_DIR) {
-            return s.to_string();
-        }
-        String::new()
+        self.config.transport_file_dir.clone()
     }
 
     pub fn get_json_dir(&self) -> String {
-        let path = self.properties.get(JSON_DIR).unwrap().to_owned();
-        self.transform_path_to_absolute(path.as_str())
+        self.transform_path_to_absolute(&self.config.json_file_dir)
+    }
+
+    // 接口和云端下发的配置暂存在jsonFileDir下的单独目录，应用成功后删除
+    pub fn get_upload_dir(&self) -> String {
+        format!("{}/.upload", self.get_json_dir())
     }
 
     pub fn get_result_dir(&self) -> String {
-        let path = self.properties.get(RESULT_DIR).unwrap().to_owned();
-        self.transform_path_to_absolute(path.as_str())
+        self.transform_path_to_absolute(&self.config.result_file_dir)
     }
 
     pub fn get_aoe_dir(&self) -> String {
-        if let Some(s) = self.get_property(AOE_DIR) {
-            return s.to_string();
-        }
-        String::new()
+        self.config.aoe_file_dir.clone()
     }
 
     pub fn get_web_dir(&self) -> String {
-        let path = self.properties.get(WEB_DIR).unwrap().to_owned();
-        self.transform_path_to_absolute(path.as_str())
+        self.transform_path_to_absolute(&self.config.web_file_dir)
     }
 
     pub fn get_db_dir(&self) -> String {
-        let path = self.properties.get(DB_DIR).unwrap().to_owned();
-        self.transform_path_to_absolute(path.as_str())
+        self.transform_path_to_absolute(&self.config.db_file_dir)
     }
 
     pub fn get_log_dir(&self) -> String {
-        let path = self.properties.get(LOG_DIR).unwrap().to_owned();
-        self.transform_path_to_absolute(path.as_str())
+        self.transform_path_to_absolute(&self.config.log_dir)
     }
 
     pub fn get_mqtt_mv_limit(&self) -> usize {
-        let s = self.properties.get(MQTT_MV_LIMIT).unwrap();
-        s.trim().parse().unwrap()
+        self.config.mqtt_mv_limit
     }
 
     pub fn get_is_local_frontend(&self) -> bool {
-        let r = self.properties.get(IS_LOCAL_FRONTEND);
-        match r {
-            Some(s) => s.trim().to_uppercase() == "TRUE",
-            None => true,
-        }
+        self.config.is_local_frontend
     }
 
     pub fn get_is_db(&self) -> bool {
-        let r = self.properties.get(IS_DB);
-        match r {
-            Some(s) => s.trim().to_uppercase() == "TRUE",
-            None => true,
-        }
+        self.config.is_db
     }
 
     pub fn get_is_his_db(&self) -> bool {
-        let r = self.properties.get(IS_HIS_DB);
-        match r {
-            Some(s) => s.trim().to_uppercase() == "TRUE",
-            None => true,
-        }
+        self.config.is_his_db
     }
 
     pub fn get_is_fake_delete(&self) -> bool {
-        let r = self.properties.get(IS_FAKE_DELETE);
-        match r {
-            Some(s) => s.trim().to_uppercase() == "TRUE",
-            None => true,
-        }
+        self.config.is_fake_delete
     }
 
     pub fn get_is_local_mqtt(&self) -> bool {
-        let r = self.properties.get(IS_LOCAL_MQTT);
-        match r {
-            Some(s) => s.trim().to_uppercase() == "TRUE",
-            None => false,
-        }
+        self.config.is_local_mqtt
     }
 
     pub fn get_local_mqtt_port(&self) -> u16 {
-        let r = self.properties.get(LOCAL_MQTT_PORT);
-        match r {
-            Some(s) => s.trim().parse::<u16>().unwrap_or(1883),
-            None => 1883,
-        }
+        self.config.local_mqtt_port
     }
 
     pub fn get_plcc_mqtt_port(&self) -> u16 {
-        let r = self.properties.get(PLCC_MQTT_PORT);
-        match r {
-            Some(s) => s.trim().parse::<u16>().unwrap_or(58082),
-            None => 58082,
-        }
+        self.config.plcc_mqtt_port
     }
 
     pub fn get_mems_mqtt_port(&self) -> u16 {
-        let r = self.properties.get(MEMS_MQTT_PORT);
-        match r {
-            Some(s) => s.trim().parse::<u16>().unwrap_or(58083),
-            None => 58083,
-        }
+        self.config.mems_mqtt_port
     }
 
     pub fn get_is_use_ssl(&self) -> bool {
-        let r = self.properties.get(IS_USE_SSL);
-        match r {
-            Some(s) => s.trim().to_uppercase() == "TRUE",
-            None => false,
-        }
+        self.config.is_use_ssl
     }
 
     pub fn get_ssl_cert_file_path(&self) -> String {
-        if let Some(s) = self.get_property(SSL_CERT_FILE_PATH) {
-            return s.to_string();
-        }
-        String::new()
+        self.config.ssl_cert_file_path.clone()
     }
 
     pub fn get_ssl_key_file_path(&self) -> String {
-        if let Some(s) = self.get_property(SSL_KEY_FILE_PATH) {
-            return s.to_string();
-        }
-        String::new()
+        self.config.ssl_key_file_path.clone()
     }
 
     pub fn get_mqtt_timeout(&self) -> u64 {
-        let s = self.properties.get(MQTT_TIMEOUT).unwrap();
-        s.trim().parse().unwrap()
-    }
-
-    pub fn get_properties(&self) -> HashMap<String, String> {
-        self.properties.clone()
+        self.config.mqtt_timeout
     }
 
     pub fn get_beeid(&self) -> String {
-        let bee_id = self.get_property(BEE_ID).unwrap_or_default();
-
-        if bee_id.is_empty() {
+        if self.config.bee_id.is_empty() {
             "1234567887654321".to_string()
         } else {
-            bee_id.to_string()
+            self.config.bee_id.clone()
         }
     }
 
     pub fn get_plcc_beeid(&self) -> String {
-        let plcc_bee_id = self.get_property(PLCC_BEE_ID).unwrap_or_default();
-        if plcc_bee_id.is_empty() {
+        if self.config.plcc_bee_id.is_empty() {
             "123456789123123".to_string()
         } else {
-            plcc_bee_id.to_string()
+            self.config.plcc_bee_id.clone()
         }
     }
 
     pub fn get_mems_beeid(&self) -> String {
-        let mems_bee_id = self.get_property(MEMS_BEE_ID).unwrap_or_default();
-        if mems_bee_id.is_empty() {
+        if self.config.mems_bee_id.is_empty() {
             "123456789123123".to_string()
         } else {
-            mems_bee_id.to_string()
+            self.config.mems_bee_id.clone()
         }
     }
 
     pub fn get_database_url(&self) -> String {
-        self.properties.get(DATABASE_URL).unwrap_or(&String::new()).clone()
+        self.config.database_url.clone()
     }
 
     pub fn get_plcc_server(&self) -> String {
-        self.properties.get(PLCC_SERVER).unwrap_or(&String::new()).clone()
+        self.config.plcc_server.clone()
     }
 
     pub fn get_plcc_user(&self) -> String {
-        self.properties.get(PLCC_USER).unwrap_or(&String::new()).clone()
+        self.config.plcc_user.clone()
     }
 
     pub fn get_plcc_pwd(&self) -> String {
-        self.properties.get(PLCC_PWD).unwrap_or(&String::new()).clone()
+        self.config.plcc_pwd.clone()
     }
 
     pub fn get_is_use_mems(&self) -> bool {
-        let r = self.properties.get(IS_USE_MEMS);
-        match r {
-            Some(s) => s.trim().to_uppercase() == "TRUE",
-            None => false,
-        }
+        self.config.is_use_mems
     }
 
     pub fn get_mems_server(&self) -> String {
-        self.properties.get(MEMS_SERVER).unwrap_or(&String::new()).clone()
+        self.config.mems_server.clone()
     }
 
     pub fn get_mems_user(&self) -> String {
-        self.properties.get(MEMS_USER).unwrap_or(&String::new()).clone()
+        self.config.mems_user.clone()
     }
 
     pub fn get_mems_pwd(&self) -> String {
-        self.properties.get(MEMS_PWD).unwrap_or(&String::new()).clone()
+        self.config.mems_pwd.clone()
+    }
+
+    pub fn get_http_connect_timeout(&self) -> u64 {
+        self.config.http_connect_timeout
+    }
+
+    pub fn get_http_request_timeout(&self) -> u64 {
+        self.config.http_request_timeout
+    }
+
+    pub fn get_http_retry_num(&self) -> usize {
+        self.config.http_retry_num
+    }
+
+    pub fn get_http_token_ttl(&self) -> u64 {
+        self.config.http_token_ttl
     }
 
     pub fn get_meter_sum_no(&self) -> String {
-        self.properties.get(METER_SUM_NO).unwrap_or(&String::new()).clone()
+        self.config.meter_sum_no.clone()
     }
 
     pub fn get_meter_dir(&self) -> String {
-        let path = self.properties.get(METER_DIR).unwrap().to_owned();
-        self.transform_path_to_absolute(path.as_str())
+        self.transform_path_to_absolute(&self.config.meter_file_dir)
     }
 
     pub fn get_dff_dir(&self) -> String {
-        if let Some(s) = self.get_property(DFF_DIR) {
-            return s.to_string();
-        }
-        String::new()
-    }
-
-    pub fn get_default_properties() -> HashMap<String, String> {
-        let mut properties: HashMap<String, String> = HashMap::with_capacity(16);
-
-        let default_props = HashMap::from([
-            (HTTP_SERVER_PORT, "80"),
-            (POINT_FILE_DIR, "points.json"),
-            (TRANSPORT_DIR, "transports.json"),
-            (AOE_DIR, "aoes.json"),
-            (JSON_DIR, "file"),
-            (MQTT_SERVER, "localhost:1883"),
-            (IS_LOCAL_MQTT, "false"),
-            (LOCAL_MQTT_PORT, "1883"),
-            (MQTT_TIMEOUT, "30"),
-
-            (MQTT_MV_LIMIT, "1000"),
-            (MQTT_AUTH, ""),
-            (MQTT_CLIENT_BUF_SIZE, "100"),
-            (MQTT_PACKAGE_MAX_SIZE, "2 MiB"),
-            (SOCKET_BUF_SIZE_NORTH, "128 MiB"),//128MB
-            (SOCKET_BUF_SIZE_SOUTH, "32 MiB"),//32MB
-            // default dirs
-            // (EXE_ROOT_DIR, "./"),
-            (EIG_HOME, ""),
-            (WEB_DIR, "www"),
-            (DB_DIR, "db"),
-            (LOG_DIR, "log"),
-
-            // default eig config
-            (APP_NAME, "ext.syy.plcc"),
-            (APP_MODEL, "DC_PLCC"),
-            (IS_LOCAL_FRONTEND, "true"),
-            (IS_DB, "true"),
-            (IS_HIS_DB, "true"),
-            (IS_FAKE_DELETE, "false"),
-            (DB_DIR_SIZE_LIMIT, "1 GB"),
-            //default LogConfig
-            (LOG_LEVEL, "info"),
-            (LOG_SAVE_TIME, ""),//日志初始化时会初始化为1 day
-            (LOG_SAVE_SIZE, ""),
-            (LOG_HIS_FILE_NUM, "7"),
-        ]);
-
-        default_props.iter().for_each(|(k, v)| {
-            properties.insert(k.to_string(), v.to_string());
-        });
-
-        let exe_root_dir = Env::get_exe_root();
-
-        properties.insert(
-            String::from(EXE_ROOT_DIR),
-            exe_root_dir,
-        );
-
-        properties
-    }
-
-    fn init_with_path(config_path: &str) -> Env {
-        //生成默认env
-        let env = Env {
-            properties: Env::get_default_properties(),
-        };
-        let mut env_new = env.clone();
-        if Path::new(config_path).exists() {
-            info!("Load config file from {config_path}", );
-            read_file(config_path, &mut env_new.properties);
-        } else {
-            info!("no config file found at {config_path}, use default config", );
-        }
-        env_new.properties.insert(String::from(CONF_PATH), config_path.to_string());
-        env_new
+        self.config.dff_file_dir.clone()
     }
 
     fn get_exe_root() -> String {
@@ -501,26 +842,40 @@ impl Env {
         exe_path.parent().unwrap().display().to_string()
     }
 
-    fn init_with_args(_app_name: &str) -> Env {
-        // 从参数或环境变量中加载config路径
-        let config_path = Self::get_exe_root()+"/adapter";
-        Env::init_with_path(&config_path)
-    }
-
     pub fn update(app_name: &str, env: Env) {
         (*ENV.lock().unwrap()).insert(app_name.to_string(), env);
     }
 
-    pub fn get_property(&self, key: &str) -> Option<&str> {
-        if let Some(v) = self.properties.get(key) {
-            Some(v.as_str())
-        } else {
-            None
+    /// 重新读取并校验配置，校验通过时替换当前配置，返回替换前的配置和变化的项，校验失败时保持原配置
+    pub fn reload(app_name: &str) -> Result<(Env, Vec<ConfigChange>), Vec<ConfigErr>> {
+        let loaded = AdapterConfig::load();
+        if !loaded.errors.is_empty() {
+            return Err(loaded.errors);
         }
-    }
-
-    pub fn set_property(&mut self, key: &str, val: &str) {
-        self.properties.insert(key.to_string(), val.to_string());
+        for w in &loaded.warnings {
+            warn!("config warning: {w}");
+        }
+        let new_env = Env { config: loaded.config };
+        let old_env = {
+            let mut env_hash = ENV.lock().unwrap();
+            let old_env = env_hash.get(app_name).cloned();
+            env_hash.insert(app_name.to_string(), new_env.clone());
+            old_env
+        };
+        let Some(old_env) = old_env else {
+            return Ok((new_env, vec![]));
+        };
+        let (old_values, new_values) = (old_env.config.to_values(true), new_env.config.to_values(true));
+        let (old_masked, new_masked) = (old_env.config.to_values(false), new_env.config.to_values(false));
+        let changes = new_values.iter()
+            .filter(|(k, v)| old_values.get(*k) != Some(*v))
+            .map(|(k, _)| ConfigChange {
+                key: k.clone(),
+                old: old_masked.get(k).cloned().unwrap_or_default(),
+                new: new_masked.get(k).cloned().unwrap_or_default(),
+            })
+            .collect();
+        Ok((old_env, changes))
     }
 
     pub fn get_env(app_name: &str) -> Env {
@@ -531,77 +886,103 @@ impl Env {
         if let Some(env) = env_hash.get(app_name) {
             return env.clone();
         }
+        drop(env_hash);
 
         warn!("Env is not initialized, init now!");
         Env::init(app_name)
     }
 
     pub fn get_log_config(&self) -> LogConfig {
-        let log_dir = self.get_log_dir();
-        let log_level = self.get_property(LOG_LEVEL).unwrap().to_string();
-        let log_save_time = self.get_property(LOG_SAVE_TIME).unwrap().to_string();
-        let log_save_size = self.get_property(LOG_SAVE_SIZE).unwrap().to_string();
-        let log_his_file_num = self.get_property(LOG_HIS_FILE_NUM).unwrap().to_string();
         LogConfig {
-            log_dir,
-            log_level,
-            log_save_time,
-            log_save_size,
-            log_his_file_num,
+            log_dir: self.get_log_dir(),
+            log_level: self.config.log_level.clone(),
+            log_save_time: self.config.log_save_time.clone(),
+            log_save_size: self.config.log_save_size.clone(),
+            log_his_file_num: self.config.log_his_file_num.to_string(),
             log_is_quiet: false,
         }
     }
 }
 
-fn read_buffed<R>(buffered: BufReader<R>, properties: &mut HashMap<String, String>)
-where
-    R: std::io::Read,
-{
-    // 存放`[key]`
-    // let mut key: String = "".to_string();
-    // 缓存 : 去掉空格
-    // let mut new_line = "".to_string();
-
-    for line_result in buffered.lines() {
-        match line_result {
-            Err(e) => {
-                error!("!!Read config file line failed: {e:?}");
-                continue;
-            }
-            Ok(line) => {
-                // new_line.clear();
-                // new_line.push_str(line.trim());
-                // 定义注释为`#`, 遇到注释跳过
-                if line.contains('#') {
-                    continue;
-                    // } else if line.contains('[') && line.contains(']') {
-                    //     // 解析`[key]`为`key::`
-                    //     key.clear();
-                    //     new_line.pop();
-                    //     new_line.remove(0);
-                    //     key.push_str(new_line.as_str());
-                    //     key.push_str("::");
-                } else if line.contains('=') {
-                    // 必须包含表达式, 才将值写入
-                    let kvs: Vec<&str> = line.as_str().split('=').collect::<Vec<&str>>();
-                    if kvs.len() == 2 {
-                        if let Some(k) = get_parameters_name(kvs[0]) {
-                            // 如果不满足则定义为异常数据，该数据暂不处理
-
-                            // 缓存
-                            // let mut new_key: String = key.clone();
-                            // new_key.push_str(k.as_str());
-                            properties.insert(k, kvs[1].trim().to_string());
-                            continue;
-                        }
-                    }
-                } else if line.trim().is_empty() {
-                    continue;
-                }
-                warn!("!!Wrong config file line: {:?}", line);
-            }
-        }
-    }
+fn default_values() -> Vec<(&'static str, String)> {
+    let defaults = [
+        (HTTP_SERVER_PORT, "80"),
+        (POINT_FILE_DIR, "points.json"),
+        (TRANSPORT_DIR, "transports.json"),
+        (AOE_DIR, "aoes.json"),
+        (DFF_DIR, ""),
+        (JSON_DIR, "file"),
+        (RESULT_DIR, ""),
+        (METER_DIR, ""),
+        (METER_SUM_NO, ""),
+        (MQTT_SERVER, "localhost:1883"),
+        (IS_LOCAL_MQTT, "false"),
+        (LOCAL_MQTT_PORT, "1883"),
+        (MQTT_TIMEOUT, "30"),
+        (HTTP_CONNECT_TIMEOUT, "5"),
+        (HTTP_REQUEST_TIMEOUT, "30"),
+        (HTTP_RETRY_NUM, "3"),
+        (HTTP_TOKEN_TTL, "1800"),
+        (MQTT_QOS, "0"),
+        (MQTT_CONTROL_QOS, "1"),
+        (MQTT_CONTROL_TOPICS, "F-RemoteCtrl,F-SetPara"),
+        (MQTT_RETAIN_TOPICS, ""),
+        (MQTT_SSL_CA_FILE_PATH, ""),
+        (RESULT_UPLOAD_INTERVAL, "5"),
+        (AOE_CATCH_UP, "true"),
+        (AOE_CATCH_UP_MAX_DAYS, "7"),
+
+        (PLCC_SERVER, ""),
+        (PLCC_USER, ""),
+        (PLCC_PWD, ""),
+        (PLCC_MQTT_PORT, "58082"),
+        (IS_USE_MEMS, "false"),
+        (MEMS_SERVER, ""),
+        (MEMS_USER, ""),
+        (MEMS_PWD, ""),
+        (MEMS_MQTT_PORT, "58083"),
+        (BEE_ID, ""),
+        (PLCC_BEE_ID, ""),
+        (MEMS_BEE_ID, ""),
+        (IS_USE_SSL, "false"),
+        (SSL_CERT_FILE_PATH, ""),
+        (SSL_KEY_FILE_PATH, ""),
+
+        (MQTT_MV_LIMIT, "1000"),
+        (MQTT_AUTH, ""),
+        (MQTT_CLIENT_BUF_SIZE, "100"),
+        (MQTT_PACKAGE_MAX_SIZE, "2 MiB"),
+        (SOCKET_BUF_SIZE_NORTH, "128 MiB"),//128MB
+        (SOCKET_BUF_SIZE_SOUTH, "32 MiB"),//32MB
+        // default dirs
+        (EIG_HOME, ""),
+        (WEB_DIR, "www"),
+        (DB_DIR, "db"),
+        (LOG_DIR, "log"),
+
+        // default eig config
+        (APP_NAME, "ext.syy.plcc"),
+        (APP_MODEL, "DC_PLCC"),
+        (IS_LOCAL_FRONTEND, "true"),
+        (IS_DB, "true"),
+        (IS_HIS_DB, "true"),
+        (IS_FAKE_DELETE, "false"),
+        (DB_DIR_SIZE_LIMIT, "1 GB"),
+        (DATABASE_URL, ""),
+        //default LogConfig
+        (LOG_LEVEL, "info"),
+        (LOG_SAVE_TIME, ""),//日志初始化时会初始化为1 day
+        (LOG_SAVE_SIZE, ""),
+        (LOG_HIS_FILE_NUM, "7"),
+    ];
+    let mut values = defaults.iter().map(|(k, v)| (*k, v.to_string())).collect::<Vec<_>>();
+    values.push((EXE_ROOT_DIR, Env::get_exe_root()));
+    values
+}
+
+/// 将命令行参数或环境变量中的名称转为配置项名称，如http-server-port、HTTP_SERVER_PORT
+pub fn config_key(name: &str) -> Option<String> {
+    get_parameters_name(name)
 }
 
 fn get_parameters_name(name_ori: &str) -> Option<String> {
@@ -615,18 +996,162 @@ fn get_parameters_name(name_ori: &str) -> Option<String> {
     Some(CONFIG_ARGS[index].to_string())
 }
 
-// 读取文件,path表示文件路径
-pub fn read_file(path: &str, properties: &mut HashMap<String, String>) {
-    // 读取文件，失败直接返回Err
-    if let Ok(content) = std::fs::read(path) {
-        read_env_from_bytes(content.as_slice(), properties);
+fn mask_value(key: &str, value: &str) -> String {
+    if SENSITIVE_ARGS.contains(&key) && !value.is_empty() {
+        "******".to_string()
     } else {
-        error!("!!Read config file failed: {}", path);
+        value.to_string()
+    }
+}
+
+// 以逗号分隔的配置项
+fn split_list(s: &str) -> Vec<String> {
+    s.split(',').map(|v| v.trim()).filter(|v| !v.is_empty()).map(|v| v.to_string()).collect()
+}
+
+// ADAPTER_*环境变量，ADAPTER_CONFIG_FILE_PATH用于指定配置文件
+fn read_env_args(warnings: &mut Vec<ConfigErr>) -> Vec<(String, String, ConfigSource)> {
+    let mut args = env::vars()
+        .filter_map(|(name, value)| {
+            let key = name.strip_prefix(ENV_PREFIX)?;
+            let source = ConfigSource::Env(name.clone());
+            match get_parameters_name(key) {
+                Some(key) => Some((key, value, source)),
+                None => {
+                    warnings.push(ConfigErr::new(None, None, &source, "未知的配置项，已忽略".to_string()));
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+    args.sort_by(|a, b| a.0.cmp(&b.0));
+    args
+}
+
+// 读取文件,path表示文件路径，扩展名为toml时按TOML格式解析，否则按key = value格式解析
+fn read_file(
+    path: &str,
+    values: &mut HashMap<String, (String, ConfigSource)>,
+    errors: &mut Vec<ConfigErr>,
+    warnings: &mut Vec<ConfigErr>,
+) {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("!!Read config file failed: {}", path);
+            errors.push(ConfigErr::new(None, None, &ConfigSource::File { path: path.to_string(), line: 0 }, format!("读取失败：{e}")));
+            return;
+        }
+    };
+    let is_toml = Path::new(path).extension().is_some_and(|e| e.eq_ignore_ascii_case(TOML_EXTENSION));
+    let lines = if is_toml { parse_toml(&content) } else { parse_properties(&content) };
+    for (line, result) in lines {
+        let source = ConfigSource::File { path: path.to_string(), line };
+        match result {
+            Ok((names, value)) => match names.iter().find_map(|n| get_parameters_name(n)) {
+                Some(key) => {
+                    values.insert(key, (value, source));
+                }
+                None => warnings.push(ConfigErr::new(names.last().map(|s| s.as_str()), None, &source, "未知的配置项，已忽略".to_string())),
+            },
+            Err(msg) => errors.push(ConfigErr::new(None, None, &source, msg)),
+        }
+    }
+}
+
+// 解析结果为行号以及候选的配置项名称和值，或者错误信息
+type ParsedLine = (usize, Result<(Vec<String>, String), String>);
+
+// key = value格式，以#开头的行为注释，值中可以包含#和=
+fn parse_properties(content: &str) -> Vec<ParsedLine> {
+    let mut result = vec![];
+    for (i, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let parsed = match line.split_once('=') {
+            Some((k, v)) if !k.trim().is_empty() => Ok((vec![k.trim().to_string()], v.trim().to_string())),
+            _ => Err("格式错误，应为key = value".to_string()),
+        };
+        result.push((i + 1, parsed));
+    }
+    result
+}
+
+// 数组转为逗号分隔的字符串，表中的配置项名称为表名加键名，如[plcc]下的server为plccServer
+// 语法错误时整个文件不生效，只返回出错的行
+fn parse_toml(content: &str) -> Vec<ParsedLine> {
+    let table = match DeTable::parse(content) {
+        Ok(table) => table,
+        Err(e) => {
+            let line = e.span().map(|s| line_of(content, s.start)).unwrap_or_default();
+            return vec![(line, Err(format!("TOML格式错误：{}", e.message())))];
+        }
+    };
+    let mut result = vec![];
+    flatten_toml_table(content, table.get_ref(), None, &mut result);
+    result.sort_by_key(|(line, _)| *line);
+    result
+}
+
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset.min(content.len())].matches('\n').count() + 1
+}
+
+fn flatten_toml_table(content: &str, table: &DeTable, prefix: Option<&str>, result: &mut Vec<ParsedLine>) {
+    for (key, value) in table {
+        let name = match prefix {
+            Some(prefix) => format!("{prefix}.{}", key.get_ref()),
+            None => key.get_ref().to_string(),
+        };
+        if let DeValue::Table(t) = value.get_ref() {
+            flatten_toml_table(content, t, Some(&name), result);
+            continue;
+        }
+        let names = if prefix.is_some() { vec![name, key.get_ref().to_string()] } else { vec![name] };
+        result.push((line_of(content, key.span().start), toml_value_to_string(value.get_ref()).map(|v| (names, v))));
+    }
+}
+
+fn toml_value_to_string(value: &DeValue) -> Result<String, String> {
+    match value {
+        DeValue::String(s) => Ok(s.to_string()),
+        DeValue::Integer(i) => i64::from_str_radix(i.as_str(), i.radix())
+            .map(|i| i.to_string())
+            .map_err(|_| format!("无效的整数{i}")),
+        DeValue::Float(f) => Ok(f.as_str().to_string()),
+        DeValue::Boolean(b) => Ok(b.to_string()),
+        DeValue::Datetime(d) => Ok(d.to_string()),
+        DeValue::Array(items) => items.iter()
+            .map(|item| toml_value_to_string(item.get_ref()))
+            .collect::<Result<Vec<String>, String>>()
+            .map(|items| items.join(",")),
+        DeValue::Table(_) => Err("数组中不能包含表".to_string()),
     }
 }
 
-pub fn read_env_from_bytes(content: &[u8], properties: &mut HashMap<String, String>) {
-    // 读取文件，失败直接返回Err
-    let buffered: BufReader<&[u8]> = BufReader::new(content);
-    read_buffed(buffered, properties);
+#[test]
+fn test_parse_properties() {
+    let content = "# comment\nplccPwd = a#b=c\n\nhttpServerPort=8080\nbad line\n";
+    let lines = parse_properties(content);
+    assert_eq!(lines[0], (2, Ok((vec!["plccPwd".to_string()], "a#b=c".to_string()))));
+    assert_eq!(lines[1], (4, Ok((vec!["httpServerPort".to_string()], "8080".to_string()))));
+    assert!(lines[2].1.is_err());
+}
+
+#[test]
+fn test_parse_toml() {
+    let content = "httpServerPort = 8_080 # port\n[plcc]\nserver = \"http://127.0.0.1\"\npwd = 'a#b'\n[mqtt]\ncontrolTopics = [\n  \"F-RemoteCtrl\",\n  \"F-SetPara\",\n]\n";
+    let lines = parse_toml(content);
+    assert_eq!(lines[0], (1, Ok((vec!["httpServerPort".to_string()], "8080".to_string()))));
+    assert_eq!(lines[1], (3, Ok((vec!["plcc.server".to_string(), "server".to_string()], "http://127.0.0.1".to_string()))));
+    assert_eq!(lines[2].1, Ok((vec!["plcc.pwd".to_string(), "pwd".to_string()], "a#b".to_string())));
+    assert_eq!(lines[3].1.as_ref().map(|(_, v)| v.as_str()), Ok("F-RemoteCtrl,F-SetPara"));
+    assert_eq!(get_parameters_name("plcc.server").as_deref(), Some(PLCC_SERVER));
+    // 语法错误时返回出错的行
+    let lines = parse_toml("httpServerPort = 8080\n[mqtt]\nqos = one\n");
+    assert_eq!(lines.len(), 1);
+    assert_eq!(lines[0].0, 3);
+    assert!(lines[0].1.is_err());
 }
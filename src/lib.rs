@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
 use serde_repr::{Serialize_repr, Deserialize_repr};
 
 pub mod runner;
+pub mod cli;
 pub mod parser;
 pub mod db;
 pub mod model;
@@ -76,10 +78,77 @@ pub enum ErrCode {
     DffIdNotFound = 643,
     DffActionErr = 644,
     MemsActionErr = 645,
+    DuplicateId = 646,
+    RevisionNotFound = 647,
+    JobRunning = 648,
+    JobNotFound = 649,
+    ConfigInvalid = 650,
     Other = 699,
 }
 
+impl ErrCode {
+    pub fn name(&self) -> String {
+        format!("{self:?}")
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct AdapterErr {
     pub code: ErrCode,
     pub msg: String,
 }
+
+/// HTTP接口统一的返回结构
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiResponse {
+    pub code: ErrCode,
+    /// 错误码名称
+    pub name: String,
+    pub msg: String,
+    /// 出错的执行阶段
+    pub stage: Option<String>,
+    pub details: Option<serde_json::Value>,
+}
+
+impl ApiResponse {
+    pub fn success() -> Self {
+        ApiResponse {
+            code: ErrCode::Success,
+            name: ErrCode::Success.name(),
+            msg: "".to_string(),
+            stage: None,
+            details: None,
+        }
+    }
+
+    pub fn from_err(err: AdapterErr) -> Self {
+        ApiResponse {
+            name: err.code.name(),
+            code: err.code,
+            msg: err.msg,
+            stage: None,
+            details: None,
+        }
+    }
+
+    /// stage需要序列化为字符串，如snake_case的枚举
+    pub fn with_stage<S: Serialize>(mut self, stage: S) -> Self {
+        self.stage = serde_json::to_value(stage).ok()
+            .and_then(|v| v.as_str().map(|s| s.to_string()));
+        self
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl From<Result<(), AdapterErr>> for ApiResponse {
+    fn from(r: Result<(), AdapterErr>) -> Self {
+        match r {
+            Ok(()) => ApiResponse::success(),
+            Err(e) => ApiResponse::from_err(e),
+        }
+    }
+}
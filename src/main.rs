@@ -1,6 +1,8 @@
-use adapter_plcc_nwsyy::runner::run_adapter;
+use std::process::ExitCode;
+
+use adapter_plcc_nwsyy::cli::run_cli;
 
 #[tokio::main]
-async fn main() -> std::io::Result<()> {
-    run_adapter().await
+async fn main() -> ExitCode {
+    run_cli(std::env::args().skip(1).collect()).await
 }
\ No newline at end of file
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
 
-use crate::{ErrCode, model::{north::{MyAoe, MyDffModel, MyDffResult, MyMeasurement, MyPbAoeResult, MyTransport}, south::DffResult}};
+use crate::{ErrCode, model::{north::{MyAoe, MyAoes, MyDffModel, MyDffModels, MyDffResult, MyMeasurement, MyPbAoeResult, MyPoints, MyTransport, MyTransports}, south::DffResult}};
 
 pub trait HasToken {
     fn token(&self) -> String;
@@ -453,6 +453,13 @@ pub struct CloudEventRequestBody {
     #[serde_as(as = "Option<Vec<DisplayFromStr>>")]
     pub aoes_id: Option<Vec<u64>>,
     pub aoes_status: Option<Vec<CloudEventAoeStatus>>,
+    /// 下发的配置，可以是全量或增量
+    #[serde(default)]
+    pub points: Option<MyPoints>,
+    #[serde(default)]
+    pub transports: Option<MyTransports>,
+    #[serde(default)]
+    pub aoes: Option<MyAoes>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
@@ -474,6 +481,12 @@ pub struct CloudEventResponseBody {
     pub aoes_status: Option<Vec<CloudEventAoeStatus>>,
     pub code: ErrCode,
     pub msg: String,
+    /// 下发配置时出错的执行阶段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+    /// 下发配置的回滚或同步结果
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
 }
 
 #[serde_as]
@@ -488,7 +501,9 @@ pub struct CloudEventAoeStatus {
 pub enum CloudEventCmd {
     GetTgPLCCConfig,
     TgAOEControl,
-    GetTgAOEStatus
+    GetTgAOEStatus,
+    SetTgPLCCConfig,
+    SetTgAOEConfig,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -588,6 +603,9 @@ pub struct MemsEventRequestBody {
     #[serde_as(as = "Option<Vec<DisplayFromStr>>")]
     pub dffs_id: Option<Vec<u64>>,
     pub dffs_status: Option<Vec<MemsEventDffStatus>>,
+    /// 下发的报表配置，可以是全量或增量
+    #[serde(default)]
+    pub dffs: Option<MyDffModels>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
@@ -605,8 +623,29 @@ pub struct MemsEventResponse {
 pub struct MemsEventResponseBody {
     pub dffs: Option<Vec<MyDffModel>>,
     pub dffs_status: Option<Vec<MemsEventDffStatus>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dffs_result: Option<Vec<MemsEventDffResult>>,
     pub code: ErrCode,
     pub msg: String,
+    /// 下发配置时出错的执行阶段
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stage: Option<String>,
+    /// 下发配置的同步结果
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+/// 报表最近一次的执行结果，结果表按行转换为JSON
+#[serde_as]
+#[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
+pub struct MemsEventDffResult {
+    #[serde_as(as = "DisplayFromStr")]
+    pub dff_id: u64,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub start_time: Option<u64>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub end_time: Option<u64>,
+    pub rows: Vec<serde_json::Map<String, serde_json::Value>>,
 }
 
 #[serde_as]
@@ -621,7 +660,11 @@ pub struct MemsEventDffStatus {
 pub enum MemsEventCmd {
     GetTgDFFConfig,
     TgDFFControl,
-    GetTgDFFStatus
+    GetTgDFFStatus,
+    SetTgDFFConfig,
+    // 立即执行手动触发的报表
+    TgDFFTrigger,
+    GetTgDFFResult,
 }
 
 #[test]
@@ -638,6 +681,9 @@ fn test_json_parse() {
                 aoe_id: 3,
                 aoe_status: 4
             }]),
+            points: None,
+            transports: None,
+            aoes: None,
         }),
     };
     let to_str = serde_json::to_string(&item).unwrap();
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+use crate::ErrCode;
+
+/// 后台执行的配置更新任务类型，上传的配置数据不随任务保存
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum JobKind {
+    UpdatePlcc,
+    UpdateMems,
+    UploadPlcc,
+    UploadMems,
+    // 重新应用的历史版本号
+    RevertRevision(u64),
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Success,
+    Failed,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Success | JobStatus::Failed)
+    }
+}
+
+/// 任务状态，stage为正在执行或失败时所在的阶段
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Job {
+    pub id: u64,
+    pub kind: JobKind,
+    pub status: JobStatus,
+    pub stage: Option<String>,
+    /// 结束后的错误码和错误信息，成功时code为Success
+    pub code: Option<ErrCode>,
+    pub msg: Option<String>,
+    /// 结束后返回的详细信息，如同步结果和回滚结果
+    pub details: Option<serde_json::Value>,
+    /// 创建、开始和结束时间，毫秒
+    pub created_at: i64,
+    pub started_at: Option<i64>,
+    pub finished_at: Option<i64>,
+}
@@ -1,11 +1,12 @@
 use core::f64;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::str::FromStr;
 use std::time::Duration;
 use std::vec;
 use std::io::Cursor;
 use serde_json::Value;
 use polars_core::frame::DataFrame;
+use polars_core::prelude::AnyValue;
 use polars_io::parquet::write::{ParquetWriter, ParquetCompression};
 use polars_io::parquet::read::ParquetReader;
 use polars_io::SerReader;
@@ -24,18 +25,59 @@ use crate::utils::parse::{load_prog, create_stmt_tree};
 pub mod north;
 pub mod south;
 pub mod datacenter;
+pub mod revision;
+pub mod job;
 
-pub fn points_to_south(points: MyPoints, old_point_mapping: &HashMap<String, u64>)
-    -> Result<(Vec<Measurement>, HashMap<String, u64>, HashMap<String, PointParam>, HashMap<String, bool>, Vec<AppApiParam>), AdapterErr>
- {
-    let points = points.points;
-    if points.is_none() {
-        return Err(AdapterErr {
+/// 策略南向id的起始值
+pub const AOE_ID_START: u64 = 65536;
+/// 报表南向id的起始值，与策略id区间不重叠
+pub const DFF_ID_START: u64 = 10_000_000;
+
+/// 已分配过的最大南向策略id和报表id，stored为数据库中记录的值，没有记录时根据已有映射推算
+pub fn resolve_last_ids(stored_aoe: Option<u64>, stored_dff: Option<u64>, aoe_mapping: &HashMap<u64, u64>, dff_mapping: &HashMap<u64, u64>) -> (u64, u64) {
+    let aoe_last_id = stored_aoe
+        .unwrap_or_else(|| {
+            // 旧版本的报表id与策略id在同一区间，需要一起避开
+            aoe_mapping.keys().chain(dff_mapping.keys()).copied()
+                .filter(|id| *id < DFF_ID_START)
+                .max()
+                .unwrap_or(0)
+        })
+        .max(AOE_ID_START - 1);
+    let dff_last_id = stored_dff
+        .unwrap_or_else(|| dff_mapping.keys().copied().max().unwrap_or(0))
+        .max(DFF_ID_START - 1);
+    (aoe_last_id, dff_last_id)
+}
+
+type DevMapping = HashMap<(String, String, String), (String, String, String)>;
+type SouthPoints = (Vec<Measurement>, HashMap<String, u64>, HashMap<String, PointParam>, HashMap<String, bool>, Vec<AppApiParam>);
+
+// 转换出错时，校验模式下记录错误后继续转换其余项，否则直接返回错误
+fn on_err(errors: &mut Option<&mut Vec<ValidateErr>>, target: ValidateTarget, id: Option<String>, err: AdapterErr) -> Result<(), AdapterErr> {
+    match errors {
+        Some(errors) => {
+            errors.push(ValidateErr::new(target, id, err));
+            Ok(())
+        }
+        None => Err(err),
+    }
+}
+
+pub fn points_to_south(points: MyPoints, old_point_mapping: &HashMap<String, u64>) -> Result<SouthPoints, AdapterErr> {
+    convert_points(points, old_point_mapping, None)
+}
+
+// errors不为空时为校验模式，与实际下发使用同一套转换逻辑
+fn convert_points(points: MyPoints, old_point_mapping: &HashMap<String, u64>, mut errors: Option<&mut Vec<ValidateErr>>)
+    -> Result<SouthPoints, AdapterErr> {
+    let Some(mut points) = points.points else {
+        on_err(&mut errors, ValidateTarget::Point, None, AdapterErr {
             code: ErrCode::PointIsEmpty,
             msg: "测点列表不能为空".to_string(),
-        });
-    }
-    let mut points = points.unwrap();
+        })?;
+        return Ok(Default::default());
+    };
     let mut points_result = vec![];
     let mut mapping_result = HashMap::new();
     let mut point_param = HashMap::new();
@@ -48,12 +90,25 @@ pub fn points_to_south(points: MyPoints, old_point_mapping: &HashMap<String, u64
     };
     // 排序，先普通测点，后计算测点，避免计算公式替换的时候所引用的测点还未创建
     points.sort_by_key(|m| m.is_computing_point);
+    let mut ids = HashSet::with_capacity(points.len());
     for p in points {
+        if !p.point_id.is_empty() && !ids.insert(p.point_id.clone()) {
+            on_err(&mut errors, ValidateTarget::Point, Some(p.point_id.clone()), AdapterErr {
+                code: ErrCode::DuplicateId,
+                msg: format!("测点id重复：{}", p.point_id),
+            })?;
+        }
         let unit = DataUnit::from_str(p.data_unit.as_str()).unwrap_or(DataUnit::Unknown);
         let alarm_level1 = Expr::from_str(p.alarm_level1_expr.as_str()).ok();
         let alarm_level2 = Expr::from_str(p.alarm_level2_expr.as_str()).ok();
         let expression = if p.is_computing_point {
-            replace_point(&p.expression, &mapping_result)?
+            match replace_point(&p.expression, &mapping_result) {
+                Ok(expression) => expression,
+                Err(e) => {
+                    on_err(&mut errors, ValidateTarget::Point, Some(p.point_id.clone()), e)?;
+                    p.expression
+                }
+            }
         } else {
             p.expression
         };
@@ -107,12 +162,42 @@ pub fn points_to_south(points: MyPoints, old_point_mapping: &HashMap<String, u64
 pub fn transports_to_south(
         transports: MyTransports,
         points_mapping: &HashMap<String, u64>,
-        dev_mapping: &HashMap<(String, String, String), (String, String, String)>,
+        dev_mapping: &DevMapping,
         point_param: &HashMap<String, PointParam>,
         point_discrete: &HashMap<String, bool>) -> Result<(Vec<Transport>, u64), AdapterErr> {
+    convert_transports(transports, points_mapping, Some(dev_mapping), point_param, point_discrete, None)
+}
+
+// dev_mapping为空时不校验数据中心属性，也不生成读写模板，只用于校验
+fn convert_transports(
+        transports: MyTransports,
+        points_mapping: &HashMap<String, u64>,
+        dev_mapping: Option<&DevMapping>,
+        point_param: &HashMap<String, PointParam>,
+        point_discrete: &HashMap<String, bool>,
+        mut errors: Option<&mut Vec<ValidateErr>>) -> Result<(Vec<Transport>, u64), AdapterErr> {
     let env = Env::get_env(ADAPTER_NAME);
     let mqtt_broker = (env.get_mqtt_server(), env.get_mqtt_server_port());
-    let new_transport = MyMqttTransportJoin::from_vec(transports.transports)?;
+    let (mqtt_user, mqtt_password) = env.get_mqtt_credential().unzip();
+    let mut current_tid = 65536_u64;
+    if let Some(transports) = &transports.transports {
+        let mut dev_ids = HashSet::with_capacity(transports.len());
+        for MyTransport::Mqtt(t) in transports {
+            if !dev_ids.insert(&t.dev_id) {
+                on_err(&mut errors, ValidateTarget::Transport, Some(t.dev_id.clone()), AdapterErr {
+                    code: ErrCode::DuplicateId,
+                    msg: format!("通道设备id重复：{}", t.dev_id),
+                })?;
+            }
+        }
+    }
+    let new_transport = match MyMqttTransportJoin::from_vec(transports.transports) {
+        Ok(new_transport) => new_transport,
+        Err(e) => {
+            on_err(&mut errors, ValidateTarget::Transport, None, e)?;
+            return Ok((vec![], current_tid));
+        }
+    };
     let app_name = env.get_app_name();
     let mut transports_result = vec![];
 
@@ -142,150 +227,105 @@ pub fn transports_to_south(
     let mut json_tags_yk = HashMap::new();
     let mut point_yk_index = 0;
 
-    let mut current_tid = 65536_u64;
-    for (_, (point_ycyx, point_yt, point_yk)) in new_transport.dev_ids_map.iter() {
-        let points = point_ycyx.iter()
-            .filter(|v|points_mapping.contains_key(*v))
-            .map(|v| (*points_mapping.get(v).unwrap(), false)).collect::<Vec<(u64, bool)>>();
-        point_ycyx_ids.extend(points);
+    for (dev_id, (point_ycyx, point_yt, point_yk)) in new_transport.dev_ids_map.iter() {
         for v in point_ycyx.iter() {
-            if points_mapping.contains_key(v) {
-                if let Some(dev_key) = get_point_attr(v) {
-                    if let Some((dev_guid, _, dc_attr)) = dev_mapping.get(&dev_key) {
-                        let mut value_map = HashMap::with_capacity(1);
-                        value_map.insert("val".to_string(), point_index_ycyx);
-                        json_tags_ycyx.insert(format!("[{point_index_ycyx}]"), value_map);
-                        filter_keys_ycyx.push(vec![
-                            "body/_array/name".to_string(),
-                            "body/_array/quality".to_string(),
-                            "dev".to_string()
-                        ]);
-                        filter_values_ycyx.push(Some(vec![str_to_json_value(&dc_attr),
-                            str_to_json_value("0"),
-                            str_to_json_value(&dev_guid)
-                        ]));
-                        filter_keys_cx.push(vec![
-                            "body/_array/body/_array/name".to_string(),
-                            "body/_array/body/_array/quality".to_string(),
-                            "body/_array/dev".to_string()
-                        ]);
-                        point_index_ycyx = point_index_ycyx + 1;
-                    } else {
-                        return Err(AdapterErr {
-                            code: ErrCode::TransportPointTagErr,
-                            msg: format!("通道解析失败，属性在数据中心未找到：{v}"),
-                        });
-                    }
-                } else {
-                    return Err(AdapterErr {
-                        code: ErrCode::TransportPointTagErr,
-                        msg: format!("通道解析失败，测点格式错误：{v}"),
-                    });
+            let (pid, dev) = match transport_point(v, points_mapping, dev_mapping) {
+                Ok(r) => r,
+                Err(e) => {
+                    on_err(&mut errors, ValidateTarget::Transport, Some(dev_id.clone()), e)?;
+                    continue;
                 }
-            } else {
-                return Err(AdapterErr {
-                    code: ErrCode::TransportPointNotFound,
-                    msg: format!("通道解析失败，找不到测点：{v}"),
-                });
-            }
+            };
+            point_ycyx_ids.push((pid, false));
+            let Some((dev_guid, dc_attr)) = dev else {
+                continue;
+            };
+            let mut value_map = HashMap::with_capacity(1);
+            value_map.insert("val".to_string(), point_index_ycyx);
+            json_tags_ycyx.insert(format!("[{point_index_ycyx}]"), value_map);
+            filter_keys_ycyx.push(vec![
+                "body/_array/name".to_string(),
+                "body/_array/quality".to_string(),
+                "dev".to_string()
+            ]);
+            filter_values_ycyx.push(Some(vec![str_to_json_value(&dc_attr),
+                str_to_json_value("0"),
+                str_to_json_value(&dev_guid)
+            ]));
+            filter_keys_cx.push(vec![
+                "body/_array/body/_array/name".to_string(),
+                "body/_array/body/_array/quality".to_string(),
+                "body/_array/dev".to_string()
+            ]);
+            point_index_ycyx = point_index_ycyx + 1;
         }
-        let points = point_yt.iter()
-            .filter(|v|points_mapping.contains_key(*v))
-            .map(|v| (*points_mapping.get(v).unwrap(), true)).collect::<Vec<(u64, bool)>>();
-        point_yt_ids.extend(points);
         for v in point_yt.iter() {
-            if points_mapping.contains_key(v) {
-                if let Some(dev_key) = get_point_attr(v) {
-                    if let Some((dev_guid, _, dc_attr)) = dev_mapping.get(&dev_key) {
-                        let mut value_map = HashMap::with_capacity(1);
-                        value_map.insert("val".to_string(), point_yt_index);
-                        json_tags_yt.insert(format!("[{point_yt_index}]"), value_map);
-                        filter_keys_yt.push(vec!["dev".to_string()]);
-                        filter_values_yt.push(Some(vec![str_to_json_value("0")]));
-                        let pid = *points_mapping.get(v).unwrap();
-                        let is_discrete = if let Some(is_discrete) = point_discrete.get(v) {
-                            *is_discrete
-                        } else {
-                            false
-                        };
-                        let datatype = if is_discrete {"int"} else {"float"};
-                        json_write_template_yt.insert(
-                            pid,
-                            format!("{{\"token\": \"increment:u16\",\"timestamp\": \"%Y-%m-%dT%H:%M:%S.%3f%z\",\"body\": [{{\"dev\": \"{dev_guid}\",\"timeout\": \"60\",\"body\": [{{\"name\": \"{dc_attr}\",\"val\": \"\",\"unit\": \"\",\"datatype\": \"{datatype}\"}}]}}]}}")
-                        );
-                        json_write_tag_yt.insert(
-                            pid,
-                            "body/_array/body/_array/val;timestamp;token".to_string()
-                        );
-                        point_yt_index = point_yt_index + 1;
-                    } else {
-                        return Err(AdapterErr {
-                            code: ErrCode::TransportPointTagErr,
-                            msg: format!("通道解析失败，属性在数据中心未找到：{v}"),
-                        });
-                    }
-                } else {
-                    return Err(AdapterErr {
-                        code: ErrCode::TransportPointTagErr,
-                        msg: format!("通道解析失败，测点格式错误：{v}"),
-                    });
+            let (pid, dev) = match transport_point(v, points_mapping, dev_mapping) {
+                Ok(r) => r,
+                Err(e) => {
+                    on_err(&mut errors, ValidateTarget::Transport, Some(dev_id.clone()), e)?;
+                    continue;
                 }
+            };
+            point_yt_ids.push((pid, true));
+            let Some((dev_guid, dc_attr)) = dev else {
+                continue;
+            };
+            let mut value_map = HashMap::with_capacity(1);
+            value_map.insert("val".to_string(), point_yt_index);
+            json_tags_yt.insert(format!("[{point_yt_index}]"), value_map);
+            filter_keys_yt.push(vec!["dev".to_string()]);
+            filter_values_yt.push(Some(vec![str_to_json_value("0")]));
+            let is_discrete = if let Some(is_discrete) = point_discrete.get(v) {
+                *is_discrete
             } else {
-                return Err(AdapterErr {
-                    code: ErrCode::TransportPointNotFound,
-                    msg: format!("通道解析失败，找不到测点：{v}"),
-                });
-            }
+                false
+            };
+            let datatype = if is_discrete {"int"} else {"float"};
+            json_write_template_yt.insert(
+                pid,
+                format!("{{\"token\": \"increment:u16\",\"timestamp\": \"%Y-%m-%dT%H:%M:%S.%3f%z\",\"body\": [{{\"dev\": \"{dev_guid}\",\"timeout\": \"60\",\"body\": [{{\"name\": \"{dc_attr}\",\"val\": \"\",\"unit\": \"\",\"datatype\": \"{datatype}\"}}]}}]}}")
+            );
+            json_write_tag_yt.insert(
+                pid,
+                "body/_array/body/_array/val;timestamp;token".to_string()
+            );
+            point_yt_index = point_yt_index + 1;
         }
-        let points = point_yk.iter()
-            .filter(|v|points_mapping.contains_key(*v))
-            .map(|v| (*points_mapping.get(v).unwrap(), true)).collect::<Vec<(u64, bool)>>();
-        point_yk_ids.extend(points);
         for v in point_yk.iter() {
-            if points_mapping.contains_key(v) {
-                if let Some(dev_key) = get_point_attr(v) {
-                    if let Some((dev_guid, _, dc_attr)) = dev_mapping.get(&dev_key) {
-                        let mut value_map = HashMap::with_capacity(1);
-                        value_map.insert("val".to_string(), point_yk_index);
-                        json_tags_yk.insert(format!("[{point_yk_index}]"), value_map);
-                        filter_keys_yk.push(vec!["dev".to_string()]);
-                        filter_values_yk.push(Some(vec![str_to_json_value("0")]));
-                        let pid = *points_mapping.get(v).unwrap();
-                        let (action, timeout, mtype, mode) = if let Some(param) = point_param.get(v) {
-                            (param.action.clone().unwrap_or("1".to_string()),
-                            param.timeout.clone().unwrap_or("60".to_string()),
-                            param.mtype.clone().unwrap_or("SCO".to_string()),
-                            param.mode.clone().unwrap_or("0".to_string()))
-                        } else {
-                            ("1".to_string(), "60".to_string(), "SCO".to_string(), "0".to_string())
-                        };
-                        json_write_template_yk.insert(
-                            pid,
-                            format!("{{\"token\": \"increment:u16\",\"time\": \"%Y-%m-%dT%H:%M:%S.%3f%z\",\"body\": [{{\"dev\": \"{dev_guid}\",\"name\": \"{dc_attr}\",\"type\": \"{mtype}\",\"cmd\": \"0\",\"action\": \"{action}\",\"mode\": \"{mode}\",\"timeout\": \"{timeout}\"}}]}}")
-                        );
-                        json_write_tag_yk.insert(
-                            pid,
-                            "body/_array/cmd;time;token".to_string()
-                        );
-                        point_yk_index = point_yk_index + 1;
-                    } else {
-                        return Err(AdapterErr {
-                            code: ErrCode::TransportPointTagErr,
-                            msg: format!("通道解析失败，属性在数据中心未找到：{v}"),
-                        });
-                    }
-                } else {
-                    return Err(AdapterErr {
-                        code: ErrCode::TransportPointTagErr,
-                        msg: format!("通道解析失败，测点格式错误：{v}"),
-                    });
+            let (pid, dev) = match transport_point(v, points_mapping, dev_mapping) {
+                Ok(r) => r,
+                Err(e) => {
+                    on_err(&mut errors, ValidateTarget::Transport, Some(dev_id.clone()), e)?;
+                    continue;
                 }
+            };
+            point_yk_ids.push((pid, true));
+            let Some((dev_guid, dc_attr)) = dev else {
+                continue;
+            };
+            let mut value_map = HashMap::with_capacity(1);
+            value_map.insert("val".to_string(), point_yk_index);
+            json_tags_yk.insert(format!("[{point_yk_index}]"), value_map);
+            filter_keys_yk.push(vec!["dev".to_string()]);
+            filter_values_yk.push(Some(vec![str_to_json_value("0")]));
+            let (action, timeout, mtype, mode) = if let Some(param) = point_param.get(v) {
+                (param.action.clone().unwrap_or("1".to_string()),
+                param.timeout.clone().unwrap_or("60".to_string()),
+                param.mtype.clone().unwrap_or("SCO".to_string()),
+                param.mode.clone().unwrap_or("0".to_string()))
             } else {
-                return Err(AdapterErr {
-                    code: ErrCode::TransportPointNotFound,
-                    msg: format!("通道解析失败，找不到测点：{v}"),
-                });
-            }
+                ("1".to_string(), "60".to_string(), "SCO".to_string(), "0".to_string())
+            };
+            json_write_template_yk.insert(
+                pid,
+                format!("{{\"token\": \"increment:u16\",\"time\": \"%Y-%m-%dT%H:%M:%S.%3f%z\",\"body\": [{{\"dev\": \"{dev_guid}\",\"name\": \"{dc_attr}\",\"type\": \"{mtype}\",\"cmd\": \"0\",\"action\": \"{action}\",\"mode\": \"{mode}\",\"timeout\": \"{timeout}\"}}]}}")
+            );
+            json_write_tag_yk.insert(
+                pid,
+                "body/_array/cmd;time;token".to_string()
+            );
+            point_yk_index = point_yk_index + 1;
         }
     }
     // 遥测遥信通道
@@ -302,8 +342,8 @@ pub fn transports_to_south(
             is_json: true,
             is_transfer: false,
             keep_alive: None,
-            user_name: None,
-            user_password: None,
+            user_name: mqtt_user.clone(),
+            user_password: mqtt_password.clone(),
             array_filter: None,
             filter_keys: Some(filter_keys_ycyx.clone()),
             filter_values: Some(filter_values_ycyx.clone()),
@@ -323,8 +363,8 @@ pub fn transports_to_south(
             is_json: true,
             is_transfer: false,
             keep_alive: None,
-            user_name: None,
-            user_password: None,
+            user_name: mqtt_user.clone(),
+            user_password: mqtt_password.clone(),
             array_filter: None,
             filter_keys: Some(filter_keys_ycyx),
             filter_values: Some(filter_values_ycyx.clone()),
@@ -344,8 +384,8 @@ pub fn transports_to_south(
             is_json: true,
             is_transfer: false,
             keep_alive: None,
-            user_name: None,
-            user_password: None,
+            user_name: mqtt_user.clone(),
+            user_password: mqtt_password.clone(),
             array_filter: None,
             filter_keys: Some(filter_keys_cx),
             filter_values: Some(filter_values_ycyx),
@@ -371,8 +411,8 @@ pub fn transports_to_south(
             is_json: true,
             is_transfer: false,
             keep_alive: None,
-            user_name: None,
-            user_password: None,
+            user_name: mqtt_user.clone(),
+            user_password: mqtt_password.clone(),
             array_filter: Some("body".to_string()),
             filter_keys: Some(filter_keys_yt),
             filter_values: Some(filter_values_yt),
@@ -396,8 +436,8 @@ pub fn transports_to_south(
             is_json: true,
             is_transfer: false,
             keep_alive: None,
-            user_name: None,
-            user_password: None,
+            user_name: mqtt_user.clone(),
+            user_password: mqtt_password.clone(),
             array_filter: Some("body".to_string()),
             filter_keys: Some(filter_keys_yk),
             filter_values: Some(filter_values_yk),
@@ -410,31 +450,89 @@ pub fn transports_to_south(
     Ok((transports_result, current_tid))
 }
 
-pub fn aoes_to_south(aoes: MyAoes, points_mapping: &HashMap<String, u64>, current_id: u64) -> Result<(Vec<AoeModel>, HashMap<u64, u64>), AdapterErr> {
-    let aoes = replace_point_for_aoe(aoes, points_mapping)?;
+// 通道中测点的南向id，以及数据中心的设备guid和属性名，dev_mapping为空时不查找属性
+fn transport_point(v: &str, points_mapping: &HashMap<String, u64>, dev_mapping: Option<&DevMapping>)
+    -> Result<(u64, Option<(String, String)>), AdapterErr> {
+    let Some(pid) = points_mapping.get(v) else {
+        return Err(AdapterErr {
+            code: ErrCode::TransportPointNotFound,
+            msg: format!("通道解析失败，找不到测点：{v}"),
+        });
+    };
+    let Some(dev_key) = get_point_attr(v) else {
+        return Err(AdapterErr {
+            code: ErrCode::TransportPointTagErr,
+            msg: format!("通道解析失败，测点格式错误：{v}"),
+        });
+    };
+    let Some(dev_mapping) = dev_mapping else {
+        return Ok((*pid, None));
+    };
+    match dev_mapping.get(&dev_key) {
+        Some((dev_guid, _, dc_attr)) => Ok((*pid, Some((dev_guid.clone(), dc_attr.clone())))),
+        None => Err(AdapterErr {
+            code: ErrCode::TransportPointTagErr,
+            msg: format!("通道解析失败，属性在数据中心未找到：{v}"),
+        }),
+    }
+}
+
+/// old_aoe_mapping为已有的南向到北向id映射，已有的策略沿用原来的南向id，
+/// 新策略从last_id之后分配，返回值中包括新的last_id
+pub fn aoes_to_south(aoes: MyAoes, points_mapping: &HashMap<String, u64>, old_aoe_mapping: &HashMap<u64, u64>, last_id: u64)
+    -> Result<(Vec<AoeModel>, HashMap<u64, u64>, u64), AdapterErr> {
+    convert_aoes(aoes, points_mapping, old_aoe_mapping, last_id, None)
+}
+
+fn convert_aoes(aoes: MyAoes, points_mapping: &HashMap<String, u64>, old_aoe_mapping: &HashMap<u64, u64>, last_id: u64,
+    mut errors: Option<&mut Vec<ValidateErr>>) -> Result<(Vec<AoeModel>, HashMap<u64, u64>, u64), AdapterErr> {
+    let old_ids = old_aoe_mapping.iter().map(|(sid, nid)| (*nid, *sid)).collect::<HashMap<u64, u64>>();
     let mut aoes_result = vec![];
     let mut aoes_mapping = HashMap::new();
-    let mut current_id = current_id;
+    let mut last_id = last_id;
     if let Some(aoes) = aoes.aoes {
+        let mut ids = HashSet::with_capacity(aoes.len());
         for a in aoes {
-            current_id = current_id + 1;
-            let trigger_type = trigger_type_to_south(a.trigger_type)?;
-            let variables = variables_to_south(a.variables)?;
-            let events = events_to_south(current_id, a.events)?;
-            let actions = actions_to_south(current_id, a.actions)?;
-            let aoe = AoeModel {
-                id: current_id,
-                name: a.name,
-                events,
-                actions,
-                trigger_type,
-                variables,
-            };
-            aoes_result.push(aoe);
-            aoes_mapping.insert(current_id, a.id);
+            let id = a.id;
+            if !ids.insert(id) {
+                on_err(&mut errors, ValidateTarget::Aoe, Some(id.to_string()), AdapterErr {
+                    code: ErrCode::DuplicateId,
+                    msg: format!("策略id重复：{id}"),
+                })?;
+            }
+            match aoe_to_south(a, points_mapping, &old_ids, &mut last_id) {
+                Ok(aoe) => {
+                    aoes_mapping.insert(aoe.id, id);
+                    aoes_result.push(aoe);
+                }
+                Err(e) => on_err(&mut errors, ValidateTarget::Aoe, Some(id.to_string()), e)?,
+            }
         }
     }
-    Ok((aoes_result, aoes_mapping))
+    Ok((aoes_result, aoes_mapping, last_id))
+}
+
+// 已有的策略沿用old_ids中的南向id，新策略分配last_id之后的id
+fn aoe_to_south(a: MyAoe, points_mapping: &HashMap<String, u64>, old_ids: &HashMap<u64, u64>, last_id: &mut u64) -> Result<AoeModel, AdapterErr> {
+    let a = replace_point_for_aoe(a, points_mapping)?;
+    let current_id = if let Some(sid) = old_ids.get(&a.id) {
+        *sid
+    } else {
+        *last_id += 1;
+        *last_id
+    };
+    let trigger_type = trigger_type_to_south(a.trigger_type)?;
+    let variables = variables_to_south(a.variables)?;
+    let events = events_to_south(current_id, a.events)?;
+    let actions = actions_to_south(current_id, a.actions)?;
+    Ok(AoeModel {
+        id: current_id,
+        name: a.name,
+        events,
+        actions,
+        trigger_type,
+        variables,
+    })
 }
 
 fn trigger_type_to_south(north: MyTriggerType) -> Result<TriggerType, AdapterErr> {
@@ -757,44 +855,40 @@ fn get_nlp(my_programming: MyProgramming) -> Result<NLP, AdapterErr> {
     }
 }
 
-fn replace_point_for_aoe(mut aoes: MyAoes, points_mapping: &HashMap<String, u64>) -> Result<MyAoes, AdapterErr> {
-    if let Some(ref mut aoes) = aoes.aoes {
-        for aoe in aoes.iter_mut() {
-            let mut new_variables = vec![];
-            for (key, value) in &aoe.variables {
-                let key = replace_point(key, points_mapping)?;
-                let value = replace_point(value, points_mapping)?;
-                new_variables.push((key, value));
-            }
-            aoe.variables = new_variables;
-            let mut new_events = vec![];
-            for mut event in aoe.events.clone() {
-                event.expr = replace_point(&event.expr, points_mapping)?;
-                new_events.push(event);
-            }
-            aoe.events = new_events;
-            let mut new_actions = vec![];
-            for mut edge in aoe.actions.clone() {
-                let new_action = match edge.action {
-                    MyEigAction::None(str) => MyEigAction::None(str),
-                    MyEigAction::SetPoints(my_set_points) => MyEigAction::SetPoints(replace_point_for_set_points(my_set_points, points_mapping)?),
-                    MyEigAction::SetPointsWithCheck(my_set_points) => MyEigAction::SetPointsWithCheck(replace_point_for_set_points(my_set_points, points_mapping)?),
-                    MyEigAction::SetPoints2(my_set_points) => MyEigAction::SetPoints2(replace_point_for_set_points(my_set_points, points_mapping)?),
-                    MyEigAction::SetPointsWithCheck2(my_set_points) => MyEigAction::SetPointsWithCheck2(replace_point_for_set_points(my_set_points, points_mapping)?),
-                    MyEigAction::Solve(my_solver) => MyEigAction::Solve(replace_point_for_solver(my_solver, points_mapping)?),
-                    MyEigAction::Nlsolve(my_solver) => MyEigAction::Nlsolve(replace_point_for_solver(my_solver, points_mapping)?),
-                    MyEigAction::Milp(my_programming) => MyEigAction::Milp(replace_point_for_programming(my_programming, points_mapping)?),
-                    MyEigAction::SimpleMilp(my_programming) => MyEigAction::SimpleMilp(replace_point_for_programming(my_programming, points_mapping)?),
-                    MyEigAction::Nlp(my_programming) => MyEigAction::Nlp(replace_point_for_programming(my_programming, points_mapping)?),
-                    MyEigAction::Url(url) => MyEigAction::Url(url),
-                };
-                edge.action = new_action;
-                new_actions.push(edge);
-            }
-            aoe.actions = new_actions;
-        }
+fn replace_point_for_aoe(mut aoe: MyAoe, points_mapping: &HashMap<String, u64>) -> Result<MyAoe, AdapterErr> {
+    let mut new_variables = vec![];
+    for (key, value) in &aoe.variables {
+        let key = replace_point(key, points_mapping)?;
+        let value = replace_point(value, points_mapping)?;
+        new_variables.push((key, value));
+    }
+    aoe.variables = new_variables;
+    let mut new_events = vec![];
+    for mut event in aoe.events.clone() {
+        event.expr = replace_point(&event.expr, points_mapping)?;
+        new_events.push(event);
     }
-    Ok(MyAoes{aoes: aoes.aoes, add: None, edit: None, delete: None})
+    aoe.events = new_events;
+    let mut new_actions = vec![];
+    for mut edge in aoe.actions.clone() {
+        let new_action = match edge.action {
+            MyEigAction::None(str) => MyEigAction::None(str),
+            MyEigAction::SetPoints(my_set_points) => MyEigAction::SetPoints(replace_point_for_set_points(my_set_points, points_mapping)?),
+            MyEigAction::SetPointsWithCheck(my_set_points) => MyEigAction::SetPointsWithCheck(replace_point_for_set_points(my_set_points, points_mapping)?),
+            MyEigAction::SetPoints2(my_set_points) => MyEigAction::SetPoints2(replace_point_for_set_points(my_set_points, points_mapping)?),
+            MyEigAction::SetPointsWithCheck2(my_set_points) => MyEigAction::SetPointsWithCheck2(replace_point_for_set_points(my_set_points, points_mapping)?),
+            MyEigAction::Solve(my_solver) => MyEigAction::Solve(replace_point_for_solver(my_solver, points_mapping)?),
+            MyEigAction::Nlsolve(my_solver) => MyEigAction::Nlsolve(replace_point_for_solver(my_solver, points_mapping)?),
+            MyEigAction::Milp(my_programming) => MyEigAction::Milp(replace_point_for_programming(my_programming, points_mapping)?),
+            MyEigAction::SimpleMilp(my_programming) => MyEigAction::SimpleMilp(replace_point_for_programming(my_programming, points_mapping)?),
+            MyEigAction::Nlp(my_programming) => MyEigAction::Nlp(replace_point_for_programming(my_programming, points_mapping)?),
+            MyEigAction::Url(url) => MyEigAction::Url(url),
+        };
+        edge.action = new_action;
+        new_actions.push(edge);
+    }
+    aoe.actions = new_actions;
+    Ok(aoe)
 }
 
 fn replace_point_for_set_points(my_set_points: MySetPoints, points_mapping: &HashMap<String, u64>) -> Result<MySetPoints, AdapterErr> {
@@ -874,46 +968,70 @@ pub fn aoe_event_result_to_north(event_result: PbEventResult) -> Result<MyPbEven
     })
 }
 
-pub fn dffs_to_south(dffs: MyDffModels, points_mapping: &HashMap<String, u64>, current_id: u64) -> Result<(Vec<DffModel>, HashMap<u64, u64>), AdapterErr> {
-    let dffs = replace_point_for_dff(&dffs, points_mapping)?;
+/// id分配方式与aoes_to_south相同
+pub fn dffs_to_south(dffs: MyDffModels, points_mapping: &HashMap<String, u64>, old_dff_mapping: &HashMap<u64, u64>, last_id: u64)
+    -> Result<(Vec<DffModel>, HashMap<u64, u64>, u64), AdapterErr> {
+    convert_dffs(dffs, points_mapping, old_dff_mapping, last_id, None)
+}
+
+fn convert_dffs(dffs: MyDffModels, points_mapping: &HashMap<String, u64>, old_dff_mapping: &HashMap<u64, u64>, last_id: u64,
+    mut errors: Option<&mut Vec<ValidateErr>>) -> Result<(Vec<DffModel>, HashMap<u64, u64>, u64), AdapterErr> {
+    let old_ids = old_dff_mapping.iter().map(|(sid, nid)| (*nid, *sid)).collect::<HashMap<u64, u64>>();
     let mut dffs_result = vec![];
     let mut dffs_mapping = HashMap::new();
-    let mut current_id = current_id;
+    let mut last_id = last_id;
     if let Some(dffs) = dffs.dffs {
+        let mut ids = HashSet::with_capacity(dffs.len());
         for d in dffs {
-            current_id = current_id + 1;
-            let trigger_type = dfftrigger_type_to_south(d.trigger_type)?;
-            let nodes = dffnodes_to_south(current_id, d.nodes)?;
-            let actions = dffactions_to_south(current_id, d.actions)?;
-            let dff = DffModel {
-                id: current_id,
-                name: d.name,
-                nodes,
-                actions,
-                trigger_type,
-                save_mode: d.save_mode,
-                is_on: d.is_on,
-                aoe_var: d.aoe_var,
-            };
-            dffs_result.push(dff);
-            dffs_mapping.insert(current_id, d.id);
+            let id = d.id;
+            if !ids.insert(id) {
+                on_err(&mut errors, ValidateTarget::Dff, Some(id.to_string()), AdapterErr {
+                    code: ErrCode::DuplicateId,
+                    msg: format!("报表id重复：{id}"),
+                })?;
+            }
+            match dff_to_south(d, points_mapping, &old_ids, &mut last_id) {
+                Ok(dff) => {
+                    dffs_mapping.insert(dff.id, id);
+                    dffs_result.push(dff);
+                }
+                Err(e) => on_err(&mut errors, ValidateTarget::Dff, Some(id.to_string()), e)?,
+            }
         }
     }
-    Ok((dffs_result, dffs_mapping))
+    Ok((dffs_result, dffs_mapping, last_id))
 }
 
-fn replace_point_for_dff(dffs: &MyDffModels, points_mapping: &HashMap<String, u64>) -> Result<MyDffModels, AdapterErr> {
-    let mut dffs = dffs.clone();
-    if let Some(dffs) = dffs.dffs.as_mut() {
-        for dff in dffs.iter_mut() {
-            for node in dff.nodes.iter_mut() {
-                if let MyDfNodeType::Source(MyDfSource::Points(points)) = &node.node_type {
-                    node.node_type = MyDfNodeType::Source(MyDfSource::Points(replace_point(points, points_mapping)?));
-                }
-            }
+fn dff_to_south(d: MyDffModel, points_mapping: &HashMap<String, u64>, old_ids: &HashMap<u64, u64>, last_id: &mut u64) -> Result<DffModel, AdapterErr> {
+    let d = replace_point_for_dff(d, points_mapping)?;
+    let current_id = if let Some(sid) = old_ids.get(&d.id) {
+        *sid
+    } else {
+        *last_id += 1;
+        *last_id
+    };
+    let trigger_type = dfftrigger_type_to_south(d.trigger_type)?;
+    let nodes = dffnodes_to_south(current_id, d.nodes)?;
+    let actions = dffactions_to_south(current_id, d.actions)?;
+    Ok(DffModel {
+        id: current_id,
+        name: d.name,
+        nodes,
+        actions,
+        trigger_type,
+        save_mode: d.save_mode,
+        is_on: d.is_on,
+        aoe_var: d.aoe_var,
+    })
+}
+
+fn replace_point_for_dff(mut dff: MyDffModel, points_mapping: &HashMap<String, u64>) -> Result<MyDffModel, AdapterErr> {
+    for node in dff.nodes.iter_mut() {
+        if let MyDfNodeType::Source(MyDfSource::Points(points)) = &node.node_type {
+            node.node_type = MyDfNodeType::Source(MyDfSource::Points(replace_point(points, points_mapping)?));
         }
     }
-    Ok(dffs)
+    Ok(dff)
 }
 
 fn dfftrigger_type_to_south(north: MyDfTriggerType) -> Result<DfTriggerType, AdapterErr> {
@@ -1085,6 +1203,43 @@ fn dffactions_to_south(dff_id: u64, north: Vec<MyDfActionEdge>) -> Result<Vec<Df
     Ok(actions)
 }
 
+/// 以校验模式转换测点，返回测点映射（id仅用于校验）和所有错误
+pub fn validate_points(points: &MyPoints) -> (HashMap<String, u64>, Vec<ValidateErr>) {
+    let mut errors = vec![];
+    let mapping = convert_points(points.clone(), &HashMap::new(), Some(&mut errors))
+        .map(|(_, mapping, ..)| mapping)
+        .unwrap_or_default();
+    (mapping, errors)
+}
+
+/// 以校验模式转换通道，dev_mapping为空时不校验数据中心属性
+pub fn validate_transports(
+        transports: &MyTransports,
+        points_mapping: &HashMap<String, u64>,
+        dev_mapping: Option<&DevMapping>) -> Vec<ValidateErr> {
+    let mut errors = vec![];
+    // 没有通道时下发也会跳过
+    if transports.transports.is_none() {
+        return errors;
+    }
+    let _ = convert_transports(transports.clone(), points_mapping, dev_mapping, &HashMap::new(), &HashMap::new(), Some(&mut errors));
+    errors
+}
+
+/// 以校验模式转换策略，收集所有出错的策略
+pub fn validate_aoes(aoes: &MyAoes, points_mapping: &HashMap<String, u64>) -> Vec<ValidateErr> {
+    let mut errors = vec![];
+    let _ = convert_aoes(aoes.clone(), points_mapping, &HashMap::new(), AOE_ID_START - 1, Some(&mut errors));
+    errors
+}
+
+/// 以校验模式转换报表，收集所有出错的报表
+pub fn validate_dffs(dffs: &MyDffModels, points_mapping: &HashMap<String, u64>) -> Vec<ValidateErr> {
+    let mut errors = vec![];
+    let _ = convert_dffs(dffs.clone(), points_mapping, &HashMap::new(), DFF_ID_START - 1, Some(&mut errors));
+    errors
+}
+
 pub fn polars_to_json_df(df: &DataFrame) -> Vec<u8> {
     let mut buf = Vec::new();
     let mut d = df.clone();
@@ -1106,3 +1261,182 @@ pub fn json_df_to_polars(df: Vec<u8>) -> DataFrame {
         DataFrame::empty()
     }
 }
+
+/// 将DataFrame转换为按行的JSON对象，key为列名
+pub fn polars_to_json_rows(df: &DataFrame) -> Vec<serde_json::Map<String, Value>> {
+    let columns = df.get_columns();
+    (0..df.height()).map(|i| {
+        columns.iter().map(|c| {
+            let v = c.get(i).map(any_value_to_json).unwrap_or(Value::Null);
+            (c.name().to_string(), v)
+        }).collect()
+    }).collect()
+}
+
+fn any_value_to_json(v: AnyValue) -> Value {
+    match v {
+        AnyValue::Null => Value::Null,
+        AnyValue::Boolean(b) => Value::from(b),
+        AnyValue::UInt8(n) => Value::from(n),
+        AnyValue::UInt16(n) => Value::from(n),
+        AnyValue::UInt32(n) => Value::from(n),
+        AnyValue::UInt64(n) => Value::from(n),
+        AnyValue::Int8(n) => Value::from(n),
+        AnyValue::Int16(n) => Value::from(n),
+        AnyValue::Int32(n) => Value::from(n),
+        AnyValue::Int64(n) => Value::from(n),
+        // NaN和无穷大无法用JSON表示，转换为null
+        AnyValue::Float32(f) => Value::from(f as f64),
+        AnyValue::Float64(f) => Value::from(f),
+        AnyValue::String(s) => Value::from(s),
+        AnyValue::StringOwned(s) => Value::from(s.as_str()),
+        other => Value::from(other.to_string()),
+    }
+}
+
+#[cfg(test)]
+fn test_point(point_id: &str, is_computing_point: bool, expression: &str) -> MyMeasurement {
+    serde_json::from_value(serde_json::json!({
+        "point_id": point_id, "point_name": point_id, "alias_id": "", "is_discrete": false,
+        "is_computing_point": is_computing_point, "expression": expression,
+        "trans_expr": "", "inv_trans_expr": "", "change_expr": "", "zero_expr": "", "data_unit": "",
+        "upper_limit": null, "lower_limit": null, "alarm_level1_expr": "", "alarm_level2_expr": "",
+        "is_realtime": false, "is_soe": false, "init_value": "0", "desc": "", "param": null, "app_api_param": null
+    })).unwrap()
+}
+
+#[cfg(test)]
+fn test_aoe(id: u64, variable: &str) -> MyAoe {
+    serde_json::from_value(serde_json::json!({
+        "id": id.to_string(), "name": format!("aoe{id}"), "events": [], "actions": [],
+        "trigger_type": {"SimpleRepeat": "1000"}, "variables": [["x", variable]]
+    })).unwrap()
+}
+
+#[cfg(test)]
+fn test_dff(id: u64) -> MyDffModel {
+    serde_json::from_value(serde_json::json!({
+        "id": id.to_string(), "is_on": true, "name": format!("dff{id}"), "trigger_type": {"Manual": ""},
+        "nodes": [], "actions": [], "save_mode": "Never", "aoe_var": null
+    })).unwrap()
+}
+
+#[test]
+fn test_validate_points() {
+    let points = MyPoints {
+        points: Some(vec![
+            test_point("${d1.m.a}", false, ""),
+            test_point("${d1.m.a}", false, ""),
+            test_point("${d1.m.b}", true, "${d1.m.c}*2"),
+        ]),
+        add: None,
+        edit: None,
+        delete: None,
+    };
+    let (mapping, errors) = validate_points(&points);
+    assert!(mapping.contains_key("${d1.m.a}"));
+    let codes = errors.iter().map(|e| (e.code.clone(), e.id.clone().unwrap_or_default())).collect::<Vec<_>>();
+    assert_eq!(codes, vec![
+        (ErrCode::DuplicateId, "${d1.m.a}".to_string()),
+        (ErrCode::PointUndefined, "${d1.m.b}".to_string()),
+    ]);
+    // 下发时遇到第一个错误即失败
+    let err = points_to_south(points, &HashMap::new()).unwrap_err();
+    assert_eq!(err.code, ErrCode::DuplicateId);
+    let empty = MyPoints { points: None, add: None, edit: None, delete: None };
+    assert_eq!(validate_points(&empty).1[0].code, ErrCode::PointIsEmpty);
+}
+
+#[test]
+fn test_validate_transports() {
+    let transport = |dev_id: &str, points: &[&str]| MyTransport::Mqtt(MyMqttTransport {
+        dev_id: dev_id.to_string(),
+        name: "t".to_string(),
+        point_ycyx_ids: points.iter().map(|p| p.to_string()).collect(),
+        point_yt_ids: vec![],
+        point_yk_ids: vec![],
+    });
+    let mut points_mapping = HashMap::new();
+    points_mapping.insert("${d1.m.a}".to_string(), 100001);
+    points_mapping.insert("${d2.m.b}".to_string(), 100002);
+    points_mapping.insert("bad".to_string(), 100003);
+    let transports = MyTransports {
+        transports: Some(vec![
+            transport("d1", &["${d1.m.a}"]),
+            // 重复的设备以最后一个为准
+            transport("d1", &["${d1.m.a}", "${d1.m.x}"]),
+            transport("d2", &["${d2.m.b}", "bad"]),
+        ]),
+        add: None,
+        edit: None,
+        delete: None,
+    };
+    let mut codes = validate_transports(&transports, &points_mapping, None).into_iter()
+        .map(|e| e.code)
+        .collect::<Vec<ErrCode>>();
+    codes.sort_by_key(|c| c.clone() as u16);
+    assert_eq!(codes, vec![ErrCode::TransportPointNotFound, ErrCode::TransportPointTagErr, ErrCode::DuplicateId]);
+    // 数据中心没有对应属性
+    let mut dev_mapping = HashMap::new();
+    dev_mapping.insert(("d2".to_string(), "m".to_string(), "b".to_string()), ("guid".to_string(), "m".to_string(), "b".to_string()));
+    let only_d1 = MyTransports { transports: Some(vec![transport("d1", &["${d1.m.a}"])]), add: None, edit: None, delete: None };
+    let errors = validate_transports(&only_d1, &points_mapping, Some(&dev_mapping));
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, ErrCode::TransportPointTagErr);
+    let only_d2 = MyTransports { transports: Some(vec![transport("d2", &["${d2.m.b}"])]), add: None, edit: None, delete: None };
+    assert!(validate_transports(&only_d2, &points_mapping, Some(&dev_mapping)).is_empty());
+    let empty = MyTransports { transports: Some(vec![]), add: None, edit: None, delete: None };
+    assert_eq!(validate_transports(&empty, &points_mapping, None)[0].code, ErrCode::TransportIsEmpty);
+}
+
+#[test]
+fn test_validate_aoes_and_dffs() {
+    let mut points_mapping = HashMap::new();
+    points_mapping.insert("${d1.m.a}".to_string(), 100001);
+    let aoes = MyAoes {
+        aoes: Some(vec![test_aoe(1, "1"), test_aoe(1, "2"), test_aoe(2, "${d1.m.x}")]),
+        add: None,
+        edit: None,
+        delete: None,
+    };
+    let errors = validate_aoes(&aoes, &points_mapping);
+    let codes = errors.iter().map(|e| (e.code.clone(), e.id.clone().unwrap_or_default())).collect::<Vec<_>>();
+    assert_eq!(codes, vec![(ErrCode::DuplicateId, "1".to_string()), (ErrCode::PointUndefined, "2".to_string())]);
+    let dffs = MyDffModels { dffs: Some(vec![test_dff(5), test_dff(5)]), add: None, edit: None, delete: None };
+    let errors = validate_dffs(&dffs, &points_mapping);
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].code, ErrCode::DuplicateId);
+}
+
+#[test]
+fn test_aoe_dff_id_reuse() {
+    // 南向id到北向id
+    let mut old_aoe_mapping = HashMap::new();
+    old_aoe_mapping.insert(65540, 1);
+    let aoes = MyAoes { aoes: Some(vec![test_aoe(1, "1"), test_aoe(2, "2")]), add: None, edit: None, delete: None };
+    let (aoes, mapping, last_id) = aoes_to_south(aoes, &HashMap::new(), &old_aoe_mapping, 65545).unwrap();
+    assert_eq!(aoes.iter().map(|a| a.id).collect::<Vec<u64>>(), vec![65540, 65546]);
+    assert_eq!(mapping.get(&65540), Some(&1));
+    assert_eq!(mapping.get(&65546), Some(&2));
+    assert_eq!(last_id, 65546);
+
+    let mut old_dff_mapping = HashMap::new();
+    old_dff_mapping.insert(10_000_003, 5);
+    let dffs = MyDffModels { dffs: Some(vec![test_dff(6), test_dff(5)]), add: None, edit: None, delete: None };
+    let (dffs, mapping, last_id) = dffs_to_south(dffs, &HashMap::new(), &old_dff_mapping, 10_000_003).unwrap();
+    assert_eq!(dffs.iter().map(|d| d.id).collect::<Vec<u64>>(), vec![10_000_004, 10_000_003]);
+    assert_eq!(mapping.get(&10_000_003), Some(&5));
+    assert_eq!(last_id, 10_000_004);
+}
+
+#[test]
+fn test_resolve_last_ids() {
+    let empty = HashMap::new();
+    assert_eq!(resolve_last_ids(None, None, &empty, &empty), (AOE_ID_START - 1, DFF_ID_START - 1));
+    // 没有记录时取映射中的最大值，旧版本在策略区间内的报表id也要避开
+    let aoe_mapping = HashMap::from([(65540, 1), (65550, 2)]);
+    let dff_mapping = HashMap::from([(65600, 5), (10_000_003, 6)]);
+    assert_eq!(resolve_last_ids(None, None, &aoe_mapping, &dff_mapping), (65600, 10_000_003));
+    // 已删除的策略和报表的id不再复用
+    assert_eq!(resolve_last_ids(Some(65700), Some(10_000_010), &aoe_mapping, &dff_mapping), (65700, 10_000_010));
+}
@@ -588,3 +588,66 @@ pub struct MyDffResult {
     pub end_time: Option<u64>,
     pub result: Vec<u8>,
 }
+
+/// 校验出错的对象类型
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ValidateTarget {
+    Point,
+    Transport,
+    Aoe,
+    Dff,
+}
+
+/// 校验发现的单个错误
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ValidateErr {
+    pub target: ValidateTarget,
+    /// 测点id、通道设备id、策略id或报表id，文件级错误时为空
+    pub id: Option<String>,
+    pub code: ErrCode,
+    pub msg: String,
+}
+
+impl ValidateErr {
+    pub fn new(target: ValidateTarget, id: Option<String>, err: AdapterErr) -> Self {
+        ValidateErr {
+            target,
+            id,
+            code: err.code,
+            msg: err.msg,
+        }
+    }
+}
+
+/// 校验结果，code为第一个错误的错误码，没有错误时为Success
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ValidateResult {
+    pub code: ErrCode,
+    pub errors: Vec<ValidateErr>,
+}
+
+impl ValidateResult {
+    pub fn new(errors: Vec<ValidateErr>) -> Self {
+        let code = errors.first().map(|e| e.code.clone()).unwrap_or(ErrCode::Success);
+        ValidateResult { code, errors }
+    }
+}
+
+/// 策略和报表增量同步后单个对象的结果
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncOutcome {
+    Added,
+    Edited,
+    Deleted,
+    Unchanged,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SyncItem {
+    pub target: ValidateTarget,
+    /// 北向id
+    pub id: u64,
+    pub outcome: SyncOutcome,
+}
@@ -0,0 +1,158 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::hash::Hash;
+use serde::{Deserialize, Serialize};
+
+use crate::model::datacenter::QueryDevResponseBody;
+use crate::model::north::{MyAoes, MyDffModels, MyPoints, MyTransports};
+
+/// 配置变更的来源
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum RevisionSource {
+    Http,
+    CloudEvent,
+    /// 重新应用历史版本，值为历史版本号
+    Revert(u64),
+}
+
+/// 每次配置生效后保存的版本，包括合并后的配置和南北向映射
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ConfigRevision {
+    pub version: u64,
+    /// 生效时间，毫秒
+    pub timestamp: u64,
+    pub source: RevisionSource,
+    pub points: Option<MyPoints>,
+    pub transports: Option<MyTransports>,
+    pub aoes: Option<MyAoes>,
+    pub dffs: Option<MyDffModels>,
+    pub point_mapping: HashMap<String, u64>,
+    pub dev_mapping: Vec<QueryDevResponseBody>,
+    pub aoe_mapping: HashMap<u64, u64>,
+    pub dff_mapping: HashMap<u64, u64>,
+}
+
+/// 版本列表中的摘要信息
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RevisionSummary {
+    pub version: u64,
+    pub timestamp: u64,
+    pub source: RevisionSource,
+    pub point_num: usize,
+    pub transport_num: usize,
+    pub aoe_num: usize,
+    pub dff_num: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ItemDiff {
+    pub added: Vec<String>,
+    pub edited: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+/// 两个版本之间的差异，测点按point_id，通道按dev_id，策略和报表按id
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RevisionDiff {
+    pub from: u64,
+    pub to: u64,
+    pub points: ItemDiff,
+    pub transports: ItemDiff,
+    pub aoes: ItemDiff,
+    pub dffs: ItemDiff,
+}
+
+impl ConfigRevision {
+    pub fn summary(&self) -> RevisionSummary {
+        RevisionSummary {
+            version: self.version,
+            timestamp: self.timestamp,
+            source: self.source.clone(),
+            point_num: self.points.as_ref().and_then(|v| v.points.as_ref()).map_or(0, |v| v.len()),
+            transport_num: self.transports.as_ref().and_then(|v| v.transports.as_ref()).map_or(0, |v| v.len()),
+            aoe_num: self.aoes.as_ref().and_then(|v| v.aoes.as_ref()).map_or(0, |v| v.len()),
+            dff_num: self.dffs.as_ref().and_then(|v| v.dffs.as_ref()).map_or(0, |v| v.len()),
+        }
+    }
+
+    pub fn diff(&self, to: &ConfigRevision) -> RevisionDiff {
+        let points = diff_items(
+            self.points.as_ref().and_then(|v| v.points.as_ref()),
+            to.points.as_ref().and_then(|v| v.points.as_ref()),
+            |p| p.point_id.clone(),
+        );
+        let transports = diff_items(
+            self.transports.as_ref().and_then(|v| v.transports.as_ref()),
+            to.transports.as_ref().and_then(|v| v.transports.as_ref()),
+            |t| t.dev_id(),
+        );
+        let aoes = diff_items(
+            self.aoes.as_ref().and_then(|v| v.aoes.as_ref()),
+            to.aoes.as_ref().and_then(|v| v.aoes.as_ref()),
+            |a| a.id,
+        );
+        let dffs = diff_items(
+            self.dffs.as_ref().and_then(|v| v.dffs.as_ref()),
+            to.dffs.as_ref().and_then(|v| v.dffs.as_ref()),
+            |d| d.id,
+        );
+        RevisionDiff {
+            from: self.version,
+            to: to.version,
+            points,
+            transports,
+            aoes,
+            dffs,
+        }
+    }
+}
+
+fn diff_items<T, K, F>(old: Option<&Vec<T>>, new: Option<&Vec<T>>, key: F) -> ItemDiff
+where
+    T: PartialEq,
+    K: Eq + Hash + Display,
+    F: Fn(&T) -> K,
+{
+    let empty = vec![];
+    let (old, new) = (old.unwrap_or(&empty), new.unwrap_or(&empty));
+    let old_map = old.iter().map(|item| (key(item), item)).collect::<HashMap<K, &T>>();
+    let new_keys = new.iter().map(|item| key(item)).collect::<HashSet<K>>();
+    let mut diff = ItemDiff::default();
+    for item in new {
+        let k = key(item);
+        match old_map.get(&k) {
+            Some(old_item) => {
+                if *old_item != item {
+                    diff.edited.push(k.to_string());
+                }
+            }
+            None => diff.added.push(k.to_string()),
+        }
+    }
+    for item in old {
+        let k = key(item);
+        if !new_keys.contains(&k) {
+            diff.deleted.push(k.to_string());
+        }
+    }
+    diff
+}
+
+#[test]
+fn test_diff_items() {
+    let old = vec![(1u64, "a"), (2, "b"), (3, "c")];
+    let new = vec![(1u64, "a"), (2, "bb"), (4, "d")];
+    let diff = diff_items(Some(&old), Some(&new), |item| item.0);
+    assert_eq!(diff.added, vec!["4".to_string()]);
+    assert_eq!(diff.edited, vec!["2".to_string()]);
+    assert_eq!(diff.deleted, vec!["3".to_string()]);
+    // 未变化
+    let diff = diff_items(Some(&old), Some(&old), |item| item.0);
+    assert!(diff.added.is_empty() && diff.edited.is_empty() && diff.deleted.is_empty());
+    // 一侧为空
+    let diff = diff_items(None, Some(&new), |item| item.0);
+    assert_eq!(diff.added, vec!["1".to_string(), "2".to_string(), "4".to_string()]);
+    let diff = diff_items(Some(&old), None, |item| item.0);
+    assert_eq!(diff.deleted, vec!["1".to_string(), "2".to_string(), "3".to_string()]);
+}
@@ -1,24 +1,36 @@
-use actix_web::{get, HttpResponse, web};
+use actix_multipart::form::{MultipartForm, bytes::Bytes as MpBytes};
+use actix_web::{get, post, HttpResponse, web};
+use actix_web::http::StatusCode;
 use async_channel::{bounded, Sender};
 use log::{info, warn};
+use once_cell::sync::{Lazy, OnceCell};
 use rocksdb::DB;
-use std::fs::{File, read_to_string, write};
-use std::io::{self, BufReader, Write};
-use std::collections::{HashMap, HashSet};
+use std::fs::{File, create_dir_all, read_to_string, remove_dir_all, remove_file, write};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use base64::{Engine, engine::general_purpose::STANDARD as b64_standard};
+use std::sync::RwLock;
 use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
 
 use crate::db::mydb;
+use crate::db::jobstore::JOBS;
 use crate::model::datacenter::QueryDevResponseBody;
 use crate::utils::global::{APP_API_PARAM_MAP, PARAM_POINT_MAP, POINT_PARAM_MAP};
 use crate::utils::memsmqtt::do_meter_data_query;
-use crate::{AdapterErr, ErrCode, ADAPTER_NAME};
-use crate::model::north::{AppApiParam, MyAoe, MyAoes, MyDffModel, MyDffModels, MyMeasurement, MyPoints, MyTransport, MyTransports, PointParam};
-use crate::model::{points_to_south, transports_to_south, aoes_to_south, dffs_to_south};
-use crate::utils::plccapi::{do_reset_plcc, update_points, update_transports};
-use crate::utils::memsapi::{do_apply_current_aoes, do_import_points, do_query_unrun_dffs, do_reset_mems, do_start_dff, update_aoes, update_dffs};
+use crate::{AdapterErr, ApiResponse, ErrCode, ADAPTER_NAME};
+use crate::model::north::{AppApiParam, MyAoe, MyAoes, MyDffModel, MyDffModels, MyMeasurement, MyPoints, MyTransport, MyTransports, PointParam, SyncItem, SyncOutcome, ValidateErr, ValidateResult, ValidateTarget};
+use crate::model::{resolve_last_ids, points_to_south, transports_to_south, aoes_to_south, dffs_to_south, validate_points, validate_transports, validate_aoes, validate_dffs};
+use crate::model::job::{Job, JobKind};
+use crate::model::revision::{ConfigRevision, RevisionDiff, RevisionSource, RevisionSummary};
+use crate::model::south::{AoeModel, Measurement, Transport};
+use crate::utils::plccapi::{do_reset_plcc, query_plcc_models, update_points, update_transports};
+use crate::utils::memsapi::{do_apply_current_aoes, do_import_points, do_query_unrun_dffs, do_refresh_aoes, do_refresh_dffs, do_reset_mems, do_start_dff, update_aoes, update_dffs};
 use crate::utils::plccmqtt::{do_query_dev, do_data_query, do_register_sync, build_dev_mapping};
 use crate::db::dbutils::*;
 use crate::utils::register_result;
+use crate::utils::supervisor::{spawn_task, TASK_PARSER};
 use crate::env::Env;
 
 const POINT_TREE: &str = "point";
@@ -26,23 +38,147 @@ const AOE_TREE: &str = "aoe";
 const DEV_TREE: &str = "dev";
 const DFF_TREE: &str = "dff";
 const APP_API_TREE: &str = "app_api";
+const REVISION_TREE: &str = "revision";
+// 数据库中最多保留的配置版本数量
+const MAX_REVISIONS: usize = 50;
+const ID_SEQ_TREE: &str = "id_seq";
+const PARSER_TREES: [&str; 7] = [POINT_TREE, DEV_TREE, AOE_TREE, DFF_TREE, APP_API_TREE, REVISION_TREE, ID_SEQ_TREE];
+
+static PARSER_SENDER: OnceCell<Sender<ParserOperation>> = OnceCell::new();
+// 每次操作后更新，供监控指标读取，避免等待正在执行的操作
+static PARSER_CF_SIZES: Lazy<RwLock<Vec<(String, u64)>>> = Lazy::new(|| RwLock::new(vec![]));
 
 pub const OPERATION_RECEIVE_BUFF_NUM: usize = 100;
 
 pub enum ParserOperation {
-    UpdatePlcc(Sender<u16>),
-    RecoverPlcc(Sender<u16>),
+    // 后台执行配置更新任务，结果写入任务状态
+    RunJob(u64, JobInput),
+    RecoverPlcc(Sender<Result<(), (PlccStage, AdapterErr)>>),
     GetPointMapping(Sender<HashMap<String, u64>>),
     GetDevMapping(Sender<Vec<QueryDevResponseBody>>),
     GetAoeMapping(Sender<HashMap<u64, u64>>),
     GetDffMapping(Sender<HashMap<u64, u64>>),
-    UpdateMems(Sender<u16>),
-    RecoverMems(Sender<u16>),
-    GetMeterData(Sender<String>),
+    RecoverMems(Sender<MemsUpdateResult>),
+    GetMeterData(Sender<Result<String, AdapterErr>>),
     GetAppApiMapping(Sender<Vec<AppApiParam>>),
-    StartDff(Sender<u16>),
-    // 退出数据库服务
-    Quit,
+    StartDff(Sender<Result<(), AdapterErr>>),
+    ListRevisions(Sender<Vec<RevisionSummary>>),
+    DiffRevision(u64, u64, Sender<Result<RevisionDiff, AdapterErr>>),
+    ValidatePlcc(Sender<ValidateResult>),
+    ValidateMems(Sender<ValidateResult>),
+    // 处理完之前的请求后退出数据库服务，关闭数据库后通知调用方
+    Quit(Sender<()>),
+}
+
+/// 后台任务的输入，上传的配置只随操作传递，不写入任务记录
+pub enum JobInput {
+    UpdatePlcc,
+    UpdateMems,
+    // 暂存上传的配置后执行更新，未上传的部分保持不变
+    UploadPlcc(Option<MyPoints>, Option<MyTransports>, RevisionSource),
+    UploadMems(Option<MyAoes>, Option<MyDffModels>, RevisionSource),
+    // 重新应用历史版本
+    RevertRevision(u64),
+}
+
+impl JobInput {
+    pub fn kind(&self) -> JobKind {
+        match self {
+            JobInput::UpdatePlcc => JobKind::UpdatePlcc,
+            JobInput::UpdateMems => JobKind::UpdateMems,
+            JobInput::UploadPlcc(..) => JobKind::UploadPlcc,
+            JobInput::UploadMems(..) => JobKind::UploadMems,
+            JobInput::RevertRevision(version) => JobKind::RevertRevision(*version),
+        }
+    }
+}
+
+/// 更新PLCC配置的执行阶段
+#[derive(Deserialize, Serialize, PartialEq, PartialOrd, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PlccStage {
+    Upload,
+    JoinPoints,
+    JoinTransports,
+    Snapshot,
+    Register,
+    Points,
+    Transports,
+    Reset,
+    DataQuery,
+    ImportPoints,
+    WriteResult,
+}
+
+/// 更新PLCC配置的结果
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PlccUpdateResult {
+    pub code: ErrCode,
+    pub msg: String,
+    /// 失败的阶段，成功时为空
+    pub stage: Option<PlccStage>,
+    /// 是否回滚成功，未进行回滚时为空
+    pub rollback: Option<bool>,
+}
+
+impl PlccUpdateResult {
+    fn success() -> Self {
+        PlccUpdateResult {
+            code: ErrCode::Success,
+            msg: "".to_string(),
+            stage: None,
+            rollback: None,
+        }
+    }
+
+    fn failed(stage: PlccStage, err: AdapterErr, rollback: Option<bool>) -> Self {
+        PlccUpdateResult {
+            code: err.code,
+            msg: err.msg,
+            stage: Some(stage),
+            rollback,
+        }
+    }
+}
+
+impl From<PlccUpdateResult> for ApiResponse {
+    fn from(r: PlccUpdateResult) -> Self {
+        let mut resp = ApiResponse::from_err(AdapterErr { code: r.code, msg: r.msg });
+        if let Some(stage) = r.stage {
+            resp = resp.with_stage(stage);
+        }
+        if let Some(rollback) = r.rollback {
+            resp = resp.with_details(serde_json::json!({ "rollback": rollback }));
+        }
+        resp
+    }
+}
+
+/// 更新MEMS配置的执行阶段
+#[derive(Deserialize, Serialize, PartialEq, PartialOrd, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum MemsStage {
+    Upload,
+    JoinAoes,
+    JoinDffs,
+    Aoes,
+    ApplyAoes,
+    Dffs,
+    Reset,
+    RefreshAoes,
+    RefreshDffs,
+    WriteResult,
+}
+
+/// 更新MEMS配置的结果，成功时包括每个策略和报表的同步结果
+pub type MemsUpdateResult = Result<Vec<SyncItem>, (MemsStage, AdapterErr)>;
+
+/// 更新PLCC前的快照，包括PLCC中的模型、本地映射和结果文件
+struct PlccSnapshot {
+    points: Vec<Measurement>,
+    transports: Vec<Transport>,
+    trees: Vec<(&'static str, Vec<Vec<u8>>, Vec<Vec<u8>>)>,
+    result_files: Vec<(String, Option<String>)>,
 }
 
 #[derive(Deserialize, Serialize, PartialEq, Debug, Clone)]
@@ -76,8 +212,7 @@ impl ParserManager {
         let mut opts = rocksdb::Options::default();
         opts.create_if_missing(true);
         opts.create_missing_column_families(true);
-        let cfs = [POINT_TREE, DEV_TREE, AOE_TREE, DFF_TREE, APP_API_TREE];
-        if let Ok(inner_db) = DB::open_cf(&opts, file_path, cfs) {
+        if let Ok(inner_db) = DB::open_cf(&opts, file_path, PARSER_TREES) {
             Some(ParserManager { inner_db })
         } else {
             mydb::add_error_db_path(file_path);
@@ -86,60 +221,74 @@ impl ParserManager {
         }
     }
 
+    // 只读打开，不占用数据库锁，服务运行时也可以使用
+    fn open_read_only(file_path: &str) -> Result<ParserManager, AdapterErr> {
+        let opts = rocksdb::Options::default();
+        DB::open_cf_for_read_only(&opts, file_path, PARSER_TREES, false)
+            .map(|inner_db| ParserManager { inner_db })
+            .map_err(|e| AdapterErr {
+                code: ErrCode::InternalErr,
+                msg: format!("打开数据库{file_path}失败：{e}"),
+            })
+    }
+
     async fn do_operation(&self, op: ParserOperation) {
         let env = Env::get_env(ADAPTER_NAME);
         let json_dir = env.get_json_dir();
+        let upload_dir = env.get_upload_dir();
         let result_dir = env.get_result_dir();
         let point_dir = env.get_point_dir();
         let transport_dir = env.get_transport_dir();
         let aoe_dir = env.get_aoe_dir();
         let dff_dir = env.get_dff_dir();
         match op {
-            ParserOperation::UpdatePlcc(sender) => {
-                let temp_prefix = "temp_";
-                let (temp_point_dir, temp_transport_dir) = (format!("{temp_prefix}{point_dir}"), format!("{temp_prefix}{transport_dir}"));
-                let mut result_code = if let Err(e) = self.join_points_json(&json_dir, &result_dir, &point_dir, &temp_point_dir).await {
-                    log::warn!("{}", e.msg);
-                    e.code
-                } else {
-                    ErrCode::Success
-                };
-                if result_code == ErrCode::Success {
-                    result_code = if let Err(e) = self.join_transports_json(&json_dir, &result_dir, &transport_dir, &temp_transport_dir).await {
-                        log::warn!("{}", e.msg);
-                        e.code
-                    } else {
-                        ErrCode::Success
-                    };
-                }
-                if result_code == ErrCode::Success {
-                    result_code = if let Err(e) = self.start_plcc_parser(&json_dir, &temp_point_dir, &temp_transport_dir, true).await {
-                        log::warn!("{}", e.msg);
-                        e.code
-                    } else {
-                        if let Err(e) = self.write_into_result_plcc(
-                            &json_dir, &point_dir, &transport_dir,
-                            &result_dir, &temp_point_dir, &temp_transport_dir,
-                        ) {
-                            log::warn!("配置编排解析成功，但将结果写入文件时报错：{e:?}");
-                            ErrCode::IoErr
-                        } else {
-                            ErrCode::Success
+            ParserOperation::RunJob(id, input) => {
+                JOBS.start(id);
+                let resp = match input {
+                    JobInput::UpdatePlcc => self.do_update_plcc(&json_dir, &result_dir, &point_dir, &transport_dir, RevisionSource::Http).await.into(),
+                    JobInput::UpdateMems => mems_api_response(self.do_update_mems(&json_dir, &result_dir, &aoe_dir, &dff_dir, RevisionSource::Http).await),
+                    JobInput::UploadPlcc(points, transports, source) => {
+                        match self.stage_plcc(&upload_dir, &point_dir, &transport_dir, points, transports) {
+                            Ok(()) => {
+                                let result = self.do_update_plcc(&upload_dir, &result_dir, &point_dir, &transport_dir, source).await;
+                                if result.code == ErrCode::Success {
+                                    clear_upload_dir(&upload_dir);
+                                }
+                                result.into()
+                            }
+                            Err(e) => {
+                                log::warn!("{}", e.msg);
+                                PlccUpdateResult::failed(PlccStage::Upload, e, None).into()
+                            }
                         }
-                    };
-                }
-                if let Err(e) = sender.send(result_code as u16).await {
-                    warn!("!!Failed to send update json : {e:?}");
-                }
+                    }
+                    JobInput::UploadMems(aoes, dffs, source) => {
+                        match self.stage_mems(&upload_dir, &aoe_dir, &dff_dir, aoes, dffs) {
+                            Ok(()) => {
+                                let result = self.do_update_mems(&upload_dir, &result_dir, &aoe_dir, &dff_dir, source).await;
+                                if result.is_ok() {
+                                    clear_upload_dir(&upload_dir);
+                                }
+                                mems_api_response(result)
+                            }
+                            Err(e) => {
+                                log::warn!("{}", e.msg);
+                                mems_api_response(Err((MemsStage::Upload, e)))
+                            }
+                        }
+                    }
+                    JobInput::RevertRevision(version) => {
+                        self.do_revert_revision(version, &result_dir, &point_dir, &transport_dir, &aoe_dir, &dff_dir).await
+                    }
+                };
+                JOBS.finish(id, resp);
             }
             ParserOperation::RecoverPlcc(sender) => {
-                let result_code = if let Err(e) = self.start_plcc_parser(&result_dir, &point_dir, &transport_dir, false).await {
+                let result = self.start_plcc_parser(&result_dir, &point_dir, &transport_dir, false).await;
+                if let Err((_, e)) = &result {
                     log::warn!("{}", e.msg);
-                    e.code
-                } else {
-                    ErrCode::Success
-                };
-                if let Err(e) = sender.send(result_code as u16).await {
+                }
+                if let Err(e) = sender.send(result).await {
                     warn!("!!Failed to send recover json : {e:?}");
                 }
             }
@@ -158,62 +307,21 @@ impl ParserManager {
                     warn!("!!Failed to send get dev_mapping : {e:?}");
                 }
             }
-            ParserOperation::UpdateMems(sender) => {
-                let temp_prefix = "temp_";
-                let (temp_aoe_dir, temp_dff_dir) = (format!("{temp_prefix}{aoe_dir}"), format!("{temp_prefix}{dff_dir}"));
-                let mut result_code = if let Err(e) = self.join_aoes_json(&json_dir, &result_dir, &aoe_dir, &temp_aoe_dir).await {
-                    log::warn!("{}", e.msg);
-                    e.code
-                } else {
-                    ErrCode::Success
-                };
-                if result_code == ErrCode::Success {
-                    result_code = if let Err(e) = self.join_dffs_json(&json_dir, &result_dir, &dff_dir, &temp_dff_dir).await {
-                        log::warn!("{}", e.msg);
-                        e.code
-                    } else {
-                        ErrCode::Success
-                    };
-                }
-                if result_code == ErrCode::Success {
-                    result_code = if let Err(e) = self.start_mems_parser(&json_dir, &temp_aoe_dir, &temp_dff_dir).await {
-                        log::warn!("{}", e.msg);
-                        e.code
-                    } else {
-                        if let Err(e) = self.write_into_result_mems(
-                            &json_dir, &aoe_dir, &dff_dir, 
-                            &result_dir, &temp_aoe_dir, &temp_dff_dir,
-                        ) {
-                            log::warn!("报表解析成功，但将结果写入文件时报错：{e:?}");
-                            ErrCode::IoErr
-                        } else {
-                            ErrCode::Success
-                        }
-                    };
-                }
-                if let Err(e) = sender.send(result_code as u16).await {
-                    warn!("!!Failed to send update dff : {e:?}");
-                }
-            }
             ParserOperation::RecoverMems(sender) => {
-                let result_code = if let Err(e) = self.start_mems_parser(&result_dir, &aoe_dir, &dff_dir).await {
+                let result = self.start_mems_parser(&result_dir, &aoe_dir, &dff_dir).await;
+                if let Err((_, e)) = &result {
                     log::warn!("{}", e.msg);
-                    e.code
-                } else {
-                    ErrCode::Success
-                };
-                if let Err(e) = sender.send(result_code as u16).await {
+                }
+                if let Err(e) = sender.send(result).await {
                     warn!("!!Failed to send recover dff : {e:?}");
                 }
             }
             ParserOperation::StartDff(sender) => {
-                let result_code = if let Err(e) = do_start_dff().await {
+                let result = do_start_dff().await;
+                if let Err(e) = &result {
                     log::warn!("{}", e.msg);
-                    e.code
-                } else {
-                    ErrCode::Success
-                };
-                if let Err(e) = sender.send(result_code as u16).await {
+                }
+                if let Err(e) = sender.send(result).await {
                     warn!("!!Failed to send start dff : {e:?}");
                 }
             }
@@ -223,10 +331,7 @@ impl ParserManager {
                 }
             }
             ParserOperation::GetMeterData(sender) => {
-                let meter_data = match do_meter_data_query().await {
-                    Ok(v) => v,
-                    Err(_) => "".to_string()
-                };
+                let meter_data = do_meter_data_query().await;
                 if let Err(e) = sender.send(meter_data).await {
                     warn!("!!Failed to send get history_data : {e:?}");
                 }
@@ -236,14 +341,393 @@ impl ParserManager {
                     warn!("!!Failed to send get app_api_mapping : {e:?}");
                 }
             }
-            ParserOperation::Quit => {}
+            ParserOperation::ListRevisions(sender) => {
+                let revisions = query_values_cbor_with_tree_name::<ConfigRevision>(&self.inner_db, REVISION_TREE)
+                    .iter()
+                    .map(|r| r.summary())
+                    .collect::<Vec<RevisionSummary>>();
+                if let Err(e) = sender.send(revisions).await {
+                    warn!("!!Failed to send list revisions : {e:?}");
+                }
+            }
+            ParserOperation::DiffRevision(from, to, sender) => {
+                let result = self.query_revision(from)
+                    .and_then(|from| self.query_revision(to).map(|to| from.diff(&to)));
+                if let Err(e) = sender.send(result).await {
+                    warn!("!!Failed to send diff revision : {e:?}");
+                }
+            }
+            ParserOperation::ValidatePlcc(sender) => {
+                let result = self.do_validate_plcc(&json_dir, &result_dir, &point_dir, &transport_dir).await;
+                if let Err(e) = sender.send(result).await {
+                    warn!("!!Failed to send validate plcc : {e:?}");
+                }
+            }
+            ParserOperation::ValidateMems(sender) => {
+                let result = self.do_validate_mems(&json_dir, &result_dir, &aoe_dir, &dff_dir);
+                if let Err(e) = sender.send(result).await {
+                    warn!("!!Failed to send validate mems : {e:?}");
+                }
+            }
+            ParserOperation::Quit(_) => {}
+        }
+    }
+
+    async fn do_update_plcc(&self, json_dir: &str, result_dir: &str, point_dir: &str, transport_dir: &str, source: RevisionSource) -> PlccUpdateResult {
+        let temp_prefix = "temp_";
+        let (temp_point_dir, temp_transport_dir) = (format!("{temp_prefix}{point_dir}"), format!("{temp_prefix}{transport_dir}"));
+        JOBS.set_stage(PlccStage::JoinPoints);
+        if let Err(e) = self.join_points_json(json_dir, result_dir, point_dir, &temp_point_dir).await {
+            log::warn!("{}", e.msg);
+            return PlccUpdateResult::failed(PlccStage::JoinPoints, e, None);
+        }
+        JOBS.set_stage(PlccStage::JoinTransports);
+        if let Err(e) = self.join_transports_json(json_dir, result_dir, transport_dir, &temp_transport_dir).await {
+            log::warn!("{}", e.msg);
+            return PlccUpdateResult::failed(PlccStage::JoinTransports, e, None);
+        }
+        // 记录更新前的状态，任一步骤失败时回滚
+        let result_files = vec![format!("{result_dir}/{point_dir}"), format!("{result_dir}/{transport_dir}")];
+        JOBS.set_stage(PlccStage::Snapshot);
+        let snapshot = match self.take_plcc_snapshot(result_files).await {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                log::warn!("{}", e.msg);
+                return PlccUpdateResult::failed(PlccStage::Snapshot, e, None);
+            }
+        };
+        let failed = match self.start_plcc_parser(json_dir, &temp_point_dir, &temp_transport_dir, true).await {
+            Ok(()) => {
+                JOBS.set_stage(PlccStage::WriteResult);
+                if let Err(e) = self.write_into_result_plcc(
+                    json_dir, point_dir, transport_dir,
+                    result_dir, &temp_point_dir, &temp_transport_dir,
+                ) {
+                    log::warn!("配置编排解析成功，但将结果写入文件时报错：{e:?}");
+                    Some((PlccStage::WriteResult, AdapterErr {
+                        code: ErrCode::IoErr,
+                        msg: format!("将结果写入文件失败：{e}"),
+                    }))
+                } else {
+                    None
+                }
+            }
+            Err((stage, e)) => {
+                log::warn!("{}", e.msg);
+                Some((stage, e))
+            }
+        };
+        match failed {
+            None => {
+                self.save_revision(source);
+                PlccUpdateResult::success()
+            }
+            Some((stage, e)) => {
+                log::warn!("更新PLCC配置在{stage:?}阶段失败，开始回滚");
+                let rollback = self.rollback_plcc(snapshot, stage).await;
+                if rollback {
+                    log::info!("回滚PLCC配置成功");
+                } else {
+                    log::error!("回滚PLCC配置失败");
+                }
+                PlccUpdateResult::failed(stage, e, Some(rollback))
+            }
+        }
+    }
+
+    async fn take_plcc_snapshot(&self, result_files: Vec<String>) -> Result<PlccSnapshot, AdapterErr> {
+        let (points, transports) = query_plcc_models().await?;
+        let trees = [POINT_TREE, DEV_TREE, APP_API_TREE].into_iter().map(|tree| {
+            let (keys, values) = query_kv_with_tree_name(&self.inner_db, tree);
+            (tree, keys, values)
+        }).collect();
+        let result_files = snapshot_files(result_files);
+        Ok(PlccSnapshot { points, transports, trees, result_files })
+    }
+
+    // stage为失败时所在的阶段，未到下发测点阶段时PLCC中的模型没有变化，不需要恢复；
+    // 否则与更新相同按差异恢复，只改动本次更新中变化的测点和通道，已经执行过reset的，回滚后需要再次reset
+    async fn rollback_plcc(&self, snapshot: PlccSnapshot, stage: PlccStage) -> bool {
+        let mut is_ok = true;
+        if stage >= PlccStage::Points {
+            let restored = match update_points(snapshot.points).await {
+                Ok(_) => update_transports(snapshot.transports).await.map(|_| ()),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = restored {
+                log::error!("!!Failed to restore plcc models: {}", e.msg);
+                is_ok = false;
+            }
+        }
+        for (tree, keys, values) in &snapshot.trees {
+            let (current_keys, _) = query_kv_with_tree_name(&self.inner_db, tree);
+            if !delete_items_by_keys_with_tree_name(&self.inner_db, tree, current_keys)
+                || !save_items_to_db_with_tree_name(&self.inner_db, tree, keys, values) {
+                log::error!("!!Failed to restore {tree} mapping");
+                is_ok = false;
+            }
+        }
+        self.reload_global_maps();
+        if !restore_files(&snapshot.result_files) {
+            is_ok = false;
+        }
+        if is_ok && stage >= PlccStage::Reset {
+            if let Err(e) = do_reset_plcc().await {
+                log::error!("!!Failed to reset plcc after rollback: {}", e.msg);
+                is_ok = false;
+            } else if let Err(e) = do_data_query().await {
+                log::error!("!!Failed to query data after rollback: {}", e.msg);
+                is_ok = false;
+            }
+        }
+        is_ok
+    }
+
+    // 恢复MEMS结果文件并重新下发，原文件不存在时无法恢复MEMS中的配置
+    async fn rollback_mems(&self, old_files: &[(String, Option<String>)], result_dir: &str, aoe_dir: &str, dff_dir: &str) -> bool {
+        if !restore_files(old_files) {
+            return false;
+        }
+        if old_files.iter().any(|(_, content)| content.is_none()) {
+            log::error!("!!No mems result files before revert, mems config is not restored");
+            return false;
+        }
+        match self.start_mems_parser(result_dir, aoe_dir, dff_dir).await {
+            Ok(_) => {
+                log::info!("回滚MEMS配置成功");
+                true
+            }
+            Err((stage, e)) => {
+                log::error!("!!Failed to restore mems at {stage:?}: {}", e.msg);
+                false
+            }
+        }
+    }
+
+    // 根据数据库中的映射刷新全局变量
+    fn reload_global_maps(&self) {
+        let points_mapping = self.query_point_mapping();
+        let point_param_map = points_mapping.iter().map(|(k, v)| (*v, k.clone())).collect::<HashMap<u64, String>>();
+        let app_api_param_map = self.query_app_api_mapping().into_iter()
+            .map(|item| (item.point_id, item))
+            .collect::<HashMap<u64, AppApiParam>>();
+        PARAM_POINT_MAP.save_all(points_mapping);
+        POINT_PARAM_MAP.save_all(point_param_map);
+        APP_API_PARAM_MAP.save_all(app_api_param_map);
+    }
+
+    // 写入单独的上传目录，不覆盖jsonFileDir中的文件；未上传的部分在上传目录中写入空的增量，合并后保持已生效的配置
+    fn stage_plcc(&self, upload_dir: &str, point_dir: &str, transport_dir: &str, points: Option<MyPoints>, transports: Option<MyTransports>) -> Result<(), AdapterErr> {
+        prepare_upload_dir(upload_dir)?;
+        let points = points.unwrap_or(MyPoints { points: None, add: None, edit: None, delete: Some(vec![]) });
+        let transports = transports.unwrap_or(MyTransports { transports: None, add: None, edit: None, delete: Some(vec![]) });
+        stage_json(&format!("{upload_dir}/{point_dir}"), &points)?;
+        stage_json(&format!("{upload_dir}/{transport_dir}"), &transports)
+    }
+
+    fn stage_mems(&self, upload_dir: &str, aoe_dir: &str, dff_dir: &str, aoes: Option<MyAoes>, dffs: Option<MyDffModels>) -> Result<(), AdapterErr> {
+        prepare_upload_dir(upload_dir)?;
+        let aoes = aoes.unwrap_or(MyAoes { aoes: None, add: None, edit: None, delete: Some(vec![]) });
+        let dffs = dffs.unwrap_or(MyDffModels { dffs: None, add: None, edit: None, delete: Some(vec![]) });
+        stage_json(&format!("{upload_dir}/{aoe_dir}"), &aoes)?;
+        stage_json(&format!("{upload_dir}/{dff_dir}"), &dffs)
+    }
+
+    async fn do_update_mems(&self, json_dir: &str, result_dir: &str, aoe_dir: &str, dff_dir: &str, source: RevisionSource) -> MemsUpdateResult {
+        let temp_prefix = "temp_";
+        let (temp_aoe_dir, temp_dff_dir) = (format!("{temp_prefix}{aoe_dir}"), format!("{temp_prefix}{dff_dir}"));
+        let result = async {
+            JOBS.set_stage(MemsStage::JoinAoes);
+            self.join_aoes_json(json_dir, result_dir, aoe_dir, &temp_aoe_dir).await
+                .map_err(|e| (MemsStage::JoinAoes, e))?;
+            JOBS.set_stage(MemsStage::JoinDffs);
+            self.join_dffs_json(json_dir, result_dir, dff_dir, &temp_dff_dir).await
+                .map_err(|e| (MemsStage::JoinDffs, e))?;
+            let items = self.start_mems_parser(json_dir, &temp_aoe_dir, &temp_dff_dir).await?;
+            JOBS.set_stage(MemsStage::WriteResult);
+            self.write_into_result_mems(
+                json_dir, aoe_dir, dff_dir,
+                result_dir, &temp_aoe_dir, &temp_dff_dir,
+            ).map_err(|e| {
+                log::warn!("报表解析成功，但将结果写入文件时报错：{e:?}");
+                (MemsStage::WriteResult, AdapterErr {
+                    code: ErrCode::IoErr,
+                    msg: format!("将结果写入文件失败：{e}"),
+                })
+            })?;
+            Ok(items)
+        }.await;
+        match &result {
+            Ok(_) => {
+                self.save_revision(source);
+            }
+            Err((_, e)) => log::warn!("{}", e.msg),
+        }
+        result
+    }
+
+    // 保存当前生效的配置为新版本，返回版本号
+    fn save_revision(&self, source: RevisionSource) -> Option<u64> {
+        let env = Env::get_env(ADAPTER_NAME);
+        let result_dir = env.get_result_dir();
+        let version = query_end_key_with_tree_name_as_u64(&self.inner_db, REVISION_TREE).map_or(1, |v| v + 1);
+        let revision = ConfigRevision {
+            version,
+            timestamp: chrono::Local::now().timestamp_millis() as u64,
+            source,
+            points: read_result_json(&format!("{result_dir}/{}", env.get_point_dir())),
+            transports: read_result_json(&format!("{result_dir}/{}", env.get_transport_dir())),
+            aoes: read_result_json(&format!("{result_dir}/{}", env.get_aoe_dir())),
+            dffs: read_result_json(&format!("{result_dir}/{}", env.get_dff_dir())),
+            point_mapping: self.query_point_mapping(),
+            dev_mapping: self.query_dev_mapping(),
+            aoe_mapping: self.query_aoe_mapping(),
+            dff_mapping: self.query_dff_mapping(),
+        };
+        if save_item_cbor_to_db_with_tree_name(&self.inner_db, REVISION_TREE, &revision, |r| {
+            r.version.to_be_bytes().to_vec()
+        }) {
+            info!("insert revision {version} success");
+            self.trim_revisions();
+            Some(version)
+        } else {
+            warn!("!!Failed to insert revision {version}");
+            None
+        }
+    }
+
+    // 清理最早的版本
+    fn trim_revisions(&self) {
+        let versions = query_keys_with_tree_name_as_u64(&self.inner_db, REVISION_TREE);
+        if versions.len() > MAX_REVISIONS {
+            let keys = versions[..versions.len() - MAX_REVISIONS].iter()
+                .map(|v| v.to_be_bytes().to_vec())
+                .collect::<Vec<Vec<u8>>>();
+            if !delete_items_by_keys_with_tree_name(&self.inner_db, REVISION_TREE, keys) {
+                warn!("!!Failed to delete old revisions");
+            }
+        }
+    }
+
+    fn query_revision(&self, version: u64) -> Result<ConfigRevision, AdapterErr> {
+        query_value_cbor_by_key_with_tree_name(&self.inner_db, REVISION_TREE, version.to_be_bytes())
+            .ok_or_else(|| AdapterErr {
+                code: ErrCode::RevisionNotFound,
+                msg: format!("配置版本{version}不存在"),
+            })
+    }
+
+    // 将历史版本写入结果文件，再按恢复流程重新解析并下发
+    // MEMS失败时先回滚本次已下发的PLCC，再按原结果文件重新下发MEMS，回滚结果写入details
+    async fn do_revert_revision(&self, version: u64, result_dir: &str, point_dir: &str, transport_dir: &str, aoe_dir: &str, dff_dir: &str) -> ApiResponse {
+        let revision = match self.query_revision(version) {
+            Ok(revision) => revision,
+            Err(e) => return ApiResponse::from_err(e),
+        };
+        let mut plcc_snapshot = None;
+        if let (Some(points), Some(transports)) = (&revision.points, &revision.transports) {
+            let (point_file, transport_file) = (format!("{result_dir}/{point_dir}"), format!("{result_dir}/{transport_dir}"));
+            let snapshot = match self.take_plcc_snapshot(vec![point_file.clone(), transport_file.clone()]).await {
+                Ok(snapshot) => snapshot,
+                Err(e) => return ApiResponse::from_err(e).with_stage(PlccStage::Snapshot),
+            };
+            let result = match stage_json(&point_file, points).and_then(|_| stage_json(&transport_file, transports)) {
+                Ok(()) => self.start_plcc_parser(result_dir, point_dir, transport_dir, true).await,
+                Err(e) => Err((PlccStage::Upload, e)),
+            };
+            if let Err((stage, e)) = result {
+                log::warn!("{}", e.msg);
+                let rollback = self.rollback_plcc(snapshot, stage).await;
+                return PlccUpdateResult::failed(stage, e, Some(rollback)).into();
+            }
+            plcc_snapshot = Some(snapshot);
+        }
+        if let (Some(aoes), Some(dffs)) = (&revision.aoes, &revision.dffs) {
+            let (aoe_file, dff_file) = (format!("{result_dir}/{aoe_dir}"), format!("{result_dir}/{dff_dir}"));
+            let old_files = snapshot_files(vec![aoe_file.clone(), dff_file.clone()]);
+            let result = match stage_json(&aoe_file, aoes).and_then(|_| stage_json(&dff_file, dffs)) {
+                Ok(()) => self.start_mems_parser(result_dir, aoe_dir, dff_dir).await,
+                Err(e) => Err((MemsStage::Upload, e)),
+            };
+            if let Err((stage, e)) = result {
+                log::warn!("{}", e.msg);
+                // PLCC先回滚，恢复原测点映射后再重新下发原MEMS配置
+                let plcc_rollback = match plcc_snapshot {
+                    // PLCC已全部生效
+                    Some(snapshot) => Some(self.rollback_plcc(snapshot, PlccStage::WriteResult).await),
+                    None => None,
+                };
+                let mems_rollback = self.rollback_mems(&old_files, result_dir, aoe_dir, dff_dir).await;
+                return ApiResponse::from_err(e).with_stage(stage).with_details(serde_json::json!({
+                    "plcc_rollback": plcc_rollback,
+                    "mems_rollback": mems_rollback,
+                }));
+            }
+        }
+        let new_version = self.save_revision(RevisionSource::Revert(version));
+        ApiResponse::success().with_details(serde_json::json!({ "version": new_version }))
+    }
+
+    // 只做合并和转换校验，不调用PLCC接口，也不写数据库
+    async fn do_validate_plcc(&self, json_dir: &str, result_dir: &str, point_dir: &str, transport_dir: &str) -> ValidateResult {
+        let mut errors = vec![];
+        let points = self.merge_points_json(json_dir, result_dir, point_dir)
+            .map_err(|e| errors.push(ValidateErr::new(ValidateTarget::Point, None, e)))
+            .ok();
+        let transports = self.merge_transports_json(json_dir, result_dir, transport_dir)
+            .map_err(|e| errors.push(ValidateErr::new(ValidateTarget::Transport, None, e)))
+            .ok();
+        let Some(points) = points else {
+            return ValidateResult::new(errors);
+        };
+        let (points_mapping, point_errors) = validate_points(&points);
+        errors.extend(point_errors);
+        let Some(transports) = transports else {
+            return ValidateResult::new(errors);
+        };
+        // 通道中的测点属性需要到数据中心查询
+        let dev_mapping = if matches!(&transports.transports, Some(t) if !t.is_empty()) {
+            match query_dev(&transports).await {
+                Ok(devs) => Some(build_dev_mapping(&devs)),
+                Err(e) => {
+                    errors.push(ValidateErr::new(ValidateTarget::Transport, None, e));
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        // 校验与下发使用同一套转换逻辑
+        errors.extend(validate_transports(&transports, &points_mapping, dev_mapping.as_ref()));
+        ValidateResult::new(errors)
+    }
+
+    fn do_validate_mems(&self, json_dir: &str, result_dir: &str, aoe_dir: &str, dff_dir: &str) -> ValidateResult {
+        let mut errors = vec![];
+        let points_mapping = self.query_point_mapping();
+        match self.merge_aoes_json(json_dir, result_dir, aoe_dir) {
+            Ok(aoes) => errors.extend(validate_aoes(&aoes, &points_mapping)),
+            Err(e) => errors.push(ValidateErr::new(ValidateTarget::Aoe, None, e)),
+        }
+        match self.merge_dffs_json(json_dir, result_dir, dff_dir) {
+            Ok(dffs) => errors.extend(validate_dffs(&dffs, &points_mapping)),
+            Err(e) => errors.push(ValidateErr::new(ValidateTarget::Dff, None, e)),
         }
+        ValidateResult::new(errors)
     }
 
     async fn join_points_json(&self, parser_path: &str, result_path: &str, point_dir: &str, temp_point_dir: &str) -> Result<(), AdapterErr> {
+        let old_points = self.merge_points_json(parser_path, result_path, point_dir)?;
+        let temp_name_points = format!("{parser_path}/{temp_point_dir}");
+        let mut points_file = File::create(&temp_name_points).unwrap();
+        points_file.write_all(serde_json::to_string(&old_points).unwrap().as_bytes()).unwrap();
+        Ok(())
+    }
+
+    // 将本次下发的配置与已生效的配置合并
+    fn merge_points_json(&self, parser_path: &str, result_path: &str, point_dir: &str) -> Result<MyPoints, AdapterErr> {
         let file_name_points = format!("{parser_path}/{point_dir}");
         let result_name_points = format!("{result_path}/{point_dir}");
-        let temp_name_points = format!("{parser_path}/{temp_point_dir}");
         if let Ok(file) = File::open(&file_name_points) {
             let reader = BufReader::new(file);
             match serde_json::from_reader::<_, MyPoints>(reader) {
@@ -298,9 +782,7 @@ impl ParserManager {
                             }
                         }
                     }
-                    let mut points_file = File::create(&temp_name_points).unwrap();
-                    points_file.write_all(serde_json::to_string(&old_points).unwrap().as_bytes()).unwrap();
-                    Ok(())
+                    Ok(old_points)
                 },
                 Err(err) => {
                     Err(AdapterErr {
@@ -318,9 +800,16 @@ impl ParserManager {
     }
 
     async fn join_transports_json(&self, parser_path: &str, result_path: &str, transport_dir: &str, temp_transport_dir: &str) -> Result<(), AdapterErr> {
+        let old_transports = self.merge_transports_json(parser_path, result_path, transport_dir)?;
+        let temp_name_transports = format!("{parser_path}/{temp_transport_dir}");
+        let mut transports_file = File::create(&temp_name_transports).unwrap();
+        transports_file.write_all(serde_json::to_string(&old_transports).unwrap().as_bytes()).unwrap();
+        Ok(())
+    }
+
+    fn merge_transports_json(&self, parser_path: &str, result_path: &str, transport_dir: &str) -> Result<MyTransports, AdapterErr> {
         let file_name_transports = format!("{parser_path}/{transport_dir}");
         let result_name_transports = format!("{result_path}/{transport_dir}");
-        let temp_name_transports = format!("{parser_path}/{temp_transport_dir}");
         if let Ok(file) = File::open(&file_name_transports) {
             let reader = BufReader::new(file);
             match serde_json::from_reader::<_, MyTransports>(reader) {
@@ -383,9 +872,7 @@ impl ParserManager {
                             }
                         }
                     }
-                    let mut transports_file = File::create(&temp_name_transports).unwrap();
-                    transports_file.write_all(serde_json::to_string(&old_transports).unwrap().as_bytes()).unwrap();
-                    Ok(())
+                    Ok(old_transports)
                 },
                 Err(err) => {
                     Err(AdapterErr {
@@ -403,9 +890,16 @@ impl ParserManager {
     }
 
     async fn join_aoes_json(&self, parser_path: &str, result_path: &str, aoe_dir: &str, temp_aoe_dir: &str) -> Result<(), AdapterErr> {
+        let old_aoes = self.merge_aoes_json(parser_path, result_path, aoe_dir)?;
+        let temp_name_aoes = format!("{parser_path}/{temp_aoe_dir}");
+        let mut aoes_file = File::create(&temp_name_aoes).unwrap();
+        aoes_file.write_all(serde_json::to_string(&old_aoes).unwrap().as_bytes()).unwrap();
+        Ok(())
+    }
+
+    fn merge_aoes_json(&self, parser_path: &str, result_path: &str, aoe_dir: &str) -> Result<MyAoes, AdapterErr> {
         let file_name_aoes = format!("{parser_path}/{aoe_dir}");
         let result_name_aoes = format!("{result_path}/{aoe_dir}");
-        let temp_name_aoes = format!("{parser_path}/{temp_aoe_dir}");
         if let Ok(file) = File::open(&file_name_aoes) {
             let reader = BufReader::new(file);
             match serde_json::from_reader::<_, MyAoes>(reader) {
@@ -462,9 +956,7 @@ impl ParserManager {
                             }
                         }
                     }
-                    let mut aoes_file = File::create(&temp_name_aoes).unwrap();
-                    aoes_file.write_all(serde_json::to_string(&old_aoes).unwrap().as_bytes()).unwrap();
-                    Ok(())
+                    Ok(old_aoes)
                 },
                 Err(err) => {
                     Err(AdapterErr {
@@ -482,9 +974,16 @@ impl ParserManager {
     }
 
     async fn join_dffs_json(&self, parser_path: &str, result_path: &str, dff_dir: &str, temp_dff_dir: &str) -> Result<(), AdapterErr> {
+        let old_dffs = self.merge_dffs_json(parser_path, result_path, dff_dir)?;
+        let temp_name_dffs = format!("{parser_path}/{temp_dff_dir}");
+        let mut dffs_file = File::create(&temp_name_dffs).unwrap();
+        dffs_file.write_all(serde_json::to_string(&old_dffs).unwrap().as_bytes()).unwrap();
+        Ok(())
+    }
+
+    fn merge_dffs_json(&self, parser_path: &str, result_path: &str, dff_dir: &str) -> Result<MyDffModels, AdapterErr> {
         let file_name_dffs = format!("{parser_path}/{dff_dir}");
         let result_name_dffs = format!("{result_path}/{dff_dir}");
-        let temp_name_dffs = format!("{parser_path}/{temp_dff_dir}");
         if let Ok(file) = File::open(&file_name_dffs) {
             let reader = BufReader::new(file);
             match serde_json::from_reader::<_, MyDffModels>(reader) {
@@ -541,9 +1040,7 @@ impl ParserManager {
                             }
                         }
                     }
-                    let mut dffs_file = File::create(&temp_name_dffs).unwrap();
-                    dffs_file.write_all(serde_json::to_string(&old_dffs).unwrap().as_bytes()).unwrap();
-                    Ok(())
+                    Ok(old_dffs)
                 },
                 Err(err) => {
                     Err(AdapterErr {
@@ -592,19 +1089,22 @@ impl ParserManager {
         Ok(())
     }
 
-    async fn start_plcc_parser(&self, path: &str, point_dir: &str, transport_dir: &str, need_reset: bool) -> Result<(), AdapterErr> {
+    async fn start_plcc_parser(&self, path: &str, point_dir: &str, transport_dir: &str, need_reset: bool) -> Result<(), (PlccStage, AdapterErr)> {
         let file_name_points = format!("{path}/{point_dir}");
         let file_name_transports = format!("{path}/{transport_dir}");
         let old_point_mapping = self.query_point_mapping();
 
         if !register_result::get_result() {
             log::info!("start do register");
-            do_register_sync().await?;
+            JOBS.set_stage(PlccStage::Register);
+            do_register_sync().await.map_err(|e| (PlccStage::Register, e))?;
             log::info!("end do register");
         }
 
         log::info!("start parse point.json");
-        let (points_mapping, point_param, point_discrete, app_api_params) = self.parse_points(file_name_points, &old_point_mapping).await?;
+        JOBS.set_stage(PlccStage::Points);
+        let (points_mapping, point_param, point_discrete, app_api_params, points_changed) = self.parse_points(file_name_points, &old_point_mapping).await
+            .map_err(|e| (PlccStage::Points, e))?;
         // 保存到全局变量中
         let point_param_map = points_mapping.iter().map(|(k, v)| (*v, k.clone())).collect::<HashMap<u64, String>>();
         let mut app_api_param_map = HashMap::with_capacity(app_api_params.len());
@@ -617,27 +1117,34 @@ impl ParserManager {
         log::info!("end parse point.json");
 
         log::info!("start parse transports.json");
-        let _ = self.parse_transports(file_name_transports, &points_mapping, &point_param, &point_discrete).await?;
+        JOBS.set_stage(PlccStage::Transports);
+        let transports_changed = self.parse_transports(file_name_transports, &points_mapping, &point_param, &point_discrete).await
+            .map_err(|e| (PlccStage::Transports, e))?;
         log::info!("end parse transports.json");
 
-        if need_reset {
+        // 只修改了名称、描述等字段时不需要reset
+        if need_reset && (points_changed || transports_changed) {
             log::info!("start do plcc reset");
-            let _ = do_reset_plcc().await?;
+            JOBS.set_stage(PlccStage::Reset);
+            let _ = do_reset_plcc().await.map_err(|e| (PlccStage::Reset, e))?;
             log::info!("end do plcc reset");
     
             log::info!("start do query_data mqtt");
-            let _ = do_data_query().await?;
+            JOBS.set_stage(PlccStage::DataQuery);
+            let _ = do_data_query().await.map_err(|e| (PlccStage::DataQuery, e))?;
             log::info!("end do query_data mqtt");
     
             log::info!("start do import_points into mems");
-            let _ = do_import_points(point_param_map.keys().cloned().collect::<Vec<u64>>()).await?;
+            JOBS.set_stage(PlccStage::ImportPoints);
+            let _ = do_import_points(point_param_map.keys().cloned().collect::<Vec<u64>>()).await
+                .map_err(|e| (PlccStage::ImportPoints, e))?;
             log::info!("end do import_points into mems");
         }
 
         Ok(())
     }
 
-    async fn parse_points(&self, path: String, old_point_mapping: &HashMap<String, u64>) -> Result<(HashMap<String, u64>, HashMap<String, PointParam>, HashMap<String, bool>, Vec<AppApiParam>), AdapterErr> {
+    async fn parse_points(&self, path: String, old_point_mapping: &HashMap<String, u64>) -> Result<(HashMap<String, u64>, HashMap<String, PointParam>, HashMap<String, bool>, Vec<AppApiParam>, bool), AdapterErr> {
         // 打开文件
         if let Ok(file) = File::open(path) {
             let reader = BufReader::new(file);
@@ -645,11 +1152,11 @@ impl ParserManager {
             match serde_json::from_reader(reader) {
                 Ok(points) => {
                     let (new_points, points_mapping, point_param, point_discrete, app_api_params) = points_to_south(points, old_point_mapping)?;
-                    let _ = update_points(new_points).await?;
+                    let changed = update_points(new_points).await?;
                     self.replace_point_mapping(old_point_mapping, &points_mapping);
                     let _ = self.delete_all_app_api_mapping();
                     self.save_app_api_mapping(&app_api_params);
-                    Ok((points_mapping, point_param, point_discrete, app_api_params))
+                    Ok((points_mapping, point_param, point_discrete, app_api_params, changed))
                 },
                 Err(err) => Err(AdapterErr {
                     code: ErrCode::PointJsonDeserializeErr,
@@ -664,7 +1171,7 @@ impl ParserManager {
         }
     }
 
-    async fn parse_transports(&self, path: String, points_mapping: &HashMap<String, u64>, point_param: &HashMap<String, PointParam>, point_discrete: &HashMap<String, bool>) -> Result<u64, AdapterErr> {
+    async fn parse_transports(&self, path: String, points_mapping: &HashMap<String, u64>, point_param: &HashMap<String, PointParam>, point_discrete: &HashMap<String, bool>) -> Result<bool, AdapterErr> {
         // 打开文件
         if let Ok(file) = File::open(path) {
             let reader = BufReader::new(file);
@@ -672,18 +1179,18 @@ impl ParserManager {
             match serde_json::from_reader::<_, MyTransports>(reader) {
                 Ok(transports) => {
                     if transports.transports.is_none() {
-                        return Ok(65536_u64);
+                        return Ok(false);
                     }
                     let devs = query_dev(&transports).await?;
                     // let devs_json = serde_json::to_string(&devs).unwrap();
                     // log::info!("do dev_guid mqtt receive: {}", devs_json);
                     log::info!("end do dev_guid mqtt");
                     let dev_mapping = build_dev_mapping(&devs);
-                    let (new_transports, current_id) = transports_to_south(transports, points_mapping, &dev_mapping, point_param, point_discrete)?;
-                    let _ = update_transports(new_transports).await?;
+                    let (new_transports, _) = transports_to_south(transports, points_mapping, &dev_mapping, point_param, point_discrete)?;
+                    let changed = update_transports(new_transports).await?;
                     let _ = self.delete_all_dev_mapping();
                     self.save_dev_mapping(&devs);
-                    Ok(current_id)
+                    Ok(changed)
                 },
                 Err(err) => Err(AdapterErr {
                     code: ErrCode::TransportJsonDeserializeErr,
@@ -698,18 +1205,27 @@ impl ParserManager {
         }
     }
 
-    async fn parse_aoes(&self, path: String, points_mapping: &HashMap<String, u64>, current_id: u64) -> Result<(), AdapterErr> {
+    // 返回修改过的策略以及每个策略的同步结果
+    async fn parse_aoes(&self, path: String, points_mapping: &HashMap<String, u64>, old_aoe_mapping: &HashMap<u64, u64>, last_id: u64)
+        -> Result<(Vec<AoeModel>, Vec<(u64, SyncOutcome)>), AdapterErr> {
         // 打开文件
         if let Ok(file) = File::open(path) {
             let reader = BufReader::new(file);
             // 反序列化为对象
             match serde_json::from_reader(reader) {
                 Ok(aoes) => {
-                    let (new_aoes, aoes_mapping) = aoes_to_south(aoes, &points_mapping, current_id)?;
-                    let _ = update_aoes(new_aoes).await?;
+                    let (new_aoes, aoes_mapping, last_id) = aoes_to_south(aoes, &points_mapping, old_aoe_mapping, last_id)?;
+                    // 先记录已分配的id，保证删除后的id不会被再次使用
+                    self.save_last_id(AOE_TREE, last_id);
+                    let outcomes = update_aoes(new_aoes.clone()).await?;
                     let _ = self.delete_all_aoe_mapping();
                     self.save_aoe_mapping(&aoes_mapping);
-                    Ok(())
+                    let edited = outcomes.iter()
+                        .filter(|(_, o)| *o == SyncOutcome::Edited)
+                        .map(|(id, _)| *id)
+                        .collect::<HashSet<u64>>();
+                    let edited_aoes = new_aoes.into_iter().filter(|a| edited.contains(&a.id)).collect();
+                    Ok((edited_aoes, outcomes))
                 },
                 Err(err) => Err(AdapterErr {
                     code: ErrCode::AoeJsonDeserializeErr,
@@ -849,45 +1365,74 @@ impl ParserManager {
         delete_items_by_keys_with_tree_name(&self.inner_db, APP_API_TREE, keys)
     }
 
-    async fn start_mems_parser(&self, path: &str, aoe_dir: &str, dff_dir: &str) -> Result<(), AdapterErr> {
+    async fn start_mems_parser(&self, path: &str, aoe_dir: &str, dff_dir: &str) -> MemsUpdateResult {
         let file_name_aoes = format!("{path}/{aoe_dir}");
         let file_name_dffs = format!("{path}/{dff_dir}");
         let points_mapping = self.query_point_mapping();
         // 记录更新前的南北向ID映射
         let old_dff_mapping = self.query_dff_mapping();
         let old_aoe_mapping = self.query_aoe_mapping();
-        let current_id = 65535_u64;
+        let (aoe_last_id, dff_last_id) = self.query_last_ids(&old_aoe_mapping, &old_dff_mapping);
 
         log::info!("start parse aoes.json");
-        self.parse_aoes(file_name_aoes, &points_mapping, current_id).await?;
-        do_apply_current_aoes().await?;
+        JOBS.set_stage(MemsStage::Aoes);
+        let (edited_aoes, aoe_outcomes) = self.parse_aoes(file_name_aoes, &points_mapping, &old_aoe_mapping, aoe_last_id).await
+            .map_err(|e| (MemsStage::Aoes, e))?;
+        JOBS.set_stage(MemsStage::ApplyAoes);
+        do_apply_current_aoes().await.map_err(|e| (MemsStage::ApplyAoes, e))?;
         log::info!("end parse aoes.json");
         
         log::info!("start parse dffs.json");
-        self.parse_dffs(file_name_dffs, &points_mapping, current_id).await?;
+        JOBS.set_stage(MemsStage::Dffs);
+        let (dff_outcomes, dff_refresh) = self.parse_dffs(file_name_dffs, &points_mapping, &old_dff_mapping, dff_last_id).await
+            .map_err(|e| (MemsStage::Dffs, e))?;
         log::info!("end parse dffs.json");
 
-        log::info!("start do mems reset");
         let new_dff_mapping = self.query_dff_mapping();
         let new_aoe_mapping = self.query_aoe_mapping();
-        do_reset_mems(&old_dff_mapping, &new_dff_mapping, &old_aoe_mapping, &new_aoe_mapping).await?;
-        log::info!("end do mems reset");
-        
-        Ok(())
+        // 只有事件驱动、数据源驱动或写入策略变量的报表有变化时才需要重置MEMS，重置后按原有状态启动策略和报表；
+        // 其余变化只停止和启动涉及的策略和报表
+        match dff_refresh {
+            None => {
+                log::info!("start do mems reset");
+                JOBS.set_stage(MemsStage::Reset);
+                do_reset_mems(&old_dff_mapping, &new_dff_mapping, &old_aoe_mapping, &new_aoe_mapping).await
+                    .map_err(|e| (MemsStage::Reset, e))?;
+                log::info!("end do mems reset");
+            }
+            Some(to_start) => {
+                let added = aoe_outcomes.iter()
+                    .filter(|(_, o)| *o == SyncOutcome::Added)
+                    .map(|(id, _)| *id)
+                    .collect::<Vec<u64>>();
+                JOBS.set_stage(MemsStage::RefreshAoes);
+                do_refresh_aoes(edited_aoes, added).await
+                    .map_err(|e| (MemsStage::RefreshAoes, e))?;
+                JOBS.set_stage(MemsStage::RefreshDffs);
+                do_refresh_dffs(to_start).await
+                    .map_err(|e| (MemsStage::RefreshDffs, e))?;
+            }
+        }
+
+        let mut items = to_sync_items(ValidateTarget::Aoe, aoe_outcomes, &old_aoe_mapping, &new_aoe_mapping);
+        items.extend(to_sync_items(ValidateTarget::Dff, dff_outcomes, &old_dff_mapping, &new_dff_mapping));
+        Ok(items)
     }
 
-    async fn parse_dffs(&self, path: String, points_mapping: &HashMap<String, u64>, current_id: u64) -> Result<(), AdapterErr> {
+    async fn parse_dffs(&self, path: String, points_mapping: &HashMap<String, u64>, old_dff_mapping: &HashMap<u64, u64>, last_id: u64)
+        -> Result<(Vec<(u64, SyncOutcome)>, Option<Vec<u64>>), AdapterErr> {
         // 打开文件
         if let Ok(file) = File::open(path) {
             let reader = BufReader::new(file);
             // 反序列化为对象
             match serde_json::from_reader(reader) {
                 Ok(dffs) => {
-                    let (new_dffs, dffs_mapping) = dffs_to_south(dffs, &points_mapping, current_id)?;
-                    let _ = update_dffs(new_dffs).await?;
+                    let (new_dffs, dffs_mapping, last_id) = dffs_to_south(dffs, &points_mapping, old_dff_mapping, last_id)?;
+                    self.save_last_id(DFF_TREE, last_id);
+                    let result = update_dffs(new_dffs).await?;
                     let _ = self.delete_all_dff_mapping();
                     self.save_dff_mapping(&dffs_mapping);
-                    Ok(())
+                    Ok(result)
                 },
                 Err(err) => Err(AdapterErr {
                     code: ErrCode::DffJsonDeserializeErr,
@@ -902,6 +1447,19 @@ impl ParserManager {
         }
     }
 
+    // 已分配过的最大南向策略id和报表id
+    fn query_last_ids(&self, aoe_mapping: &HashMap<u64, u64>, dff_mapping: &HashMap<u64, u64>) -> (u64, u64) {
+        let stored_aoe = query_value_cbor_by_key_with_tree_name::<u64, _>(&self.inner_db, ID_SEQ_TREE, AOE_TREE);
+        let stored_dff = query_value_cbor_by_key_with_tree_name::<u64, _>(&self.inner_db, ID_SEQ_TREE, DFF_TREE);
+        resolve_last_ids(stored_aoe, stored_dff, aoe_mapping, dff_mapping)
+    }
+
+    fn save_last_id(&self, key: &str, last_id: u64) {
+        if !save_item_cbor_to_db_with_tree_name(&self.inner_db, ID_SEQ_TREE, last_id, |_| key.as_bytes().to_vec()) {
+            warn!("!!Failed to insert last id of {key}");
+        }
+    }
+
     fn query_dff_mapping(&self) -> HashMap<u64, u64> {
         let dffs: Vec<DffMapping> = query_values_cbor_with_tree_name(&self.inner_db, DFF_TREE);
         let mut map = HashMap::with_capacity(dffs.len());
@@ -941,19 +1499,244 @@ impl ParserManager {
 
 }
 
+// 记录文件当前内容，文件不存在时为None
+fn snapshot_files(files: Vec<String>) -> Vec<(String, Option<String>)> {
+    files.into_iter().map(|f| {
+        let content = read_to_string(&f).ok();
+        (f, content)
+    }).collect()
+}
+
+fn restore_files(files: &[(String, Option<String>)]) -> bool {
+    let mut is_ok = true;
+    for (file, content) in files {
+        let r = match content {
+            Some(content) => write(file, content),
+            None => if Path::new(file).exists() { remove_file(file) } else { Ok(()) },
+        };
+        if let Err(e) = r {
+            log::error!("!!Failed to restore {file}: {e:?}");
+            is_ok = false;
+        }
+    }
+    is_ok
+}
+
+fn read_result_json<T: DeserializeOwned>(file_name: &str) -> Option<T> {
+    let file = File::open(file_name).ok()?;
+    serde_json::from_reader(BufReader::new(file)).ok()
+}
+
+// 清除上次失败后残留的暂存文件
+fn prepare_upload_dir(upload_dir: &str) -> Result<(), AdapterErr> {
+    clear_upload_dir(upload_dir);
+    create_dir_all(upload_dir).map_err(|e| AdapterErr {
+        code: ErrCode::IoErr,
+        msg: format!("创建上传目录{upload_dir}失败：{e}"),
+    })
+}
+
+// 应用成功后删除暂存的增量，避免之后的更新任务重复合并
+fn clear_upload_dir(upload_dir: &str) {
+    if Path::new(upload_dir).exists() {
+        if let Err(e) = remove_dir_all(upload_dir) {
+            log::warn!("!!Failed to remove upload dir {upload_dir}: {e:?}");
+        }
+    }
+}
+
+fn stage_json<T: Serialize>(file_name: &str, value: &T) -> Result<(), AdapterErr> {
+    let content = serde_json::to_string(value).map_err(|e| AdapterErr {
+        code: ErrCode::InternalErr,
+        msg: format!("配置序列化失败：{e}"),
+    })?;
+    write(file_name, content).map_err(|e| AdapterErr {
+        code: ErrCode::IoErr,
+        msg: format!("写入配置文件{file_name}失败：{e}"),
+    })
+}
+
+/// 等待解析服务处理完已收到的请求并关闭数据库
+pub async fn stop_parser_service() {
+    if let Err(e) = call_parser(ParserOperation::Quit).await {
+        warn!("!!Failed to stop parser service : {}", e.msg);
+    }
+}
+
+/// 解析服务数据库中的所有映射
+#[derive(Serialize, Debug, Default)]
+pub struct MappingDump {
+    /// 北向测点id到南向测点id
+    pub points: BTreeMap<String, u64>,
+    /// 南向策略id到北向策略id
+    pub aoes: BTreeMap<u64, u64>,
+    /// 南向报表id到北向报表id
+    pub dffs: BTreeMap<u64, u64>,
+    pub devs: Vec<QueryDevResponseBody>,
+    pub app_apis: Vec<AppApiParam>,
+    /// 已分配过的最大南向策略id和报表id
+    pub aoe_last_id: u64,
+    pub dff_last_id: u64,
+}
+
+/// 导出数据库时的一条记录，key和value为base64编码
+#[derive(Serialize, Deserialize, Debug)]
+struct DbRecord {
+    cf: String,
+    key: String,
+    value: String,
+}
+
+/// 离线读取映射，供命令行使用
+pub fn dump_mappings(parser_db_dir: &str) -> Result<MappingDump, AdapterErr> {
+    let db = ParserManager::open_read_only(parser_db_dir)?;
+    let aoes = db.query_aoe_mapping();
+    let dffs = db.query_dff_mapping();
+    let (aoe_last_id, dff_last_id) = db.query_last_ids(&aoes, &dffs);
+    Ok(MappingDump {
+        points: db.query_point_mapping().into_iter().collect(),
+        aoes: aoes.into_iter().collect(),
+        dffs: dffs.into_iter().collect(),
+        devs: db.query_dev_mapping(),
+        app_apis: db.query_app_api_mapping(),
+        aoe_last_id,
+        dff_last_id,
+    })
+}
+
+/// 将数据库所有列族导出为JSON Lines，返回记录数
+pub fn export_db<W: Write>(parser_db_dir: &str, writer: &mut W) -> Result<usize, AdapterErr> {
+    let db = ParserManager::open_read_only(parser_db_dir)?;
+    let mut count = 0;
+    for tree in PARSER_TREES {
+        let (keys, values) = query_kv_with_tree_name(&db.inner_db, tree);
+        for (key, value) in keys.iter().zip(values.iter()) {
+            let record = DbRecord { cf: tree.to_string(), key: b64_standard.encode(key), value: b64_standard.encode(value) };
+            let line = serde_json::to_string(&record).unwrap();
+            writeln!(writer, "{line}").map_err(|e| AdapterErr {
+                code: ErrCode::IoErr,
+                msg: format!("写入导出文件失败：{e}"),
+            })?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// 导入export_db导出的记录，已有的同名key会被覆盖，需要先停止服务
+pub fn import_db<R: BufRead>(parser_db_dir: &str, reader: R) -> Result<usize, AdapterErr> {
+    let db = ParserManager::new(parser_db_dir).ok_or_else(|| AdapterErr {
+        code: ErrCode::InternalErr,
+        msg: format!("打开数据库{parser_db_dir}失败，请确认服务已停止"),
+    })?;
+    let mut records: HashMap<String, (Vec<Vec<u8>>, Vec<Vec<u8>>)> = HashMap::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(|e| AdapterErr {
+            code: ErrCode::IoErr,
+            msg: format!("读取导入文件失败：{e}"),
+        })?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let invalid = |msg: String| AdapterErr {
+            code: ErrCode::DataJsonDeserializeErr,
+            msg: format!("导入文件第{}行格式错误：{msg}", i + 1),
+        };
+        let record = serde_json::from_str::<DbRecord>(&line).map_err(|e| invalid(e.to_string()))?;
+        if !PARSER_TREES.contains(&record.cf.as_str()) {
+            return Err(invalid(format!("未知的列族{}", record.cf)));
+        }
+        let key = b64_standard.decode(&record.key).map_err(|e| invalid(e.to_string()))?;
+        let value = b64_standard.decode(&record.value).map_err(|e| invalid(e.to_string()))?;
+        let (keys, values) = records.entry(record.cf).or_default();
+        keys.push(key);
+        values.push(value);
+    }
+    // 全部解析成功后再写入
+    let mut count = 0;
+    for (tree, (keys, values)) in records {
+        if !save_items_to_db_with_tree_name(&db.inner_db, &tree, &keys, &values) {
+            return Err(AdapterErr {
+                code: ErrCode::InternalErr,
+                msg: format!("写入列族{tree}失败"),
+            });
+        }
+        count += keys.len();
+    }
+    if let Err(e) = db.inner_db.flush() {
+        warn!("!!Failed to flush parser db : {e:?}");
+    }
+    Ok(count)
+}
+
+/// 解析服务数据库各列族的大小
+pub fn parser_cf_sizes() -> Vec<(String, u64)> {
+    PARSER_CF_SIZES.read().unwrap().clone()
+}
+
+/// 进程内调用解析服务，供MQTT任务查询映射，不能在解析服务的操作中调用
+pub async fn call_parser<T>(op: impl FnOnce(Sender<T>) -> ParserOperation) -> Result<T, AdapterErr> {
+    let sender = PARSER_SENDER.get().ok_or_else(|| AdapterErr {
+        code: ErrCode::InternalErr,
+        msg: "解析服务未启动".to_string(),
+    })?;
+    let (tx, rx) = bounded(1);
+    sender.send(op(tx)).await.map_err(|_| AdapterErr {
+        code: ErrCode::InternalErr,
+        msg: "解析服务已停止".to_string(),
+    })?;
+    rx.recv().await.map_err(|_| AdapterErr {
+        code: ErrCode::InternalErr,
+        msg: "解析服务未返回结果".to_string(),
+    })
+}
+
+/// 供进程内调用方提交后台任务，立即返回任务信息
+pub async fn submit_parser_job(input: JobInput) -> Result<Job, AdapterErr> {
+    let sender = PARSER_SENDER.get().ok_or_else(|| AdapterErr {
+        code: ErrCode::InternalErr,
+        msg: "解析服务未启动".to_string(),
+    })?;
+    send_job(sender, input).await
+}
+
+// 创建任务并交给解析服务，发送失败时结束任务
+async fn send_job(sender: &Sender<ParserOperation>, input: JobInput) -> Result<Job, AdapterErr> {
+    let job = JOBS.submit(input.kind())?;
+    if sender.send(ParserOperation::RunJob(job.id, input)).await.is_err() {
+        let e = AdapterErr {
+            code: ErrCode::InternalErr,
+            msg: "解析服务未启动".to_string(),
+        };
+        JOBS.finish(job.id, ApiResponse::from_err(e.clone()));
+        return Err(e);
+    }
+    Ok(job)
+}
+
 pub fn start_parser_service(parser_db_dir: String) -> Sender<ParserOperation> {
     info!("start parser service job...");
     // 启动解析服务
     let (op_sender, op_receiver) = bounded(OPERATION_RECEIVE_BUFF_NUM);
-    tokio::spawn(async move {
+    if PARSER_SENDER.set(op_sender.clone()).is_err() {
+        warn!("!!Parser service has already been started");
+    }
+    spawn_task(TASK_PARSER, async move {
         if let Some(db) = ParserManager::new(&parser_db_dir) {
             loop {
                 match op_receiver.recv().await {
-                    Ok(op) => {
-                        if matches!(op, ParserOperation::Quit) {
-                            break;
+                    Ok(ParserOperation::Quit(sender)) => {
+                        if let Err(e) = db.inner_db.flush() {
+                            warn!("!!Failed to flush parser db : {e:?}");
                         }
+                        drop(db);
+                        info!("parser service stopped");
+                        let _ = sender.send(()).await;
+                        break;
+                    }
+                    Ok(op) => {
                         db.do_operation(op).await;
+                        *PARSER_CF_SIZES.write().unwrap() = query_cf_sizes(&db.inner_db, &PARSER_TREES);
                     }
                     Err(e) => {
                         warn!("!!Error occurs when listening new db operation, err: {e:?}");
@@ -968,153 +1751,445 @@ pub fn start_parser_service(parser_db_dir: String) -> Sender<ParserOperation> {
     op_sender
 }
 
+fn http_status(code: &ErrCode) -> StatusCode {
+    match code {
+        ErrCode::Success => StatusCode::OK,
+        ErrCode::DataJsonDeserializeErr => StatusCode::BAD_REQUEST,
+        ErrCode::PointJsonNotFound
+        | ErrCode::TransportJsonNotFound
+        | ErrCode::AoeJsonNotFound
+        | ErrCode::DffJsonNotFound
+        | ErrCode::AoeIdNotFound
+        | ErrCode::DffIdNotFound
+        | ErrCode::RevisionNotFound
+        | ErrCode::JobNotFound => StatusCode::NOT_FOUND,
+        ErrCode::JobRunning => StatusCode::CONFLICT,
+        ErrCode::PointJsonDeserializeErr
+        | ErrCode::PointIsEmpty
+        | ErrCode::PointUndefined
+        | ErrCode::TransportJsonDeserializeErr
+        | ErrCode::TransportIsEmpty
+        | ErrCode::TransportPointNotFound
+        | ErrCode::TransportPointTagErr
+        | ErrCode::QueryDevAttrNotFound
+        | ErrCode::DevGuidNotFound
+        | ErrCode::AoeJsonDeserializeErr
+        | ErrCode::AoeVariableErr
+        | ErrCode::AoeEventErr
+        | ErrCode::AoeActionErr
+        | ErrCode::DffJsonDeserializeErr
+        | ErrCode::DffVariableErr
+        | ErrCode::DuplicateId
+        | ErrCode::ConfigInvalid => StatusCode::UNPROCESSABLE_ENTITY,
+        ErrCode::MqttTimeoutErr | ErrCode::QueryDevTimeout => StatusCode::GATEWAY_TIMEOUT,
+        ErrCode::MqttConnectErr
+        | ErrCode::PlccConnectErr
+        | ErrCode::MemsConnectErr
+        | ErrCode::QueryDevDeserializeErr
+        | ErrCode::AppRegisterErr
+        | ErrCode::ModelRegisterErr
+        | ErrCode::QueryRegisterDevErr
+        | ErrCode::PlccActionErr
+        | ErrCode::DffActionErr
+        | ErrCode::MemsActionErr => StatusCode::BAD_GATEWAY,
+        ErrCode::PlccAdapterNotFound
+        | ErrCode::InternalErr
+        | ErrCode::IoErr
+        | ErrCode::Other => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+pub(crate) fn api_response(resp: ApiResponse) -> HttpResponse {
+    HttpResponse::build(http_status(&resp.code)).content_type("application/json").json(resp)
+}
+
+fn stage_response<S: Serialize>(result: Result<(), (S, AdapterErr)>) -> HttpResponse {
+    match result {
+        Ok(()) => api_response(ApiResponse::success()),
+        Err((stage, e)) => api_response(ApiResponse::from_err(e).with_stage(stage)),
+    }
+}
+
+// 将每个南向id的同步结果转换为北向id，已删除的对象从旧映射中查找
+fn to_sync_items(target: ValidateTarget, outcomes: Vec<(u64, SyncOutcome)>, old_mapping: &HashMap<u64, u64>, new_mapping: &HashMap<u64, u64>) -> Vec<SyncItem> {
+    outcomes.into_iter().filter_map(|(sid, outcome)| {
+        let nid = if outcome == SyncOutcome::Deleted {
+            old_mapping.get(&sid)
+        } else {
+            new_mapping.get(&sid)
+        };
+        nid.map(|id| SyncItem { target, id: *id, outcome })
+    }).collect()
+}
+
+/// 成功时在details中返回每个策略和报表的同步结果
+pub fn mems_api_response(result: MemsUpdateResult) -> ApiResponse {
+    match result {
+        Ok(items) => ApiResponse::success().with_details(serde_json::to_value(&items).unwrap_or_default()),
+        Err((stage, e)) => ApiResponse::from_err(e).with_stage(stage),
+    }
+}
+
+/// 任务提交结果，成功时details为任务信息，执行结果通过任务状态查询
+pub fn job_api_response(result: Result<Job, AdapterErr>) -> ApiResponse {
+    match result {
+        Ok(job) => ApiResponse::success().with_details(serde_json::to_value(&job).unwrap_or_default()),
+        Err(e) => ApiResponse::from_err(e),
+    }
+}
+
+// 查询结果放入details，与其他接口返回相同的结构
+fn details_response<T: Serialize>(value: &T) -> HttpResponse {
+    api_response(ApiResponse::success().with_details(serde_json::to_value(value).unwrap_or_default()))
+}
+
+fn mems_response(result: MemsUpdateResult) -> HttpResponse {
+    api_response(mems_api_response(result))
+}
+
+fn validate_response(result: ValidateResult) -> HttpResponse {
+    let resp = if result.errors.is_empty() {
+        ApiResponse::success()
+    } else {
+        ApiResponse::from_err(AdapterErr {
+            code: result.code,
+            msg: format!("校验发现{}个错误", result.errors.len()),
+        })
+    };
+    api_response(resp.with_details(serde_json::to_value(&result.errors).unwrap_or_default()))
+}
+
+// 向解析服务发送请求并等待结果，失败时返回对应的HTTP错误
+async fn request_parser<T>(
+    sender: &Sender<ParserOperation>,
+    op: impl FnOnce(Sender<T>) -> ParserOperation,
+) -> Result<T, HttpResponse> {
+    let (tx, rx) = bounded(1);
+    if sender.send(op(tx)).await.is_err() {
+        let resp = ApiResponse::from_err(AdapterErr {
+            code: ErrCode::InternalErr,
+            msg: "解析服务未启动".to_string(),
+        });
+        return Err(HttpResponse::ServiceUnavailable().content_type("application/json").json(resp));
+    }
+    rx.recv().await.map_err(|_| {
+        let resp = ApiResponse::from_err(AdapterErr {
+            code: ErrCode::InternalErr,
+            msg: "解析服务未返回结果".to_string(),
+        });
+        HttpResponse::InternalServerError().content_type("application/json").json(resp)
+    })
+}
+
+// 创建任务后交给解析服务在后台执行，立即返回任务信息
+async fn submit_job(sender: &Sender<ParserOperation>, input: JobInput) -> HttpResponse {
+    match send_job(sender, input).await {
+        Ok(job) => HttpResponse::Accepted().content_type("application/json").json(job_api_response(Ok(job))),
+        Err(e) if e.code == ErrCode::JobRunning => api_response(ApiResponse::from_err(e)),
+        Err(e) => HttpResponse::ServiceUnavailable().content_type("application/json").json(ApiResponse::from_err(e)),
+    }
+}
+
 #[get("/api/v1/parser/update_plcc")]
 async fn update_plcc(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::UpdatePlcc(tx)).await {
-        if let Ok(r) = rx.recv().await {
-            return HttpResponse::Ok().content_type("application/json").json(r);
-        }
-    }
-    HttpResponse::RequestTimeout().finish()
+    submit_job(&sender, JobInput::UpdatePlcc).await
 }
 
 #[get("/api/v1/parser/recover_plcc")]
 async fn recover_plcc(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::RecoverPlcc(tx)).await {
-        if let Ok(r) = rx.recv().await {
-            return HttpResponse::Ok().content_type("application/json").json(r);
-        }
+    match request_parser(&sender, ParserOperation::RecoverPlcc).await {
+        Ok(r) => stage_response(r),
+        Err(resp) => resp,
     }
-    HttpResponse::RequestTimeout().finish()
 }
 
 #[get("/api/v1/parser/point_mapping")]
 async fn get_point_mapping(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::GetPointMapping(tx)).await {
-        if let Ok(r) = rx.recv().await {
-            return HttpResponse::Ok().content_type("application/json").json(r);
-        }
+    match request_parser(&sender, ParserOperation::GetPointMapping).await {
+        Ok(r) => details_response(&r),
+        Err(resp) => resp,
     }
-    HttpResponse::RequestTimeout().finish()
 }
 
 #[get("/api/v1/parser/dev_mapping")]
 async fn get_dev_mapping(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::GetDevMapping(tx)).await {
-        if let Ok(r) = rx.recv().await {
-            return HttpResponse::Ok().content_type("application/json").json(r);
-        }
+    match request_parser(&sender, ParserOperation::GetDevMapping).await {
+        Ok(r) => details_response(&r),
+        Err(resp) => resp,
     }
-    HttpResponse::RequestTimeout().finish()
 }
 
 #[get("/api/v1/parser/aoe_mapping")]
 async fn get_aoe_mapping(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::GetAoeMapping(tx)).await {
-        if let Ok(r) = rx.recv().await {
-            return HttpResponse::Ok().content_type("application/json").json(r);
-        }
+    match request_parser(&sender, ParserOperation::GetAoeMapping).await {
+        Ok(r) => details_response(&r),
+        Err(resp) => resp,
     }
-    HttpResponse::RequestTimeout().finish()
 }
 
 #[get("/api/v1/parser/update_mems")]
 async fn update_mems(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::UpdateMems(tx)).await {
-        if let Ok(r) = rx.recv().await {
-            return HttpResponse::Ok().content_type("application/json").json(r);
-        }
+    submit_job(&sender, JobInput::UpdateMems).await
+}
+
+#[get("/api/v1/jobs")]
+async fn list_jobs() -> HttpResponse {
+    details_response(&JOBS.list())
+}
+
+#[get("/api/v1/jobs/{id}")]
+async fn get_job(
+    path: web::Path<u64>,
+) -> HttpResponse {
+    let id = path.into_inner();
+    match JOBS.get(id) {
+        Some(job) => details_response(&job),
+        None => api_response(ApiResponse::from_err(AdapterErr {
+            code: ErrCode::JobNotFound,
+            msg: format!("任务{id}不存在"),
+        })),
     }
-    HttpResponse::RequestTimeout().finish()
 }
 
 #[get("/api/v1/parser/recover_mems")]
 async fn recover_mems(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::RecoverMems(tx)).await {
-        if let Ok(r) = rx.recv().await {
-            return HttpResponse::Ok().content_type("application/json").json(r);
-        }
+    match request_parser(&sender, ParserOperation::RecoverMems).await {
+        Ok(r) => mems_response(r),
+        Err(resp) => resp,
     }
-    HttpResponse::RequestTimeout().finish()
 }
 
 #[get("/api/v1/parser/start_dff")]
 async fn start_dff(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::StartDff(tx)).await {
-        if let Ok(r) = rx.recv().await {
-            return HttpResponse::Ok().content_type("application/json").json(r);
-        }
+    match request_parser(&sender, ParserOperation::StartDff).await {
+        Ok(r) => api_response(r.into()),
+        Err(resp) => resp,
     }
-    HttpResponse::RequestTimeout().finish()
 }
 
 #[get("/api/v1/parser/dff_mapping")]
 async fn get_dff_mapping(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::GetDffMapping(tx)).await {
-        if let Ok(r) = rx.recv().await {
-            return HttpResponse::Ok().content_type("application/json").json(r);
-        }
+    match request_parser(&sender, ParserOperation::GetDffMapping).await {
+        Ok(r) => details_response(&r),
+        Err(resp) => resp,
     }
-    HttpResponse::RequestTimeout().finish()
 }
 
 #[get("/api/v1/meter/data")]
 async fn get_meter_data(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::GetMeterData(tx)).await {
-        if let Ok(csv_string) = rx.recv().await {
-            return HttpResponse::Ok()
-                .insert_header(("Content-Type", "text/csv; charset=utf-8"))
-                .insert_header((
-                    "Content-Disposition",
-                    "attachment; filename=\"history_data.csv\"",
-                ))
-                .body(csv_string);
-        }
+    match request_parser(&sender, ParserOperation::GetMeterData).await {
+        Ok(Ok(csv_string)) => HttpResponse::Ok()
+            .insert_header(("Content-Type", "text/csv; charset=utf-8"))
+            .insert_header((
+                "Content-Disposition",
+                "attachment; filename=\"history_data.csv\"",
+            ))
+            .body(csv_string),
+        Ok(Err(e)) => api_response(ApiResponse::from_err(e)),
+        Err(resp) => resp,
     }
-    HttpResponse::RequestTimeout().finish()
 }
 
 #[get("/api/v1/parser/app_api_mapping")]
 async fn get_app_api_mapping(
     sender: web::Data<Sender<ParserOperation>>,
 ) -> HttpResponse {
-    let (tx, rx) = bounded(1);
-    if let Ok(()) = sender.send(ParserOperation::GetAppApiMapping(tx)).await {
-        if let Ok(r) = rx.recv().await {
-            return HttpResponse::Ok().content_type("application/json").json(r);
-        }
+    match request_parser(&sender, ParserOperation::GetAppApiMapping).await {
+        Ok(r) => details_response(&r),
+        Err(resp) => resp,
+    }
+}
+
+#[get("/api/v1/parser/validate_plcc")]
+async fn validate_plcc(
+    sender: web::Data<Sender<ParserOperation>>,
+) -> HttpResponse {
+    match request_parser(&sender, ParserOperation::ValidatePlcc).await {
+        Ok(r) => validate_response(r),
+        Err(resp) => resp,
+    }
+}
+
+#[get("/api/v1/parser/validate_mems")]
+async fn validate_mems(
+    sender: web::Data<Sender<ParserOperation>>,
+) -> HttpResponse {
+    match request_parser(&sender, ParserOperation::ValidateMems).await {
+        Ok(r) => validate_response(r),
+        Err(resp) => resp,
+    }
+}
+
+#[derive(MultipartForm)]
+struct PlccUploadForm {
+    points: Option<MpBytes>,
+    transports: Option<MpBytes>,
+}
+
+#[derive(MultipartForm)]
+struct MemsUploadForm {
+    aoes: Option<MpBytes>,
+    dffs: Option<MpBytes>,
+}
+
+fn parse_upload<T: DeserializeOwned>(body: &[u8], code: ErrCode, name: &str) -> Result<T, HttpResponse> {
+    serde_json::from_slice(body).map_err(|e| {
+        api_response(ApiResponse::from_err(AdapterErr {
+            code,
+            msg: format!("{name}JSON反序列化失败：{e}"),
+        }))
+    })
+}
+
+fn parse_upload_part<T: DeserializeOwned>(part: Option<MpBytes>, code: ErrCode, name: &str) -> Result<Option<T>, HttpResponse> {
+    match part {
+        Some(part) => parse_upload(&part.data, code, name).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn empty_upload() -> HttpResponse {
+    api_response(ApiResponse::from_err(AdapterErr {
+        code: ErrCode::DataJsonDeserializeErr,
+        msg: "未上传任何配置".to_string(),
+    }))
+}
+
+#[post("/api/v1/parser/points")]
+async fn upload_points(
+    sender: web::Data<Sender<ParserOperation>>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let points = match parse_upload::<MyPoints>(&body, ErrCode::PointJsonDeserializeErr, "测点") {
+        Ok(points) => points,
+        Err(resp) => return resp,
+    };
+    submit_job(&sender, JobInput::UploadPlcc(Some(points), None, RevisionSource::Http)).await
+}
+
+#[post("/api/v1/parser/transports")]
+async fn upload_transports(
+    sender: web::Data<Sender<ParserOperation>>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let transports = match parse_upload::<MyTransports>(&body, ErrCode::TransportJsonDeserializeErr, "通道") {
+        Ok(transports) => transports,
+        Err(resp) => return resp,
+    };
+    submit_job(&sender, JobInput::UploadPlcc(None, Some(transports), RevisionSource::Http)).await
+}
+
+#[post("/api/v1/parser/aoes")]
+async fn upload_aoes(
+    sender: web::Data<Sender<ParserOperation>>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let aoes = match parse_upload::<MyAoes>(&body, ErrCode::AoeJsonDeserializeErr, "策略") {
+        Ok(aoes) => aoes,
+        Err(resp) => return resp,
+    };
+    submit_job(&sender, JobInput::UploadMems(Some(aoes), None, RevisionSource::Http)).await
+}
+
+#[post("/api/v1/parser/dffs")]
+async fn upload_dffs(
+    sender: web::Data<Sender<ParserOperation>>,
+    body: web::Bytes,
+) -> HttpResponse {
+    let dffs = match parse_upload::<MyDffModels>(&body, ErrCode::DffJsonDeserializeErr, "报表") {
+        Ok(dffs) => dffs,
+        Err(resp) => return resp,
+    };
+    submit_job(&sender, JobInput::UploadMems(None, Some(dffs), RevisionSource::Http)).await
+}
+
+#[post("/api/v1/parser/upload_plcc")]
+async fn upload_plcc(
+    sender: web::Data<Sender<ParserOperation>>,
+    MultipartForm(form): MultipartForm<PlccUploadForm>,
+) -> HttpResponse {
+    let points = match parse_upload_part::<MyPoints>(form.points, ErrCode::PointJsonDeserializeErr, "测点") {
+        Ok(points) => points,
+        Err(resp) => return resp,
+    };
+    let transports = match parse_upload_part::<MyTransports>(form.transports, ErrCode::TransportJsonDeserializeErr, "通道") {
+        Ok(transports) => transports,
+        Err(resp) => return resp,
+    };
+    if points.is_none() && transports.is_none() {
+        return empty_upload();
+    }
+    submit_job(&sender, JobInput::UploadPlcc(points, transports, RevisionSource::Http)).await
+}
+
+#[post("/api/v1/parser/upload_mems")]
+async fn upload_mems(
+    sender: web::Data<Sender<ParserOperation>>,
+    MultipartForm(form): MultipartForm<MemsUploadForm>,
+) -> HttpResponse {
+    let aoes = match parse_upload_part::<MyAoes>(form.aoes, ErrCode::AoeJsonDeserializeErr, "策略") {
+        Ok(aoes) => aoes,
+        Err(resp) => return resp,
+    };
+    let dffs = match parse_upload_part::<MyDffModels>(form.dffs, ErrCode::DffJsonDeserializeErr, "报表") {
+        Ok(dffs) => dffs,
+        Err(resp) => return resp,
+    };
+    if aoes.is_none() && dffs.is_none() {
+        return empty_upload();
     }
-    HttpResponse::RequestTimeout().finish()
+    submit_job(&sender, JobInput::UploadMems(aoes, dffs, RevisionSource::Http)).await
+}
+
+#[get("/api/v1/parser/revisions")]
+async fn list_revisions(
+    sender: web::Data<Sender<ParserOperation>>,
+) -> HttpResponse {
+    match request_parser(&sender, ParserOperation::ListRevisions).await {
+        Ok(r) => details_response(&r),
+        Err(resp) => resp,
+    }
+}
+
+#[get("/api/v1/parser/revisions/{from}/diff/{to}")]
+async fn diff_revision(
+    sender: web::Data<Sender<ParserOperation>>,
+    path: web::Path<(u64, u64)>,
+) -> HttpResponse {
+    let (from, to) = path.into_inner();
+    match request_parser(&sender, |tx| ParserOperation::DiffRevision(from, to, tx)).await {
+        Ok(Ok(r)) => details_response(&r),
+        Ok(Err(e)) => api_response(ApiResponse::from_err(e)),
+        Err(resp) => resp,
+    }
+}
+
+#[post("/api/v1/parser/revisions/{version}/apply")]
+async fn revert_revision(
+    sender: web::Data<Sender<ParserOperation>>,
+    path: web::Path<u64>,
+) -> HttpResponse {
+    submit_job(&sender, JobInput::RevertRevision(path.into_inner())).await
 }
 
 pub fn config_parser_web_service(cfg: &mut web::ServiceConfig) {
@@ -1129,7 +2204,20 @@ pub fn config_parser_web_service(cfg: &mut web::ServiceConfig) {
     .service(start_dff)
     .service(get_dff_mapping)
     .service(get_meter_data)
-    .service(get_app_api_mapping);
+    .service(get_app_api_mapping)
+    .service(validate_plcc)
+    .service(validate_mems)
+    .service(upload_points)
+    .service(upload_transports)
+    .service(upload_aoes)
+    .service(upload_dffs)
+    .service(upload_plcc)
+    .service(upload_mems)
+    .service(list_revisions)
+    .service(diff_revision)
+    .service(revert_revision)
+    .service(list_jobs)
+    .service(get_job);
 }
 
 async fn query_dev(transports: &MyTransports) -> Result<Vec<QueryDevResponseBody>, AdapterErr> {
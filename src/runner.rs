@@ -1,20 +1,38 @@
 use log::info;
 
 use actix_cors::Cors;
+use actix_multipart::form::MultipartFormConfig;
 use actix_web::{App, HttpServer, web};
 use actix_web::middleware::Compress;
 use actix_web::web::Data;
 use crate::ADAPTER_NAME;
-use crate::parser::{start_parser_service, config_parser_web_service};
+use crate::parser::{start_parser_service, stop_parser_service, config_parser_web_service};
 use crate::utils::plccmqtt::{do_register, do_data_query, do_keep_alive, do_cloud_event, do_app_api_event};
 use crate::utils::memsmqtt::{do_meter_data_query_job, do_mems_event};
-use crate::utils::memsapi::{aoe_result_upload, dff_result_upload};
+use crate::utils::memsapi::{aoe_result_upload, dff_result_upload, flush_pending_uploads};
 use crate::utils::log_init::write_log_config;
-use crate::env::Env;
+use crate::utils::events::config_event_web_service;
+use crate::utils::health::config_health_web_service;
+use crate::utils::metrics::config_metrics_web_service;
+use crate::utils::config_reload::{config_reload_web_service, do_config_watch};
+use crate::utils::supervisor::{shutdown, wait_exit_signal};
+use crate::db::jobstore::JOBS;
+use crate::env::{AdapterConfig, Env};
 
 pub async fn run_adapter() -> std::io::Result<()> {
-    Env::init(ADAPTER_NAME);
-    let env = Env::get_env(ADAPTER_NAME);
+    // 启动前校验配置，列出所有错误后退出
+    let loaded = AdapterConfig::load();
+    for w in &loaded.warnings {
+        eprintln!("配置警告：{w}");
+    }
+    if !loaded.errors.is_empty() {
+        for e in &loaded.errors {
+            eprintln!("配置错误：{e}");
+        }
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, format!("配置校验失败，共{}个错误", loaded.errors.len())));
+    }
+    let env = Env { config: loaded.config };
+    Env::update(ADAPTER_NAME, env.clone());
     // 初始化日志
     let log_config = env.get_log_config();
     let (_, log_config_file) = write_log_config(ADAPTER_NAME, &log_config);
@@ -31,8 +49,13 @@ pub async fn run_adapter() -> std::io::Result<()> {
             log::error!("Failed to initialize log4rs, err: {e}");
         }
     }
+    for w in &loaded.warnings {
+        log::warn!("config warning: {w}");
+    }
     let http_server_port = env.get_http_server_port();
     let data_path = env.get_db_dir();
+    // 先启动解析服务，MQTT任务直接在进程内查询映射
+    let parser_sender = start_parser_service(data_path.to_string());
     // APP注册和数据查询
     log::info!("|-> start do register mqtt");
     match do_register().await {
@@ -109,8 +132,13 @@ pub async fn run_adapter() -> std::io::Result<()> {
         // }
         // log::info!("end do meter_data_query");
     }
-    let parser_sender = start_parser_service(data_path.to_string());
+    // 配置文件修改后自动重新加载
+    if let Err(err) = do_config_watch().await {
+        log::error!("do config_watch error: {}", err.msg);
+    }
     let cloned_parser_sender = Data::new(parser_sender.clone());
+    let (handle_tx, handle_rx) = tokio::sync::oneshot::channel();
+    let (stopped_tx, stopped_rx) = tokio::sync::oneshot::channel::<()>();
     let actix_web_job = std::thread::spawn(move || {
         // 启动web服务，提供resutful服务
         let actix_rt = actix_rt::Runtime::new().expect("!!Failed to build actix web runtime.");
@@ -130,15 +158,40 @@ pub async fn run_adapter() -> std::io::Result<()> {
                     // sets payload size limit to 2147Mb
                     .app_data(web::PayloadConfig::new(1usize << 31))
                     .app_data(web::JsonConfig::default().limit(1usize << 31))
-                    .configure(config_parser_web_service);
+                    .app_data(MultipartFormConfig::default().total_limit(1usize << 31).memory_limit(1usize << 31))
+                    .configure(config_parser_web_service)
+                    .configure(config_event_web_service)
+                    .configure(config_health_web_service)
+                    .configure(config_metrics_web_service)
+                    .configure(config_reload_web_service);
                 app
             });
-            app.bind(&addr).unwrap_or_else(|_| panic!("Failed to bind {addr}"))
-                .run()
-                .await
-                .unwrap_or_else(|_| panic!("Failed to run web server at {addr}"));
+            // 退出信号由主线程统一处理
+            let server = app.bind(&addr).unwrap_or_else(|_| panic!("Failed to bind {addr}"))
+                .disable_signals()
+                .run();
+            let _ = handle_tx.send(server.handle());
+            server.await.unwrap_or_else(|_| panic!("Failed to run web server at {addr}"));
+            let _ = stopped_tx.send(());
         });
     });
+    tokio::select! {
+        _ = wait_exit_signal() => {}
+        _ = stopped_rx => log::warn!("web服务已停止"),
+    }
+    log::info!("|-> start graceful shutdown");
+    // 先停止接收新请求，再停止后台任务，解析服务处理完已收到的请求后关闭数据库
+    if let Ok(handle) = handle_rx.await {
+        handle.stop(true).await;
+    }
+    shutdown();
+    stop_parser_service().await;
+    let flush_timeout = tokio::time::Duration::from_secs(env.get_mqtt_timeout());
+    if tokio::time::timeout(flush_timeout, flush_pending_uploads()).await.is_err() {
+        log::warn!("退出前发送结果超时");
+    }
+    JOBS.flush();
+    log::info!("|<- end graceful shutdown");
     // waiting web service to quit
     actix_web_job.join().expect("actix web job is down.");
     Ok(())
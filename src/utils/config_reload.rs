@@ -0,0 +1,172 @@
+use std::collections::BTreeSet;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use actix_web::{post, web, HttpResponse};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::time::{interval, Duration};
+
+use crate::{ADAPTER_NAME, AdapterErr, ApiResponse, ErrCode};
+use crate::env::*;
+use crate::parser::api_response;
+use crate::utils::events::{publish, AdapterEvent};
+use crate::utils::httpclient::{MEMS_CLIENT, PLCC_CLIENT};
+use crate::utils::log_init::write_log_config;
+use crate::utils::mqttmanager::close_manager;
+use crate::utils::supervisor::*;
+
+// 检查配置文件修改时间的间隔
+const WATCH_SECS: u64 = 5;
+
+// 子系统名称
+const SUB_MQTT: &str = "mqtt";
+const SUB_PLCC_MQTT: &str = "plcc_mqtt";
+const SUB_PLCC_HTTP: &str = "plcc_http";
+const SUB_MEMS_HTTP: &str = "mems_http";
+const SUB_RESULT_UPLOAD: &str = "result_upload";
+const SUB_LOG: &str = "log";
+
+// 各子系统依赖的配置项，其余配置项每次使用时读取，替换后立即生效
+const MQTT_KEYS: [&str; 8] = [MQTT_SERVER, MQTT_AUTH, IS_LOCAL_MQTT, LOCAL_MQTT_PORT, IS_USE_SSL,
+    SSL_CERT_FILE_PATH, SSL_KEY_FILE_PATH, MQTT_SSL_CA_FILE_PATH];
+const PLCC_MQTT_KEYS: [&str; 2] = [PLCC_MQTT_PORT, PLCC_BEE_ID];
+const PLCC_HTTP_KEYS: [&str; 5] = [PLCC_SERVER, PLCC_USER, PLCC_PWD, HTTP_CONNECT_TIMEOUT, HTTP_REQUEST_TIMEOUT];
+const MEMS_HTTP_KEYS: [&str; 5] = [MEMS_SERVER, MEMS_USER, MEMS_PWD, HTTP_CONNECT_TIMEOUT, HTTP_REQUEST_TIMEOUT];
+const RESULT_UPLOAD_KEYS: [&str; 1] = [RESULT_UPLOAD_INTERVAL];
+const LOG_KEYS: [&str; 5] = [LOG_DIR, LOG_LEVEL, LOG_SAVE_TIME, LOG_SAVE_SIZE, LOG_HIS_FILE_NUM];
+// 只在启动时读取，需要重启进程才能完全生效
+const RESTART_REQUIRED_KEYS: [&str; 5] = [HTTP_SERVER_PORT, DB_DIR, IS_USE_MEMS, APP_NAME, APP_MODEL];
+
+// 文件监视和接口可能同时触发重新加载
+static RELOAD_LOCK: Lazy<Mutex<()>> = Lazy::new(|| Mutex::new(()));
+
+/// 重新加载配置的结果
+#[derive(Serialize, Debug, Default)]
+pub struct ReloadReport {
+    pub changed: Vec<ConfigChange>,
+    /// 已按新配置重建的子系统
+    pub restarted: Vec<String>,
+    /// 已重启的后台任务
+    pub tasks: Vec<String>,
+    /// 需要重启进程才能完全生效的配置项
+    pub restart_required: Vec<String>,
+}
+
+impl ReloadReport {
+    fn restart(&mut self, subsystem: &str, tasks: &[&str]) {
+        self.restarted.push(subsystem.to_string());
+        for task in tasks {
+            if !self.tasks.iter().any(|t| t == task) && restart_task(task) {
+                self.tasks.push(task.to_string());
+            }
+        }
+    }
+}
+
+/// 重新读取并校验配置，校验通过后替换当前配置，并重建受影响的MQTT连接、HTTP客户端和定时任务
+pub fn reload_config() -> Result<ReloadReport, Vec<ConfigErr>> {
+    let _lock = RELOAD_LOCK.lock().unwrap();
+    let (old_env, changed) = Env::reload(ADAPTER_NAME)?;
+    let env = Env::get_env(ADAPTER_NAME);
+    let keys = changed.iter().map(|c| c.key.as_str()).collect::<BTreeSet<&str>>();
+    let touched = |group: &[&str]| group.iter().any(|k| keys.contains(k));
+    let mut report = ReloadReport::default();
+    // 数据中心和PLCC的连接按用途区分，即使IS_LOCAL_MQTT时地址相同，关闭一个也不影响另一个
+    if touched(&MQTT_KEYS) {
+        close_manager(&old_env.get_mqtt_server(), old_env.get_mqtt_server_port(), true);
+        report.restart(SUB_MQTT, &[TASK_KEEP_ALIVE, TASK_CLOUD_EVENT, TASK_MEMS_EVENT, TASK_AOE_UPLOAD, TASK_DFF_UPLOAD]);
+    }
+    if touched(&PLCC_MQTT_KEYS) {
+        close_manager("127.0.0.1", old_env.get_plcc_mqtt_port(), false);
+        report.restart(SUB_PLCC_MQTT, &[TASK_APP_API_EVENT]);
+    }
+    if touched(&PLCC_HTTP_KEYS) {
+        PLCC_CLIENT.reset();
+        report.restart(SUB_PLCC_HTTP, &[]);
+    }
+    if touched(&MEMS_HTTP_KEYS) {
+        MEMS_CLIENT.reset();
+        report.restart(SUB_MEMS_HTTP, &[]);
+    }
+    if touched(&RESULT_UPLOAD_KEYS) {
+        report.restart(SUB_RESULT_UPLOAD, &[TASK_AOE_UPLOAD, TASK_DFF_UPLOAD]);
+    }
+    if touched(&LOG_KEYS) {
+        // log4rs定时重新读取日志配置文件
+        write_log_config(ADAPTER_NAME, &env.get_log_config());
+        report.restart(SUB_LOG, &[]);
+    }
+    report.restart_required = RESTART_REQUIRED_KEYS.iter()
+        .filter(|k| keys.contains(*k))
+        .map(|k| k.to_string())
+        .collect();
+    report.changed = changed;
+    if report.changed.is_empty() {
+        log::info!("配置已重新加载，没有变化");
+    } else {
+        log::info!("配置已重新加载，变化的配置项：{:?}，重建的子系统：{:?}", keys, report.restarted);
+        if !report.restart_required.is_empty() {
+            log::warn!("配置项{:?}需要重启服务才能完全生效", report.restart_required);
+        }
+        publish(AdapterEvent::ConfigReloaded {
+            keys: keys.iter().map(|k| k.to_string()).collect(),
+            restarted: report.restarted.clone(),
+            restart_required: report.restart_required.clone(),
+        });
+    }
+    Ok(report)
+}
+
+pub async fn do_config_watch() -> Result<(), AdapterErr> {
+    supervise(TASK_CONFIG_WATCH, RestartPolicy::Always, config_watch);
+    Ok(())
+}
+
+// 定时检查配置文件的修改时间，变化后重新加载
+async fn config_watch() -> Result<(), AdapterErr> {
+    let mut last = modified_time(&Env::get_env(ADAPTER_NAME).get_conf_path());
+    let mut ticker = interval(Duration::from_secs(WATCH_SECS));
+    loop {
+        ticker.tick().await;
+        let path = Env::get_env(ADAPTER_NAME).get_conf_path();
+        let modified = modified_time(&path);
+        if modified == last {
+            continue;
+        }
+        last = modified;
+        log::info!("配置文件{path}已修改，重新加载");
+        if let Err(errors) = reload_config() {
+            for e in &errors {
+                log::error!("配置错误：{e}");
+            }
+            log::error!("配置校验失败，共{}个错误，继续使用原配置", errors.len());
+        }
+    }
+}
+
+fn modified_time(path: &str) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// 重新加载配置，校验失败时返回所有错误且原配置不变
+#[post("/api/v1/config/reload")]
+async fn config_reload() -> HttpResponse {
+    match reload_config() {
+        Ok(report) => {
+            let details = serde_json::to_value(&report).unwrap_or_default();
+            api_response(ApiResponse::success().with_details(details))
+        }
+        Err(errors) => {
+            let details = errors.iter().map(|e| e.to_string()).collect::<Vec<String>>();
+            api_response(ApiResponse::from_err(AdapterErr {
+                code: ErrCode::ConfigInvalid,
+                msg: format!("配置校验失败，共{}个错误，继续使用原配置", errors.len()),
+            }).with_details(serde_json::json!(details)))
+        }
+    }
+}
+
+pub fn config_reload_web_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(config_reload);
+}
@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+use std::sync::{Mutex, Once};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use actix_web::{get, web, HttpResponse};
+use actix_web::web::Bytes;
+use async_channel::{Sender, TrySendError};
+use chrono::Local;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use crate::ErrCode;
+use crate::db::outbox::OutboxKind;
+use crate::model::datacenter::CloudEventCmd;
+use crate::model::job::Job;
+
+// 每个订阅者缓存的事件数量，客户端读取过慢时丢弃新事件
+const SUBSCRIBER_CAPACITY: usize = 100;
+const HEARTBEAT_SECS: u64 = 15;
+
+type EventSender = Sender<Result<Bytes, actix_web::Error>>;
+
+static SUBSCRIBERS: Lazy<Mutex<Vec<(Option<HashSet<String>>, EventSender)>>> = Lazy::new(|| Mutex::new(vec![]));
+static EVENT_ID: AtomicU64 = AtomicU64::new(0);
+static HEARTBEAT: Once = Once::new();
+
+/// 推送给前端的适配器运行事件，type字段为事件类型
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AdapterEvent {
+    /// 配置更新任务的状态或阶段变化
+    Job { job: Job },
+    MqttConnection {
+        broker: String,
+        connected: bool,
+        error: Option<String>,
+    },
+    /// 策略或报表结果已发送到数据中心
+    ResultUploaded {
+        kind: OutboxKind,
+        topic: String,
+        message_id: u64,
+    },
+    CloudEventRequest {
+        token: String,
+        request_id: String,
+        cmd: CloudEventCmd,
+    },
+    CloudEventResponse {
+        token: String,
+        request_id: String,
+        /// 请求无法解析时为空
+        cmd: Option<CloudEventCmd>,
+        code: ErrCode,
+        msg: String,
+        stage: Option<String>,
+    },
+    /// 虚拟测点触发的第三方APP调用
+    AppApiInvoked {
+        point_id: u64,
+        app_url: String,
+        aoe_variable: String,
+        code: ErrCode,
+        msg: String,
+    },
+    /// 配置重新加载后发生变化
+    ConfigReloaded {
+        keys: Vec<String>,
+        restarted: Vec<String>,
+        restart_required: Vec<String>,
+    },
+}
+
+#[derive(Serialize, Debug)]
+struct EventMessage<'a> {
+    id: u64,
+    /// 事件时间，毫秒
+    time: i64,
+    #[serde(flatten)]
+    event: &'a AdapterEvent,
+}
+
+/// 发布事件，没有订阅者时直接丢弃
+pub fn publish(event: AdapterEvent) {
+    let mut subscribers = SUBSCRIBERS.lock().unwrap();
+    if subscribers.is_empty() {
+        return;
+    }
+    let id = EVENT_ID.fetch_add(1, Ordering::Relaxed) + 1;
+    let message = EventMessage { id, time: Local::now().timestamp_millis(), event: &event };
+    let Ok(value) = serde_json::to_value(&message) else {
+        return;
+    };
+    let event_type = value["type"].as_str().unwrap_or_default().to_string();
+    let bytes = Bytes::from(format!("id: {id}\nevent: {event_type}\ndata: {value}\n\n"));
+    subscribers.retain(|(types, s)| {
+        if types.as_ref().is_some_and(|t| !t.contains(&event_type)) {
+            return !s.is_closed();
+        }
+        match s.try_send(Ok(bytes.clone())) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => {
+                log::debug!("事件订阅者繁忙，丢弃事件{id}");
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        }
+    });
+}
+
+// 定时发送注释行，避免代理因连接空闲断开，同时清理已断开的订阅者
+async fn heartbeat() {
+    loop {
+        actix_rt::time::sleep(Duration::from_secs(HEARTBEAT_SECS)).await;
+        let mut subscribers = SUBSCRIBERS.lock().unwrap();
+        subscribers.retain(|(_, s)| !matches!(s.try_send(Ok(Bytes::from_static(b": ping\n\n"))), Err(TrySendError::Closed(_))));
+    }
+}
+
+#[derive(Deserialize)]
+struct EventQuery {
+    /// 只订阅指定类型的事件，多个类型以逗号分隔
+    types: Option<String>,
+}
+
+#[get("/api/v1/events")]
+async fn event_stream(query: web::Query<EventQuery>) -> HttpResponse {
+    HEARTBEAT.call_once(|| {
+        actix_rt::spawn(heartbeat());
+    });
+    let types = query.types.as_ref().map(|t| {
+        t.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect::<HashSet<String>>()
+    });
+    let (tx, rx) = async_channel::bounded(SUBSCRIBER_CAPACITY);
+    let _ = tx.try_send(Ok(Bytes::from_static(b": connected\n\n")));
+    SUBSCRIBERS.lock().unwrap().push((types, tx));
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        // 避免压缩中间件缓存事件
+        .insert_header(("Content-Encoding", "identity"))
+        .streaming(rx)
+}
+
+pub fn config_event_web_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(event_stream);
+}
@@ -0,0 +1,153 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+
+use actix_web::{get, web, HttpResponse};
+use chrono::Local;
+use serde::Serialize;
+
+use crate::ADAPTER_NAME;
+use crate::db::jobstore::JOBS;
+use crate::db::mydb;
+use crate::db::outbox::OUTBOX;
+use crate::env::Env;
+use crate::utils::httpclient::{BackendState, MEMS_CLIENT, PLCC_CLIENT};
+use crate::utils::mqttmanager::{mqtt_states, MqttConnState, ROLE_MAIN};
+use crate::utils::register_result;
+use crate::utils::supervisor::*;
+
+// 最近一次成功的时间，毫秒，0表示尚未成功
+static LAST_DATA_QUERY: AtomicI64 = AtomicI64::new(0);
+static LAST_AOE_UPLOAD: AtomicI64 = AtomicI64::new(0);
+static LAST_DFF_UPLOAD: AtomicI64 = AtomicI64::new(0);
+
+pub fn record_data_query() {
+    LAST_DATA_QUERY.store(Local::now().timestamp_millis(), Ordering::Relaxed);
+}
+
+pub fn record_result_upload(is_aoe: bool) {
+    let last = if is_aoe { &LAST_AOE_UPLOAD } else { &LAST_DFF_UPLOAD };
+    last.store(Local::now().timestamp_millis(), Ordering::Relaxed);
+}
+
+#[derive(Serialize, Debug)]
+pub struct LastActivity {
+    /// 最近一次成功的时间，毫秒
+    pub at: Option<i64>,
+    /// 距今的秒数
+    pub age_secs: Option<i64>,
+}
+
+impl LastActivity {
+    fn from(last: &AtomicI64) -> Self {
+        let at = Some(last.load(Ordering::Relaxed)).filter(|t| *t > 0);
+        let age_secs = at.map(|t| (Local::now().timestamp_millis() - t) / 1000);
+        LastActivity { at, age_secs }
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct DbState {
+    pub name: String,
+    pub path: String,
+    pub open: bool,
+}
+
+#[derive(Serialize, Debug)]
+pub struct HealthReport {
+    /// 数据库正常，后台任务都在运行、已完成或者没有连续多次重启失败
+    pub healthy: bool,
+    /// 在healthy的基础上，数据中心MQTT已连接、APP已注册且PLCC和MEMS可以登录，只在就绪检查中计算
+    pub ready: Option<bool>,
+    pub dbs: Vec<DbState>,
+    pub mqtt: Option<MqttConnState>,
+    /// 只在就绪检查中探测
+    pub plcc: Option<BackendState>,
+    pub mems: Option<BackendState>,
+    pub registered: bool,
+    pub last_data_query: LastActivity,
+    pub last_aoe_upload: LastActivity,
+    pub last_dff_upload: Option<LastActivity>,
+    pub tasks: Vec<TaskState>,
+}
+
+// 存活检查不探测PLCC和MEMS，也不建立MQTT连接，避免依赖的服务变慢导致服务被重启
+async fn health_report(probe: bool) -> HealthReport {
+    let env = Env::get_env(ADAPTER_NAME);
+    let use_mems = env.get_is_use_mems();
+    let db_dir = env.get_db_dir();
+    let outbox_dir = format!("{db_dir}_outbox");
+    let dbs = vec![
+        DbState { name: "parser".to_string(), open: !mydb::is_error_db_path(&db_dir), path: db_dir },
+        DbState { name: "outbox".to_string(), open: OUTBOX.is_some(), path: outbox_dir },
+        DbState { name: "job".to_string(), open: JOBS.is_open(), path: JOBS.path().to_string() },
+    ];
+    let mut expected = vec![TASK_PARSER, TASK_AOE_UPLOAD, TASK_KEEP_ALIVE, TASK_CLOUD_EVENT, TASK_APP_API_EVENT, TASK_CONFIG_WATCH];
+    if use_mems {
+        expected.extend([TASK_DFF_UPLOAD, TASK_MEMS_EVENT]);
+    }
+    let mut registry = task_states();
+    // 未出现在登记表中的常驻任务没有启动成功
+    for name in expected {
+        registry.entry(name.to_string()).or_insert_with(|| TaskState {
+            name: name.to_string(),
+            alive: false,
+            supervised: false,
+            completed: false,
+            restart_count: 0,
+            consecutive_failures: 0,
+            last_error: Some("任务未启动".to_string()),
+            started_at: None,
+            stopped_at: None,
+        });
+    }
+    let tasks = registry.into_values().collect::<Vec<TaskState>>();
+    let mqtt = mqtt_states().into_iter().find(|m| m.role == ROLE_MAIN);
+    let (plcc, mems) = if probe {
+        let plcc = PLCC_CLIENT.probe().await;
+        let mems = if use_mems { Some(MEMS_CLIENT.probe().await) } else { None };
+        (Some(plcc), mems)
+    } else {
+        (None, None)
+    };
+    let registered = register_result::get_result();
+
+    let healthy = dbs.iter().all(|d| d.open) && tasks.iter().all(|t| t.is_ok());
+    let ready = probe.then(|| healthy
+        && mqtt.as_ref().is_some_and(|m| m.connected)
+        && registered
+        && plcc.as_ref().is_some_and(|p| p.logged_in)
+        && mems.as_ref().is_none_or(|m| m.logged_in));
+    HealthReport {
+        healthy,
+        ready,
+        dbs,
+        mqtt,
+        plcc,
+        mems,
+        registered,
+        last_data_query: LastActivity::from(&LAST_DATA_QUERY),
+        last_aoe_upload: LastActivity::from(&LAST_AOE_UPLOAD),
+        last_dff_upload: use_mems.then(|| LastActivity::from(&LAST_DFF_UPLOAD)),
+        tasks,
+    }
+}
+
+/// 存活检查，数据库打不开或后台任务退出时返回503，需要重启
+#[get("/api/v1/health")]
+async fn health() -> HttpResponse {
+    let report = health_report(false).await;
+    let mut builder = if report.healthy { HttpResponse::Ok() } else { HttpResponse::ServiceUnavailable() };
+    builder.content_type("application/json").json(report)
+}
+
+/// 就绪检查，依赖的服务都可用时才返回200
+#[get("/api/v1/ready")]
+async fn ready() -> HttpResponse {
+    let report = health_report(true).await;
+    let mut builder = if report.ready == Some(true) { HttpResponse::Ok() } else { HttpResponse::ServiceUnavailable() };
+    builder.content_type("application/json").json(report)
+}
+
+pub fn config_health_web_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(health)
+    .service(ready);
+}
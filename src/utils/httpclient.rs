@@ -0,0 +1,317 @@
+use std::sync::RwLock;
+use std::time::Instant;
+
+use base64::{Engine, engine::general_purpose::STANDARD as b64_standard};
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use reqwest::{Client, Method, StatusCode};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use sha2::Sha256;
+use tokio::time::Duration;
+
+use crate::{ADAPTER_NAME, AdapterErr, ErrCode, URL_LOGIN};
+use crate::env::Env;
+use crate::utils::metrics::{inc_counter, observe, url_label, HTTP_REQUEST_DURATION, HTTP_REQUEST_ERRORS};
+
+const PASSWORD_V_KEY: &[u8] = b"zju-plcc";
+const HEADER_TOKEN: &str = "access-token";
+// 错误信息中保留的响应内容长度
+const BODY_LOG_LEN: usize = 256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub static PLCC_CLIENT: Lazy<ApiClient> = Lazy::new(|| ApiClient::new(Backend::Plcc));
+pub static MEMS_CLIENT: Lazy<ApiClient> = Lazy::new(|| ApiClient::new(Backend::Mems));
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    Plcc,
+    Mems,
+}
+
+impl Backend {
+    fn name(&self) -> &'static str {
+        match self {
+            Backend::Plcc => "PLCC",
+            Backend::Mems => "MEMS",
+        }
+    }
+
+    fn err_code(&self) -> ErrCode {
+        match self {
+            Backend::Plcc => ErrCode::PlccConnectErr,
+            Backend::Mems => ErrCode::MemsConnectErr,
+        }
+    }
+
+    // 服务地址、用户名和密码
+    fn server_and_user(&self) -> (String, String, String) {
+        let env = Env::get_env(ADAPTER_NAME);
+        match self {
+            Backend::Plcc => (env.get_plcc_server(), env.get_plcc_user(), env.get_plcc_pwd()),
+            Backend::Mems => (env.get_mems_server(), env.get_mems_user(), env.get_mems_pwd()),
+        }
+    }
+}
+
+/// 健康检查中PLCC或MEMS的状态
+#[derive(Serialize, Debug, Clone)]
+pub struct BackendState {
+    pub name: String,
+    pub reachable: bool,
+    pub logged_in: bool,
+    pub error: Option<String>,
+}
+
+struct CachedToken {
+    token: String,
+    expire_at: Instant,
+}
+
+/// PLCC或MEMS的HTTP客户端，缓存登录token，token过期或返回401时自动重新登录
+pub struct ApiClient {
+    backend: Backend,
+    // 超时配置变化后重建
+    client: RwLock<Client>,
+    token: RwLock<Option<CachedToken>>,
+}
+
+impl ApiClient {
+    pub fn new(backend: Backend) -> Self {
+        ApiClient {
+            backend,
+            client: RwLock::new(build_client(backend)),
+            token: RwLock::new(None),
+        }
+    }
+
+    /// 按当前配置重建客户端并清除token，服务地址、用户或超时配置变化后调用
+    pub fn reset(&self) {
+        *self.client.write().unwrap() = build_client(self.backend);
+        self.clear_token();
+        log::info!("{} http客户端已按新配置重建", self.backend.name());
+    }
+
+    fn client(&self) -> Client {
+        self.client.read().unwrap().clone()
+    }
+
+    /// 发送GET请求并解析返回的JSON
+    pub async fn get_json<T: DeserializeOwned>(&self, path: &str, action: &str) -> Result<T, AdapterErr> {
+        let body = self.request(Method::GET, path, None, action).await?;
+        serde_json::from_slice::<T>(&body).map_err(|e| self.err(format!("{action}失败，解析返回内容出错：{e}，内容：{}", truncate(&body))))
+    }
+
+    /// 发送JSON请求体，只关心是否成功
+    pub async fn send_json<B: Serialize + ?Sized>(&self, method: Method, path: &str, body: &B, action: &str) -> Result<(), AdapterErr> {
+        let body = serde_json::to_vec(body).map_err(|e| AdapterErr {
+            code: ErrCode::InternalErr,
+            msg: format!("{action}失败，序列化请求内容出错：{e}"),
+        })?;
+        self.request(method, path, Some(body), action).await.map(|_| ())
+    }
+
+    /// 发送原始请求体，body为空时不带请求体
+    pub async fn send_bytes(&self, method: Method, path: &str, body: Option<Vec<u8>>, action: &str) -> Result<(), AdapterErr> {
+        self.request(method, path, body, action).await.map(|_| ())
+    }
+
+    /// 获取可用的token，缓存过期时重新登录
+    pub async fn token(&self) -> Result<String, AdapterErr> {
+        if let Some(cached) = self.token.read().unwrap().as_ref() {
+            if cached.expire_at > Instant::now() {
+                return Ok(cached.token.clone());
+            }
+        }
+        self.relogin().await
+    }
+
+    /// 检查服务是否可达以及能否登录，token未过期时不重复登录
+    pub async fn probe(&self) -> BackendState {
+        let name = self.backend.name().to_string();
+        let (server, _, _) = self.backend.server_and_user();
+        if let Err(e) = self.client().get(&server).send().await {
+            return BackendState {
+                name,
+                reachable: false,
+                logged_in: false,
+                error: Some(format!("连接{server}出错：{e}")),
+            };
+        }
+        let error = self.token().await.err().map(|e| e.msg);
+        BackendState {
+            name,
+            reachable: true,
+            logged_in: error.is_none(),
+            error,
+        }
+    }
+
+    async fn relogin(&self) -> Result<String, AdapterErr> {
+        let token = self.login().await?;
+        let ttl = Env::get_env(ADAPTER_NAME).get_http_token_ttl();
+        *self.token.write().unwrap() = Some(CachedToken {
+            token: token.clone(),
+            expire_at: Instant::now() + Duration::from_secs(ttl),
+        });
+        Ok(token)
+    }
+
+    fn clear_token(&self) {
+        *self.token.write().unwrap() = None;
+    }
+
+    // 记录每个URL的耗时和失败次数，包括重试的时间
+    async fn request(&self, method: Method, path: &str, body: Option<Vec<u8>>, action: &str) -> Result<Vec<u8>, AdapterErr> {
+        let start = Instant::now();
+        let result = self.send_request(method, path, body, action).await;
+        let url = url_label(path);
+        let labels = [("backend", self.backend.name()), ("url", url.as_str())];
+        observe(HTTP_REQUEST_DURATION, &labels, start.elapsed().as_secs_f64());
+        if result.is_err() {
+            inc_counter(HTTP_REQUEST_ERRORS, &labels);
+        }
+        result
+    }
+
+    // GET和DELETE可以安全重试，其余请求只在token失效时重发一次
+    async fn send_request(&self, method: Method, path: &str, body: Option<Vec<u8>>, action: &str) -> Result<Vec<u8>, AdapterErr> {
+        let retry_num = if method == Method::GET || method == Method::DELETE {
+            Env::get_env(ADAPTER_NAME).get_http_retry_num()
+        } else {
+            0
+        };
+        let (server, _, _) = self.backend.server_and_user();
+        let url = format!("{server}/{path}");
+        let mut relogin = false;
+        let mut attempt = 0;
+        loop {
+            let token = self.token().await?;
+            let mut builder = self.client()
+                .request(method.clone(), &url)
+                .headers(get_header(&token));
+            if let Some(body) = &body {
+                builder = builder.body(body.clone());
+            }
+            let err = match builder.send().await {
+                Ok(response) => {
+                    let status = response.status();
+                    let content = response.bytes().await.map(|b| b.to_vec()).unwrap_or_default();
+                    if status.is_success() {
+                        return Ok(content);
+                    }
+                    if (status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN) && !relogin {
+                        log::warn!("{} token失效，重新登录", self.backend.name());
+                        self.clear_token();
+                        relogin = true;
+                        continue;
+                    }
+                    let err = self.err(format!("{action}失败，HTTP {status}：{}", truncate(&content)));
+                    if !status.is_server_error() {
+                        return Err(err);
+                    }
+                    err
+                }
+                Err(e) => self.err(format!("{action}失败，连接{}出错：{e}", self.backend.name())),
+            };
+            if attempt >= retry_num {
+                return Err(err);
+            }
+            attempt += 1;
+            log::warn!("{}，第{attempt}次重试", err.msg);
+            actix_rt::time::sleep(backoff(attempt)).await;
+        }
+    }
+
+    async fn login(&self) -> Result<String, AdapterErr> {
+        let name = self.backend.name();
+        let (server, user, pwd) = self.backend.server_and_user();
+        let login_url = format!("{server}/{URL_LOGIN}");
+        let body = json!((user, password_v_encode(pwd)));
+        let response = self.client()
+            .post(&login_url)
+            .json(&body)
+            .send().await
+            .map_err(|e| self.err(format!("{name}登录失败：{e}")))?;
+        let status = response.status();
+        let content = response.bytes().await.map(|b| b.to_vec()).unwrap_or_default();
+        if !status.is_success() {
+            return Err(self.err(format!("{name}登录失败，HTTP {status}：{}", truncate(&content))));
+        }
+        let (token, user_id, _user_name) = serde_json::from_slice::<(String, u16, String)>(&content)
+            .map_err(|e| self.err(format!("{name}登录失败，解析返回内容出错：{e}，内容：{}", truncate(&content))))?;
+        if user_id == 0 {
+            let error = match token.as_str() {
+                "password_error" => {
+                    "密码错误".to_string()
+                },
+                "login_count_exceeded" => {
+                    "密码连续错误超过5次，请5分钟后再试".to_string()
+                },
+                "user_expired" => {
+                    "此用户账号已过期".to_string()
+                },
+                _ => {
+                    "未知错误".to_string()
+                }
+            };
+            Err(self.err(error))
+        } else {
+            Ok(token)
+        }
+    }
+
+    fn err(&self, msg: String) -> AdapterErr {
+        AdapterErr {
+            code: self.backend.err_code(),
+            msg,
+        }
+    }
+}
+
+fn build_client(backend: Backend) -> Client {
+    let env = Env::get_env(ADAPTER_NAME);
+    Client::builder()
+        .connect_timeout(Duration::from_secs(env.get_http_connect_timeout()))
+        .timeout(Duration::from_secs(env.get_http_request_timeout()))
+        .build()
+        .unwrap_or_else(|e| {
+            log::error!("!!Failed to build {} http client: {e}", backend.name());
+            Client::new()
+        })
+}
+
+// 重试间隔依次为0.5s、1s、2s……，最长8s
+fn backoff(attempt: usize) -> Duration {
+    Duration::from_millis(500 * (1 << (attempt - 1).min(4)))
+}
+
+fn truncate(content: &[u8]) -> String {
+    let s = String::from_utf8_lossy(content);
+    if s.chars().count() > BODY_LOG_LEN {
+        format!("{}...", s.chars().take(BODY_LOG_LEN).collect::<String>())
+    } else {
+        s.to_string()
+    }
+}
+
+// 前端加密
+fn password_v_encode(password: String) -> String {
+    let mut mac = HmacSha256::new_from_slice(PASSWORD_V_KEY).expect("HMAC can take key of any size");
+    mac.update(password.as_bytes());
+    //加密算法
+    let result = b64_standard.encode(mac.finalize().into_bytes());
+    result
+}
+
+fn get_header(token: &str) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    if let Ok(v) = HeaderValue::from_str(token) {
+        headers.insert(HEADER_TOKEN, v);
+    }
+    headers
+}
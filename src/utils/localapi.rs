@@ -1,146 +1,34 @@
 use std::collections::HashMap;
-use reqwest::Client;
 use crate::model::datacenter::QueryDevResponseBody;
 use crate::model::north::AppApiParam;
-use crate::{AdapterErr, ErrCode, ADAPTER_NAME};
-use crate::env::Env;
+use crate::AdapterErr;
+use crate::parser::{call_parser, ParserOperation};
+
+// 映射直接从进程内的解析服务查询，HTTP接口只保留给外部调用
 
 pub async fn query_aoe_mapping() -> Result<HashMap<u64, u64>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let http_server_port = env.get_http_server_port();
-    let url = format!("http://localhost:{http_server_port}/api/v1/parser/aoe_mapping");
-    let client = Client::new();
-    match client
-        .get(&url)
-        .send().await {
-        Ok(response) => {
-            if let Ok(aoes_mapping) = response.json::<HashMap<u64, u64>>().await {
-                Ok(aoes_mapping)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::InternalErr,
-                    msg: "调用AOE映射API获取AOE映射失败".to_string(),
-                })
-            }
-        },
-        Err(ee) => {
-            log::error!("link to local api error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::InternalErr,
-                msg: "连接本地API失败：".to_string(),
-            })
-        }
-    }
+    call_parser(ParserOperation::GetAoeMapping).await.map_err(|e| with_action(e, "获取AOE映射"))
 }
 
 pub async fn query_dev_mapping() -> Result<Vec<QueryDevResponseBody>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let http_server_port = env.get_http_server_port();
-    let url = format!("http://localhost:{http_server_port}/api/v1/parser/dev_mapping");
-    let client = Client::new();
-    match client
-        .get(&url)
-        .send().await {
-        Ok(response) => {
-            if let Ok(devs) = response.json::<Vec<QueryDevResponseBody>>().await {
-                Ok(devs)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::InternalErr,
-                    msg: "调用设备列表API获取设备映射失败".to_string(),
-                })
-            }
-        },
-        Err(ee) => {
-            log::error!("link to local api error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::InternalErr,
-                msg: format!("连接本地API失败：{:?}", ee),
-            })
-        }
-    }
+    call_parser(ParserOperation::GetDevMapping).await.map_err(|e| with_action(e, "获取设备映射"))
 }
 
 pub async fn query_point_mapping() -> Result<HashMap<String, u64>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let http_server_port = env.get_http_server_port();
-    let url = format!("http://localhost:{http_server_port}/api/v1/parser/point_mapping");
-    let client = Client::new();
-    match client
-        .get(&url)
-        .send().await {
-        Ok(response) => {
-            if let Ok(point_mapping) = response.json::<HashMap<String, u64>>().await {
-                Ok(point_mapping)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::InternalErr,
-                    msg: "调用测点映射API获取测点映射失败".to_string(),
-                })
-            }
-        },
-        Err(ee) => {
-            log::error!("link to local api error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::InternalErr,
-                msg: "连接本地API失败：".to_string(),
-            })
-        }
-    }
+    call_parser(ParserOperation::GetPointMapping).await.map_err(|e| with_action(e, "获取测点映射"))
 }
 
 pub async fn query_dff_mapping() -> Result<HashMap<u64, u64>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let http_server_port = env.get_http_server_port();
-    let url = format!("http://localhost:{http_server_port}/api/v1/parser/dff_mapping");
-    let client = Client::new();
-    match client
-        .get(&url)
-        .send().await {
-        Ok(response) => {
-            if let Ok(dffs_mapping) = response.json::<HashMap<u64, u64>>().await {
-                Ok(dffs_mapping)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::InternalErr,
-                    msg: "调用DFF映射API获取DFF映射失败".to_string(),
-                })
-            }
-        },
-        Err(ee) => {
-            log::error!("link to local api error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::InternalErr,
-                msg: "连接本地API失败：".to_string(),
-            })
-        }
-    }
+    call_parser(ParserOperation::GetDffMapping).await.map_err(|e| with_action(e, "获取DFF映射"))
 }
 
 pub async fn query_app_api_mapping() -> Result<Vec<AppApiParam>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let http_server_port = env.get_http_server_port();
-    let url = format!("http://localhost:{http_server_port}/api/v1/parser/app_api_mapping");
-    let client = Client::new();
-    match client
-        .get(&url)
-        .send().await {
-        Ok(response) => {
-            if let Ok(app_api_map) = response.json::<Vec<AppApiParam>>().await {
-                Ok(app_api_map)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::InternalErr,
-                    msg: "调用第三方APP调用参数API获取第三方APP调用参数失败".to_string(),
-                })
-            }
-        },
-        Err(ee) => {
-            log::error!("link to local api error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::InternalErr,
-                msg: "连接本地API失败：".to_string(),
-            })
-        }
+    call_parser(ParserOperation::GetAppApiMapping).await.map_err(|e| with_action(e, "获取第三方APP映射"))
+}
+
+fn with_action(e: AdapterErr, action: &str) -> AdapterErr {
+    AdapterErr {
+        code: e.code,
+        msg: format!("{action}失败：{}", e.msg),
     }
 }
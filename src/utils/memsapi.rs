@@ -1,59 +1,137 @@
 use std::collections::{HashMap, HashSet};
 
-use rumqttc::AsyncClient;
-use reqwest::{Client, StatusCode};
-use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
-use serde_json::json;
-use base64::{Engine, engine::general_purpose::STANDARD as b64_standard};
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
+use reqwest::Method;
+use serde::Serialize;
+use chrono::{Local, TimeZone};
 use tokio::time::{interval, Duration};
 use crate::model::datacenter::MemsEventDffStatus;
 use crate::model::polars_to_json_df;
-use crate::model::south::{CommitNote, DffModel, DffResult, FlowOperation, SysAoes, SysPoints};
-use crate::model::north::{MyDffResult, MyPbActionResult, MyPbEventResult};
+use crate::model::south::{CommitNote, DfTriggerType, DffModel, DffResult, FlowOperation, SysAoes, SysPoints};
+use crate::model::north::{MyDffResult, MyPbActionResult, MyPbEventResult, SyncOutcome};
+use crate::utils::httpclient::MEMS_CLIENT;
+use crate::utils::is_same_model;
 use crate::utils::jsonmodel::from_serde_value_to_dff_model;
-use crate::utils::mqttclient::{get_mqttoptions, client_publish};
+use crate::utils::mqttmanager::{main_manager, MqttManager};
+use crate::db::outbox::{OutboxKind, OUTBOX, WATERMARK_AOE, WATERMARK_DFF};
+use crate::utils::events::{publish, AdapterEvent};
+use crate::utils::metrics::{inc_counter, RESULTS_UPLOADED};
+use crate::utils::health::record_result_upload;
+use crate::utils::supervisor::{supervise, RestartPolicy, TASK_AOE_UPLOAD, TASK_DFF_UPLOAD};
 use crate::utils::memsmqtt::{generate_dff_update, generate_dff_set};
 use crate::utils::plccmqtt::query_register_dev;
 use crate::utils::localapi::query_dff_mapping;
 
 use crate::model::{aoe_event_result_to_north, aoe_action_result_to_north};
 use crate::model::datacenter::CloudEventAoeStatus;
-use crate::model::south::{AoeControl, AoeModel, PbAoeResults};
+use crate::model::south::{AoeAction, AoeControl, AoeModel, PbAoeResult, PbAoeResults};
 use crate::model::north::MyPbAoeResult;
 use crate::utils::global::{PARAM_POINT_MAP, POINT_PARAM_MAP, MEMS_LAST_RESET_TIME};
 use crate::utils::plccmqtt::{generate_aoe_update, generate_aoe_set};
 use crate::utils::localapi::{query_aoe_mapping, query_point_mapping};
 
-use crate::{ADAPTER_NAME, AdapterErr, ErrCode, MODEL_FROZEN, URL_RUNNING_DFFS, URL_DFF_RESULTS, URL_DFFS, URL_LOGIN,
+use crate::{ADAPTER_NAME, AdapterErr, ErrCode, MODEL_FROZEN, URL_RUNNING_DFFS, URL_DFF_RESULTS, URL_DFFS,
     URL_MEMS_RESET, URL_DFF_START, URL_DFF_CONTROL, URL_UNRUN_DFFS, URL_IMPORT_POINTS, URL_POINTS_VERSION,
     URL_POINTS_APPLY, URL_POINTS, URL_AOES_VERSION, URL_AOES, URL_AOE_CONTROL, URL_AOE_RESULTS, URL_RUNNING_AOES,
     URL_UNRUN_AOES, URL_AOES_APPLY};
 use crate::env::Env;
 
-const PASSWORD_V_KEY: &[u8] = b"zju-plcc";
-const HEADER_TOKEN: &str = "access-token";
+/// 与MEMS中已有的报表比较，只删除、新增和修改有变化的报表，返回每个报表（南向id）的结果，
+/// 以及不重置MEMS时需要启动的报表，需要重置MEMS时为None
+pub async fn update_dffs(dffs: Vec<DffModel>) -> Result<(Vec<(u64, SyncOutcome)>, Option<Vec<u64>>), AdapterErr> {
+    let old_dffs = query_dffs().await?;
+    let old_map = old_dffs.iter().map(|v| (v.id, v)).collect::<HashMap<u64, &DffModel>>();
+    let new_map = dffs.iter().map(|v| (v.id, v.clone())).collect::<HashMap<u64, DffModel>>();
+    let (aids, to_save, outcomes) = diff_models(&old_dffs, dffs, |v| v.id);
+    let need_reset = outcomes.iter()
+        .filter(|(_, o)| *o != SyncOutcome::Unchanged)
+        .any(|(id, _)| old_map.get(id).is_some_and(|v| is_dff_reset_required(v))
+            || new_map.get(id).is_some_and(is_dff_reset_required));
+    let mut to_start = vec![];
+    if !need_reset {
+        // 先停止运行中的待删除和待修改报表，修改的报表保存后重新启动
+        let changed = outcomes.iter()
+            .filter(|(_, o)| matches!(o, SyncOutcome::Deleted | SyncOutcome::Edited))
+            .map(|(id, _)| *id)
+            .collect::<HashSet<u64>>();
+        let running_dffs = query_running_dffs().await?;
+        let stop_dffs = running_dffs.into_iter()
+            .filter(|id| changed.contains(id))
+            .collect::<Vec<u64>>();
+        for (id, outcome) in &outcomes {
+            match outcome {
+                SyncOutcome::Edited if stop_dffs.contains(id) => to_start.push(*id),
+                SyncOutcome::Added if new_map.get(id).is_some_and(is_dff_auto_start) => to_start.push(*id),
+                _ => {}
+            }
+        }
+        if !stop_dffs.is_empty() {
+            dff_action(FlowOperation::StopFlows(stop_dffs)).await?;
+        }
+    }
+    // 先保存再删除，保存失败时不会删除已有的报表
+    if !to_save.is_empty() {
+        save_dffs(to_save).await?;
+    }
+    if !aids.is_empty() {
+        delete_dffs(aids).await?;
+    }
+    Ok((outcomes, if need_reset { None } else { Some(to_start) }))
+}
 
-type HmacSha256 = Hmac<Sha256>;
+// 事件驱动、数据源驱动和写入策略变量的报表在MEMS加载全部配置时建立关联，增加、修改或删除这类报表需要重置MEMS；
+// 周期、定时和手动触发的报表可以单独停止和启动
+fn is_dff_reset_required(dff: &DffModel) -> bool {
+    matches!(dff.trigger_type, DfTriggerType::EventDrive(_) | DfTriggerType::DataSource) || dff.aoe_var.is_some()
+}
 
-pub async fn update_dffs(dffs: Vec<DffModel>) -> Result<(), AdapterErr> {
-    let token = login().await?;
-    let old_dffs = query_dffs(token.clone()).await?;
-    let aids = old_dffs.iter().map(|v| v.id).collect::<Vec<u64>>();
-    if !aids.is_empty() {
-        delete_dffs(token.clone(), aids).await?;
+// 新增的报表按is_on启动，手动触发的报表启动即执行，不自动启动
+fn is_dff_auto_start(dff: &DffModel) -> bool {
+    dff.is_on && dff.trigger_type != DfTriggerType::Manual
+}
+
+/// 不重置MEMS时，启动新增的报表和重新启动修改前运行中的报表，其余报表保持原有的运行状态
+pub async fn do_refresh_dffs(to_start: Vec<u64>) -> Result<(), AdapterErr> {
+    if to_start.is_empty() {
+        return Ok(());
+    }
+    dff_action(FlowOperation::StartFlows(to_start)).await
+}
+
+// 返回已移除模型的id、需要保存的模型以及每个模型的同步结果，修改的模型按id直接保存覆盖，不在删除列表中
+fn diff_models<T: Serialize>(old: &[T], new: Vec<T>, id: fn(&T) -> u64) -> (Vec<u64>, Vec<T>, Vec<(u64, SyncOutcome)>) {
+    let old_map = old.iter().map(|v| (id(v), v)).collect::<HashMap<u64, &T>>();
+    let new_ids = new.iter().map(id).collect::<HashSet<u64>>();
+    let mut to_delete = vec![];
+    let mut outcomes = vec![];
+    for v in old {
+        if !new_ids.contains(&id(v)) {
+            to_delete.push(id(v));
+            outcomes.push((id(v), SyncOutcome::Deleted));
+        }
     }
-    save_dffs(token, dffs).await
+    let mut to_save = vec![];
+    for v in new {
+        match old_map.get(&id(&v)) {
+            Some(old) if is_same_model(*old, &v) => outcomes.push((id(&v), SyncOutcome::Unchanged)),
+            Some(_) => {
+                outcomes.push((id(&v), SyncOutcome::Edited));
+                to_save.push(v);
+            }
+            None => {
+                outcomes.push((id(&v), SyncOutcome::Added));
+                to_save.push(v);
+            }
+        }
+    }
+    (to_delete, to_save, outcomes)
 }
 
 pub async fn do_start_all() -> Result<(), AdapterErr> {
-    let token = login().await?;
-    let aoes = query_aoes(token.clone()).await?;
+    let aoes = query_aoes().await?;
     let running_aoes = aoes.iter().map(|k| k.id).collect::<Vec<u64>>();
-    let dffs = query_dffs(token.clone()).await?;
+    let dffs = query_dffs().await?;
     let running_dffs = dffs.iter().map(|k| k.id).collect::<Vec<u64>>();
-    start_all(token, running_aoes, running_dffs).await
+    start_all(running_aoes, running_dffs).await
 }
 
 pub async fn do_reset_mems(
@@ -72,9 +150,8 @@ pub async fn do_reset_mems(
             break;
         }
     }
-    let token = login().await?;
     // 记录重置前的dff状态
-    let unrun_dffs = query_unrun_dffs(token.clone()).await?;
+    let unrun_dffs = query_unrun_dffs().await?;
     let unrun_dffs_north = unrun_dffs.iter().filter_map(|v| old_dff_mapping.get(v).cloned()).collect::<Vec<u64>>();
     let running_dffs = new_dff_mapping
         .iter()
@@ -86,7 +163,7 @@ pub async fn do_reset_mems(
             }
         }).collect::<Vec<u64>>();
     // 记录重置前的aoe状态
-    let unrun_aoes = query_unrun_aoes(token.clone()).await?;
+    let unrun_aoes = query_unrun_aoes().await?;
     let unrun_aoes_north = unrun_aoes.iter().filter_map(|v| old_aoe_mapping.get(v).cloned()).collect::<Vec<u64>>();
     let running_aoes = new_aoe_mapping
         .iter()
@@ -97,358 +174,173 @@ pub async fn do_reset_mems(
                 Some(*k)
             }
         }).collect::<Vec<u64>>();
-    reset(token, running_aoes, running_dffs).await
+    reset(running_aoes, running_dffs).await
 }
 
-async fn delete_dffs(token: String, ids: Vec<u64>) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
+async fn delete_dffs(ids: Vec<u64>) -> Result<(), AdapterErr> {
     let ids = ids.iter()
         .map(|n| n.to_string())
         .collect::<Vec<_>>()
         .join(",");
-    let url = format!("{mems_server}/{URL_DFFS}/{ids}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .delete(&url)
-        .headers(headers)
-        .json(&ids)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用报表API删除报表失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
-}
-
-async fn query_dffs(token: String) -> Result<Vec<DffModel>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_DFFS}_json");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        if let Ok(dffs_value) = response.json::<Vec<serde_json::Value>>().await {
-            let mut dffs = vec![];
-            for dff_value in dffs_value {
-                match from_serde_value_to_dff_model(&dff_value) {
-                    Ok(dff) => {
-                        dffs.push(dff);
-                    }
-                    Err(e) => {
-                        log::error!("from_serde_value_to_dff_model: {e}");
-                    },
-                }
+    let path = format!("{URL_DFFS}/{ids}");
+    MEMS_CLIENT.send_json(Method::DELETE, &path, &ids, "调用报表API删除报表").await
+}
+
+async fn query_dffs() -> Result<Vec<DffModel>, AdapterErr> {
+    let path = format!("{URL_DFFS}_json");
+    let dffs_value = MEMS_CLIENT.get_json::<Vec<serde_json::Value>>(&path, "调用报表API获取报表").await?;
+    let mut dffs = vec![];
+    for dff_value in dffs_value {
+        match from_serde_value_to_dff_model(&dff_value) {
+            Ok(dff) => {
+                dffs.push(dff);
             }
-            Ok(dffs)
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用报表API获取报表失败".to_string(),
-            })
+            Err(e) => {
+                log::error!("from_serde_value_to_dff_model: {e}");
+            },
         }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
     }
+    Ok(dffs)
 }
 
-async fn save_dffs(token: String, dffs: Vec<DffModel>) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_DFFS}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .json(&dffs)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用报表API新增报表失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn save_dffs(dffs: Vec<DffModel>) -> Result<(), AdapterErr> {
+    MEMS_CLIENT.send_json(Method::POST, URL_DFFS, &dffs, "调用报表API新增报表").await
 }
 
-async fn login() -> Result<String, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_user = env.get_mems_user();
-    let mems_pwd = env.get_mems_pwd();
-    let mems_server = env.get_mems_server();
-    let login_url = format!("{mems_server}/{URL_LOGIN}");
-    let body = json!((mems_user, password_v_encode(mems_pwd)));
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&login_url)
-        .json(&body)
-        .send().await {
-        let (token, user_id, _user_name) = response.json::<(String, u16, String)>().await.unwrap();
-        if user_id == 0 {
-            let error = match token.as_str() {
-                "password_error" => {
-                    "密码错误".to_string()
-                },
-                "login_count_exceeded" => {
-                    "密码连续错误超过5次，请5分钟后再试".to_string()
-                },
-                "user_expired" => {
-                    "此用户账号已过期".to_string()
-                },
-                _ => {
-                    "未知错误".to_string()
-                }
-            };
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: error,
-            })
-        } else {
-            Ok(token)
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "MEMS登录失败".to_string(),
-        })
-    }
+async fn reset(running_aoes: Vec<u64>, running_dffs: Vec<u64>) -> Result<(), AdapterErr> {
+    MEMS_CLIENT.send_bytes(Method::POST, URL_MEMS_RESET, None, "调用重置报表API").await?;
+    start_all(running_aoes, running_dffs).await
 }
 
-// 前端加密
-fn password_v_encode(password: String) -> String {
-    let mut mac = HmacSha256::new_from_slice(PASSWORD_V_KEY).expect("HMAC can take key of any size");
-    mac.update(password.as_bytes());
-    //加密算法
-    let result = b64_standard.encode(mac.finalize().into_bytes());
-    result
-}
-
-fn get_header(token: String) -> HeaderMap {
-    let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    headers.insert(HEADER_TOKEN, HeaderValue::from_str(&token).unwrap());
-    headers
-}
-
-async fn reset(token: String, running_aoes: Vec<u64>, running_dffs: Vec<u64>) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_MEMS_RESET}");
-    let headers = get_header(token.clone());
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            start_all(token, running_aoes, running_dffs).await
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用重置报表API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "连接MEMS失败".to_string(),
-        })
-    }
-}
-
-async fn start_all(token: String, running_aoes: Vec<u64>, running_dffs: Vec<u64>) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_DFF_START}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .json(&(running_aoes, running_dffs))
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用启用策略和报表API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn start_all(running_aoes: Vec<u64>, running_dffs: Vec<u64>) -> Result<(), AdapterErr> {
+    MEMS_CLIENT.send_json(Method::POST, URL_DFF_START, &(running_aoes, running_dffs), "调用启用策略和报表API").await
 }
 
 pub async fn dff_result_upload() -> Result<(), AdapterErr> {
-    tokio::spawn(async {
-        if let Err(e) = dff_upload_loop().await {
-            log::error!("do dff_result_upload error: {}", e.msg);
-        }
-    });
+    supervise(TASK_DFF_UPLOAD, RestartPolicy::Always, dff_upload_loop);
     Ok(())
 }
 
 async fn dff_upload_loop() -> Result<(), AdapterErr> {
     let env = Env::get_env(ADAPTER_NAME);
-    let mqtt_server = env.get_mqtt_server();
-    let mqtt_server_port = env.get_mqtt_server_port();
     let app_name = env.get_app_name();
     let frozen_model = MODEL_FROZEN.to_string();
 
-    let mut ticker = interval(Duration::from_secs(5));
-    let mut count = 0;
-    let mut token = "".to_string();
-    let mut last_time: HashMap<u64, u64> = HashMap::new();
+    let mut ticker = interval(Duration::from_secs(env.get_result_upload_interval()));
+    // 重启后从上次的上传进度继续
+    let mut last_time = OUTBOX.as_ref().map(|o| o.query_watermark(WATERMARK_DFF)).unwrap_or_default();
 
-    let mqttoptions = get_mqttoptions("mems_dff_result", &mqtt_server, mqtt_server_port);
+    let client = main_manager()?;
     let topic_request_update = format!("/sys.brd/{app_name}/S-dataservice/F-UpdateSOE");
     let topic_request_set = format!("/sys.dbc/{app_name}/S-dataservice/F-SetSOE");
-    let (client, mut eventloop) = AsyncClient::new(mqttoptions, 100);
-    tokio::spawn(async move {
-        loop {
-            match eventloop.poll().await {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("do dff_result_upload error: {:?}", e);
-                    break;
-                }
-            }
-        }
-    });
     loop {
         ticker.tick().await;
-        if count >= 86400 {
-            count = 0;
-        }
-        if count == 0 {
-            match login().await {
-                Ok(v) => token = v,
-                Err(_) => continue
-            }
-        }
-        count += 1;
-        if let Err(e) = do_dff_upload(&client, &topic_request_update, &topic_request_set, &token, &mut last_time, &frozen_model).await {
+        if let Err(e) = do_dff_upload(&client, &topic_request_update, &topic_request_set, &mut last_time, &frozen_model).await {
             log::error!("do dff_result_upload error: {}", e.msg);
         }
     }
 }
 
-async fn do_dff_upload(client: &AsyncClient, topic_request_update: &str, topic_request_set: &str, token: &str, last_time: &mut HashMap<u64, u64>, frozen_model: &str) -> Result<(), AdapterErr> {
+async fn do_dff_upload(client: &MqttManager, topic_request_update: &str, topic_request_set: &str, last_time: &mut HashMap<u64, u64>, frozen_model: &str) -> Result<(), AdapterErr> {
     let env = Env::get_env(ADAPTER_NAME);
     let app_name = env.get_app_name();
-    let my_dffs = query_dffs(token.to_string()).await?;
+    let my_dffs = query_dffs().await?;
     let aids = my_dffs.iter().map(|v| v.id).collect::<Vec<u64>>();
-    let dff_results = query_dff_results(token.to_string(), aids).await?;
+    let dff_results = query_dff_results(aids).await?;
     let dff_mapping = query_dff_mapping().await?;
+    // 结果写入待发送队列后才更新上传进度
+    let mut new_time = last_time.clone();
     let my_dff_result = dff_results.iter()
         .filter(|a| {
             let dff_id = a.flow_id;
             let end_time = a.end_time;
-            if let Some(v) = last_time.get_mut(&dff_id) {
+            if let Some(v) = new_time.get_mut(&dff_id) {
                 let old = v.clone();
                 *v = end_time;
                 old != end_time
             } else {
-                last_time.insert(dff_id, end_time);
+                new_time.insert(dff_id, end_time);
                 true
             }
-        }).map(|a|{
-            let flow_id = if let Some(nid) = dff_mapping.get(&a.flow_id) {
-                Some(nid.to_string())
-            } else {
-                None
-            };
-            MyDffResult {
-                flow_id,
-                start_time: Some(a.start_time),
-                end_time: Some(a.end_time),
-                result: polars_to_json_df(&a.result),
-            }
-        }).collect::<Vec<MyDffResult>>();
+        }).map(|a| to_my_dff_result(a, &dff_mapping)).collect::<Vec<MyDffResult>>();
     if !my_dff_result.is_empty() {
         let dev = query_register_dev().await?;
         let body = generate_dff_update(my_dff_result.clone(), frozen_model.to_string(), dev.clone(), app_name.clone());
-        let payload = serde_json::to_string(&body).unwrap();
-        client_publish(client, topic_request_update, &payload).await?;
+        let update = serde_json::to_string(&body).unwrap();
         let body = generate_dff_set(my_dff_result, frozen_model.to_string(), dev, app_name);
-        let payload = serde_json::to_string(&body).unwrap();
-        client_publish(client, topic_request_set, &payload).await?;
+        let set = serde_json::to_string(&body).unwrap();
+        let messages = vec![
+            (OutboxKind::DffUpdate, topic_request_update.to_string(), update),
+            (OutboxKind::DffSet, topic_request_set.to_string(), set),
+        ];
+        enqueue_results(client, messages, WATERMARK_DFF, &new_time).await?;
+    }
+    *last_time = new_time;
+    flush_outbox(client, false).await
+}
+
+/// 报表结果转换为北向格式，结果表编码为parquet
+pub fn to_my_dff_result(a: &DffResult, dff_mapping: &HashMap<u64, u64>) -> MyDffResult {
+    MyDffResult {
+        flow_id: dff_mapping.get(&a.flow_id).map(|nid| nid.to_string()),
+        start_time: Some(a.start_time),
+        end_time: Some(a.end_time),
+        result: polars_to_json_df(&a.result),
     }
-    Ok(())
 }
 
-async fn query_dff_results(token: String, ids: Vec<u64>) -> Result<Vec<DffResult>, AdapterErr> {
+/// 查询报表最近一次的执行结果，没有结果的报表不返回
+pub async fn do_query_dff_results(ids: Vec<u64>) -> Result<Vec<DffResult>, AdapterErr> {
     let mut results = vec![];
     for id in ids {
-        if let Ok(Some(dff_result)) = query_dff_result(token.clone(), id).await {
+        if let Some(dff_result) = query_dff_result(id).await? {
             results.push(dff_result);
         }
     }
     Ok(results)
 }
 
-async fn query_dff_result(token: String, id: u64) -> Result<Option<DffResult>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_DFF_RESULTS}?id={id}");
-    let headers = get_header(token);
-    let client = Client::new();
-    match client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        Ok(response) => {
-            if let Ok(dff_result) = response.json::<Option<DffResult>>().await {
-                Ok(dff_result)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::MemsConnectErr,
-                    msg: "调用API获取报表执行结果失败".to_string(),
-                })
+/// 立即执行手动触发的报表，其他触发方式的报表不允许执行
+pub async fn do_trigger_manual_dffs(ids: Vec<u64>) -> Result<(), AdapterErr> {
+    let dffs = query_dffs().await?;
+    for id in &ids {
+        match dffs.iter().find(|d| d.id == *id) {
+            Some(dff) if dff.trigger_type == DfTriggerType::Manual => {}
+            Some(dff) => {
+                return Err(AdapterErr {
+                    code: ErrCode::DffActionErr,
+                    msg: format!("报表{}不是手动触发", dff.name),
+                });
+            }
+            None => {
+                return Err(AdapterErr {
+                    code: ErrCode::DffIdNotFound,
+                    msg: format!("未找到报表{id}"),
+                });
             }
-        },
-        Err(ee) => {
-            log::error!("link to plcc error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: format!("failed to connect MEMS：{:?}", ee),
-            })
         }
     }
+    dff_action(FlowOperation::StartFlows(ids)).await
+}
+
+async fn query_dff_results(ids: Vec<u64>) -> Result<Vec<DffResult>, AdapterErr> {
+    let mut results = vec![];
+    for id in ids {
+        if let Ok(Some(dff_result)) = query_dff_result(id).await {
+            results.push(dff_result);
+        }
+    }
+    Ok(results)
+}
+
+async fn query_dff_result(id: u64) -> Result<Option<DffResult>, AdapterErr> {
+    let path = format!("{URL_DFF_RESULTS}?id={id}");
+    MEMS_CLIENT.get_json(&path, "调用API获取报表执行结果").await
 }
 
 pub async fn do_query_dff_status() -> Result<Vec<MemsEventDffStatus>, AdapterErr> {
-    let token = login().await?;
     let mut dff_status = vec![];
-    match query_unrun_dffs(token.clone()).await {
+    match query_unrun_dffs().await {
         Ok(unrun_dffs) => {
             for dff_id in unrun_dffs {
                 dff_status.push(MemsEventDffStatus {
@@ -461,7 +353,7 @@ pub async fn do_query_dff_status() -> Result<Vec<MemsEventDffStatus>, AdapterErr
             return Err(e);
         }
     }
-    match query_running_dffs(token.clone()).await {
+    match query_running_dffs().await {
         Ok(running_dffs) => {
             for dff_id in running_dffs {
                 dff_status.push(MemsEventDffStatus {
@@ -479,261 +371,120 @@ pub async fn do_query_dff_status() -> Result<Vec<MemsEventDffStatus>, AdapterErr
 }
 
 pub async fn do_query_unrun_dffs() -> Result<Vec<u64>, AdapterErr> {
-    let token = login().await?;
-    query_unrun_dffs(token).await
+    query_unrun_dffs().await
 }
 
-async fn query_unrun_dffs(token: String) -> Result<Vec<u64>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_UNRUN_DFFS}");
-    let headers = get_header(token);
-    let client = Client::new();
-    match client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        Ok(response) => {
-            if let Ok(dff_ids) = response.json::<Vec<u64>>().await {
-                Ok(dff_ids)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::MemsConnectErr,
-                    msg: "调用查询未运行报表API失败".to_string(),
-                })
-            }
-        },
-        Err(ee) => {
-            log::error!("link to plcc error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: format!("failed to connect MEMS：{:?}", ee),
-            })
-        }
-    }
+async fn query_unrun_dffs() -> Result<Vec<u64>, AdapterErr> {
+    MEMS_CLIENT.get_json(URL_UNRUN_DFFS, "调用查询未运行报表API").await
 }
 
-async fn query_running_dffs(token: String) -> Result<Vec<u64>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_RUNNING_DFFS}");
-    let headers = get_header(token);
-    let client = Client::new();
-    match client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        Ok(response) => {
-            if let Ok(dff_ids) = response.json::<Vec<u64>>().await {
-                Ok(dff_ids)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::MemsConnectErr,
-                    msg: "调用查询运行中报表API失败".to_string(),
-                })
-            }
-        },
-        Err(ee) => {
-            log::error!("link to plcc error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: format!("failed to connect MEMS：{:?}", ee),
-            })
-        }
-    }
+async fn query_running_dffs() -> Result<Vec<u64>, AdapterErr> {
+    MEMS_CLIENT.get_json(URL_RUNNING_DFFS, "调用查询运行中报表API").await
 }
 
 pub async fn do_dff_action(dff_control: FlowOperation) -> Result<(), AdapterErr> {
-    let token = login().await?;
-    dff_action(token, dff_control).await
+    dff_action(dff_control).await
 }
 
-async fn dff_action(token: String, dff_control: FlowOperation) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_DFF_CONTROL}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .json(&dff_control)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用执行报表动作API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn dff_action(dff_control: FlowOperation) -> Result<(), AdapterErr> {
+    MEMS_CLIENT.send_json(Method::POST, URL_DFF_CONTROL, &dff_control, "调用执行报表动作API").await
 }
 
 pub async fn do_query_aoes() -> Result<Vec<AoeModel>, AdapterErr> {
-    let token = login().await?;
-    query_aoes(token).await
+    query_aoes().await
+}
+
+/// 与MEMS中已有的策略比较，只删除、新增和修改有变化的策略，返回每个策略（南向id）的结果
+pub async fn update_aoes(aoes: Vec<AoeModel>) -> Result<Vec<(u64, SyncOutcome)>, AdapterErr> {
+    let old_aoes = query_aoes().await?;
+    let (aids, to_save, outcomes) = diff_models(&old_aoes, aoes, |v| v.id);
+    // 先停止运行中的待删除策略
+    let deleted = outcomes.iter()
+        .filter(|(_, o)| *o == SyncOutcome::Deleted)
+        .map(|(id, _)| *id)
+        .collect::<HashSet<u64>>();
+    if !deleted.is_empty() {
+        let running_aoes = query_running_aoes().await?;
+        let actions = running_aoes.into_iter()
+            .filter(|id| deleted.contains(id))
+            .map(AoeAction::StopAoe)
+            .collect::<Vec<AoeAction>>();
+        if !actions.is_empty() {
+            aoe_action(AoeControl { AoeActions: actions }).await?;
+        }
+    }
+    // 先保存再删除，保存失败时不会删除已有的策略
+    if !to_save.is_empty() {
+        save_aoes(to_save).await?;
+    }
+    if !aids.is_empty() {
+        delete_aoes(aids).await?;
+    }
+    Ok(outcomes)
 }
 
-pub async fn update_aoes(aoes: Vec<AoeModel>) -> Result<(), AdapterErr> {
-    let token = login().await?;
-    let old_aoes = query_aoes(token.clone()).await?;
-    let aids = old_aoes.iter().map(|v| v.id).collect::<Vec<u64>>();
-    if !aids.is_empty() {
-        delete_aoes(token.clone(), aids).await?;
+/// 不重置MEMS时，替换运行中的已修改策略并启动新增的策略，其余策略保持原有的运行状态
+pub async fn do_refresh_aoes(edited: Vec<AoeModel>, added: Vec<u64>) -> Result<(), AdapterErr> {
+    let mut actions = edited.into_iter().map(AoeAction::UpdateAoe).collect::<Vec<AoeAction>>();
+    actions.extend(added.into_iter().map(AoeAction::StartAoe));
+    if actions.is_empty() {
+        return Ok(());
     }
-    save_aoes(token, aoes).await
+    aoe_action(AoeControl { AoeActions: actions }).await
 }
 
-async fn delete_aoes(token: String, ids: Vec<u64>) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
+async fn delete_aoes(ids: Vec<u64>) -> Result<(), AdapterErr> {
     let ids = ids.iter()
         .map(|n| n.to_string())
         .collect::<Vec<_>>()
         .join(",");
-    let url = format!("{mems_server}/{URL_AOES}/{ids}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .delete(&url)
-        .headers(headers)
-        .json(&ids)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用策略API删除策略失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+    let path = format!("{URL_AOES}/{ids}");
+    MEMS_CLIENT.send_json(Method::DELETE, &path, &ids, "调用策略API删除策略").await
 }
 
-async fn query_aoes(token: String) -> Result<Vec<AoeModel>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_AOES}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        if let Ok(aoes) = response.json::<Vec<AoeModel>>().await {
-            Ok(aoes)
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用策略API获取策略失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn query_aoes() -> Result<Vec<AoeModel>, AdapterErr> {
+    MEMS_CLIENT.get_json(URL_AOES, "调用策略API获取策略").await
 }
 
-async fn save_aoes(token: String, aoes: Vec<AoeModel>) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_AOES}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .json(&aoes)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用策略API新增策略失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn save_aoes(aoes: Vec<AoeModel>) -> Result<(), AdapterErr> {
+    MEMS_CLIENT.send_json(Method::POST, URL_AOES, &aoes, "调用策略API新增策略").await
 }
 
 pub async fn aoe_result_upload() -> Result<(), AdapterErr> {
-    tokio::spawn(async {
-        if let Err(e) = aoe_upload_loop().await {
-            log::error!("do aoe_result_upload error: {}", e.msg);
-        }
-    });
+    supervise(TASK_AOE_UPLOAD, RestartPolicy::Always, aoe_upload_loop);
     Ok(())
 }
 
 async fn aoe_upload_loop() -> Result<(), AdapterErr> {
     let env = Env::get_env(ADAPTER_NAME);
-    let mqtt_server = env.get_mqtt_server();
-    let mqtt_server_port = env.get_mqtt_server_port();
     let app_name = env.get_app_name();
     let frozen_model = MODEL_FROZEN.to_string();
 
-    let mut ticker = interval(Duration::from_secs(5));
-    let mut count = 0;
-    let mut token = "".to_string();
-    let mut last_time: HashMap<u64, u64> = HashMap::new();
+    let mut ticker = interval(Duration::from_secs(env.get_result_upload_interval()));
+    // 重启后从上次的上传进度继续
+    let mut last_time = OUTBOX.as_ref().map(|o| o.query_watermark(WATERMARK_AOE)).unwrap_or_default();
 
-    let mqttoptions = get_mqttoptions("plcc_aoe_result", &mqtt_server, mqtt_server_port);
+    let client = main_manager()?;
     let topic_request_update = format!("/sys.brd/{app_name}/S-dataservice/F-UpdateSOE");
     let topic_request_set = format!("/sys.dbc/{app_name}/S-dataservice/F-SetSOE");
-    let (client, mut eventloop) = AsyncClient::new(mqttoptions, 100);
-    tokio::spawn(async move {
-        loop {
-            match eventloop.poll().await {
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("do aoe_result_upload error: {:?}", e);
-                    break;
-                }
-            }
-        }
-    });
     loop {
         ticker.tick().await;
-        if count >= 86400 {
-            count = 0;
-        }
-        if count == 0 {
-            match login().await {
-                Ok(v) => token = v,
-                Err(_) => continue
-            }
-        }
-        count += 1;
-        if let Err(e) = do_aoe_upload(&client, &topic_request_update, &topic_request_set, &token, &mut last_time, &frozen_model).await {
+        if let Err(e) = do_aoe_upload(&client, &topic_request_update, &topic_request_set, &mut last_time, &frozen_model).await {
             log::error!("do aoe_result_upload error: {}", e.msg);
         }
     }
 }
 
-async fn do_aoe_upload(client: &AsyncClient, topic_request_update: &str, topic_request_set: &str, token: &str, last_time: &mut HashMap<u64, u64>, frozen_model: &str) -> Result<(), AdapterErr> {
+async fn do_aoe_upload(client: &MqttManager, topic_request_update: &str, topic_request_set: &str, last_time: &mut HashMap<u64, u64>, frozen_model: &str) -> Result<(), AdapterErr> {
     let env = Env::get_env(ADAPTER_NAME);
     let app_name = env.get_app_name();
-    let my_aoes = query_aoes(token.to_string()).await?;
+    let my_aoes = query_aoes().await?;
     let aids = my_aoes.iter().map(|v| v.id).collect::<Vec<u64>>();
-    let aoe_results = query_aoe_result(token.to_string(), aids).await?;
+    let aoe_results = if env.get_aoe_catch_up() {
+        query_aoe_results_since(&aids, last_time).await?
+    } else {
+        query_aoe_result(aids).await?.results
+    };
     // 查询映射，如果映射为空，则从数据库填充
     let mut points_mapping = POINT_PARAM_MAP.get_all();
     if points_mapping.is_empty() {
@@ -744,16 +495,18 @@ async fn do_aoe_upload(client: &AsyncClient, topic_request_update: &str, topic_r
         points_mapping = point_param_map;
     }
     let aoe_mapping = query_aoe_mapping().await?;
-    let my_aoe_result = aoe_results.results.iter()
+    // 结果写入待发送队列后才更新上传进度
+    let mut new_time = last_time.clone();
+    let my_aoe_result = aoe_results.iter()
         .filter(|a| {
             let aoe_id = a.aoe_id.unwrap();
             let end_time = a.end_time.unwrap();
-            if let Some(v) = last_time.get_mut(&aoe_id) {
+            if let Some(v) = new_time.get_mut(&aoe_id) {
                 let old = v.clone();
                 *v = end_time;
                 old != end_time
             } else {
-                last_time.insert(aoe_id, end_time);
+                new_time.insert(aoe_id, end_time);
                 true
             }
         })
@@ -784,54 +537,122 @@ async fn do_aoe_upload(client: &AsyncClient, topic_request_update: &str, topic_r
     if !my_aoe_result.is_empty() {
         let dev = query_register_dev().await?;
         let body = generate_aoe_update(my_aoe_result.clone(), frozen_model.to_string(), dev.clone(), app_name.clone());
-        let payload = serde_json::to_string(&body).unwrap();
-        client_publish(client, topic_request_update, &payload).await?;
+        let update = serde_json::to_string(&body).unwrap();
         let body = generate_aoe_set(my_aoe_result, frozen_model.to_string(), dev, app_name);
-        let payload = serde_json::to_string(&body).unwrap();
-        client_publish(client, topic_request_set, &payload).await?;
+        let set = serde_json::to_string(&body).unwrap();
+        let messages = vec![
+            (OutboxKind::AoeUpdate, topic_request_update.to_string(), update),
+            (OutboxKind::AoeSet, topic_request_set.to_string(), set),
+        ];
+        enqueue_results(client, messages, WATERMARK_AOE, &new_time).await?;
+    }
+    *last_time = new_time;
+    flush_outbox(client, true).await
+}
+
+// 先写入待发送队列再发布，数据库不可用时直接发布
+async fn enqueue_results(client: &MqttManager, messages: Vec<(OutboxKind, String, String)>, watermark_key: &str, watermark: &HashMap<u64, u64>) -> Result<(), AdapterErr> {
+    if let Some(outbox) = OUTBOX.as_ref() {
+        outbox.enqueue(messages, watermark_key, watermark)
+    } else {
+        for (_, topic, payload) in messages {
+            client.publish(&topic, &payload).await?;
+        }
+        Ok(())
+    }
+}
+
+/// 退出前尽量发送待发送队列中的结果，未发送的保留到下次启动
+pub async fn flush_pending_uploads() {
+    let Ok(client) = main_manager() else {
+        return;
+    };
+    let use_mems = Env::get_env(ADAPTER_NAME).get_is_use_mems();
+    for is_aoe in [true, false] {
+        if !is_aoe && !use_mems {
+            continue;
+        }
+        if let Err(e) = flush_outbox(&client, is_aoe).await {
+            log::warn!("退出前发送结果失败：{}", e.msg);
+        }
+    }
+    if let Some(outbox) = OUTBOX.as_ref() {
+        outbox.flush();
+    }
+}
+
+// 按顺序发送到期的消息，收到broker的确认后才从队列中删除，失败时按退避时间重试
+async fn flush_outbox(client: &MqttManager, is_aoe: bool) -> Result<(), AdapterErr> {
+    let Some(outbox) = OUTBOX.as_ref() else {
+        return Ok(());
+    };
+    if !client.is_connected() {
+        return Ok(());
+    }
+    for message in outbox.due_messages(is_aoe) {
+        match client.publish_confirmed(&message.topic, &message.payload).await {
+            Ok(_) => {
+                outbox.remove(message.id);
+                record_result_upload(is_aoe);
+                inc_counter(RESULTS_UPLOADED, &[("kind", &format!("{:?}", message.kind))]);
+                publish(AdapterEvent::ResultUploaded {
+                    kind: message.kind,
+                    topic: message.topic,
+                    message_id: message.id,
+                });
+            }
+            Err(e) => {
+                log::warn!("发送{:?}失败，第{}次重试: {}", message.kind, message.attempts + 1, e.msg);
+                outbox.mark_failed(message);
+                return Err(e);
+            }
+        }
     }
     Ok(())
 }
 
-async fn query_aoe_result(token: String, ids: Vec<u64>) -> Result<PbAoeResults, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
+async fn query_aoe_result(ids: Vec<u64>) -> Result<PbAoeResults, AdapterErr> {
     let ids = ids.iter()
         .map(|n| n.to_string())
         .collect::<Vec<_>>()
         .join(",");
     let today = chrono::Local::now().format("%Y-%m-%d").to_string();
-    let url = format!("{mems_server}/{URL_AOE_RESULTS}?id={ids}&date={today}&last_only=true");
-    let headers = get_header(token);
-    let client = Client::new();
-    match client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        Ok(response) => {
-            if let Ok(aoe_results) = response.json::<PbAoeResults>().await {
-                Ok(aoe_results)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::MemsConnectErr,
-                    msg: "调用API获取策略执行结果失败".to_string(),
-                })
-            }
-        },
-        Err(ee) => {
-            log::error!("link to plcc error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: format!("failed to connect MEMS：{:?}", ee),
-            })
+    let path = format!("{URL_AOE_RESULTS}?id={ids}&date={today}&last_only=true");
+    MEMS_CLIENT.get_json(&path, "调用API获取策略执行结果").await
+}
+
+/// 逐个策略查询从上次上传的结束时间到现在的所有执行结果，可跨越多天，按结束时间排序
+async fn query_aoe_results_since(ids: &[u64], last_time: &HashMap<u64, u64>) -> Result<Vec<PbAoeResult>, AdapterErr> {
+    let max_days = Env::get_env(ADAPTER_NAME).get_aoe_catch_up_max_days();
+    let today = Local::now().date_naive();
+    let earliest = today - chrono::Days::new(max_days.max(0) as u64);
+    let mut results = vec![];
+    for id in ids {
+        let watermark = last_time.get(id).copied();
+        let mut date = watermark
+            .and_then(|t| Local.timestamp_millis_opt(t as i64).single())
+            .map(|t| t.date_naive())
+            .unwrap_or(today)
+            .max(earliest);
+        while date <= today {
+            let path = format!("{URL_AOE_RESULTS}?id={id}&date={}", date.format("%Y-%m-%d"));
+            let day_results: PbAoeResults = MEMS_CLIENT.get_json(&path, "调用API获取策略执行结果").await?;
+            results.extend(day_results.results.into_iter().filter(|r| {
+                match (watermark, r.end_time) {
+                    (Some(w), Some(end_time)) => end_time > w,
+                    (_, end_time) => end_time.is_some(),
+                }
+            }));
+            date = date + chrono::Days::new(1);
         }
     }
+    results.sort_by_key(|r| r.end_time);
+    Ok(results)
 }
 
 pub async fn do_query_aoe_status() -> Result<Vec<CloudEventAoeStatus>, AdapterErr> {
-    let token = login().await?;
     let mut aoe_status = vec![];
-    match query_unrun_aoes(token.clone()).await {
+    match query_unrun_aoes().await {
         Ok(unrun_aoes) => {
             for aoe_id in unrun_aoes {
                 aoe_status.push(CloudEventAoeStatus {
@@ -844,7 +665,7 @@ pub async fn do_query_aoe_status() -> Result<Vec<CloudEventAoeStatus>, AdapterEr
             return Err(e);
         }
     }
-    match query_running_aoes(token.clone()).await {
+    match query_running_aoes().await {
         Ok(running_aoes) => {
             for aoe_id in running_aoes {
                 aoe_status.push(CloudEventAoeStatus {
@@ -861,110 +682,30 @@ pub async fn do_query_aoe_status() -> Result<Vec<CloudEventAoeStatus>, AdapterEr
     Ok(aoe_status)
 }
 
-async fn query_unrun_aoes(token: String) -> Result<Vec<u64>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_UNRUN_AOES}");
-    let headers = get_header(token);
-    let client = Client::new();
-    match client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        Ok(response) => {
-            if let Ok(aoe_ids) = response.json::<Vec<u64>>().await {
-                Ok(aoe_ids)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::MemsConnectErr,
-                    msg: "调用查询未运行策略API失败".to_string(),
-                })
-            }
-        },
-        Err(ee) => {
-            log::error!("link to plcc error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: format!("failed to connect MEMS：{:?}", ee),
-            })
-        }
-    }
+async fn query_unrun_aoes() -> Result<Vec<u64>, AdapterErr> {
+    MEMS_CLIENT.get_json(URL_UNRUN_AOES, "调用查询未运行策略API").await
 }
 
-async fn query_running_aoes(token: String) -> Result<Vec<u64>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_RUNNING_AOES}");
-    let headers = get_header(token);
-    let client = Client::new();
-    match client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        Ok(response) => {
-            if let Ok(aoe_ids) = response.json::<Vec<u64>>().await {
-                Ok(aoe_ids)
-            } else {
-                Err(AdapterErr {
-                    code: ErrCode::MemsConnectErr,
-                    msg: "调用查询运行中策略API失败".to_string(),
-                })
-            }
-        },
-        Err(ee) => {
-            log::error!("link to plcc error: {:?}", ee);
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: format!("failed to connect MEMS：{:?}", ee),
-            })
-        }
-    }
+async fn query_running_aoes() -> Result<Vec<u64>, AdapterErr> {
+    MEMS_CLIENT.get_json(URL_RUNNING_AOES, "调用查询运行中策略API").await
 }
 
 pub async fn do_aoe_action(aoe_control: AoeControl) -> Result<(), AdapterErr> {
-    let token = login().await?;
-    aoe_action(token, aoe_control).await
+    aoe_action(aoe_control).await
 }
 
-async fn aoe_action(token: String, aoe_control: AoeControl) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_AOE_CONTROL}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .json(&aoe_control)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用启停策略API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn aoe_action(aoe_control: AoeControl) -> Result<(), AdapterErr> {
+    MEMS_CLIENT.send_json(Method::POST, URL_AOE_CONTROL, &aoe_control, "调用启停策略API").await
 }
 
 pub async fn do_import_points(point_ids: Vec<u64>) -> Result<(), AdapterErr> {
-    let token = login().await?;
-    import_points(token, point_ids).await
+    import_points(point_ids).await
 }
 
-async fn import_points(token: String, point_ids: Vec<u64>) -> Result<(), AdapterErr> {
+async fn import_points(point_ids: Vec<u64>) -> Result<(), AdapterErr> {
     let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
     let plcc_bee_id = env.get_plcc_beeid();
-    let url = format!("{mems_server}/{URL_IMPORT_POINTS}/{plcc_bee_id}");
-    let headers = get_header(token);
-    let client = Client::new();
+    let path = format!("{URL_IMPORT_POINTS}/{plcc_bee_id}");
     let data = point_ids
         .iter()
         .map(|x| x.to_string())
@@ -972,269 +713,71 @@ async fn import_points(token: String, point_ids: Vec<u64>) -> Result<(), Adapter
         .join(",");
     let data = format!("{:?}", data);
     let body = data.as_bytes().to_vec();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .body(body)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用同步测点API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+    MEMS_CLIENT.send_bytes(Method::POST, &path, Some(body), "调用同步测点API").await
 }
 
 pub async fn do_apply_current_aoes() -> Result<(), AdapterErr> {
-    let token = login().await?;
-    let notes = query_aoes_versions(token.clone()).await?;
+    let notes = query_aoes_versions().await?;
     let version = generate_new_id(&notes.iter().map(|v| v.version).collect::<Vec<u32>>());
     let note = CommitNote {
         version,
         note: "auto apply".to_string(),
         tree_id: "memsAoeSettingTreeId".to_string(),
     };
-    add_aoes_version(token.clone(), note).await?;
-    let aoes = query_apply_aoes(token.clone(), version).await?;
-    apply_aoes_version(token, aoes).await?;
+    add_aoes_version(note).await?;
+    let aoes = query_apply_aoes(version).await?;
+    apply_aoes_version(aoes).await?;
     Ok(())
 }
 
-async fn query_aoes_versions(token: String) -> Result<Vec<CommitNote>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_AOES_VERSION}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        if let Ok(notes) = response.json::<Vec<CommitNote>>().await {
-            Ok(notes)
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用查询所有策略版本API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn query_aoes_versions() -> Result<Vec<CommitNote>, AdapterErr> {
+    MEMS_CLIENT.get_json(URL_AOES_VERSION, "调用查询所有策略版本API").await
 }
 
-async fn add_aoes_version(token: String, note: CommitNote) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_AOES_VERSION}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .json(&note)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用策略版本添加API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn add_aoes_version(note: CommitNote) -> Result<(), AdapterErr> {
+    MEMS_CLIENT.send_json(Method::POST, URL_AOES_VERSION, &note, "调用策略版本添加API").await
 }
 
-async fn query_apply_aoes(token: String, version: u32) -> Result<SysAoes, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_AOES}/for_apply?version={version}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        if let Ok(aoes) = response.json::<SysAoes>().await {
-            Ok(aoes)
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用根据版本查询策略API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn query_apply_aoes(version: u32) -> Result<SysAoes, AdapterErr> {
+    let path = format!("{URL_AOES}/for_apply?version={version}");
+    MEMS_CLIENT.get_json(&path, "调用根据版本查询策略API").await
 }
 
-async fn apply_aoes_version(token: String, aoes: SysAoes) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_AOES_APPLY}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .json(&aoes)
-        .headers(headers)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用策略应用API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn apply_aoes_version(aoes: SysAoes) -> Result<(), AdapterErr> {
+    MEMS_CLIENT.send_json(Method::POST, URL_AOES_APPLY, &aoes, "调用策略应用API").await
 }
 
 pub async fn do_apply_current_points() -> Result<(), AdapterErr> {
     let env = Env::get_env(ADAPTER_NAME);
     let mems_bee_id = env.get_mems_beeid();
-    let token = login().await?;
-    let notes = query_points_versions(token.clone()).await?;
+    let notes = query_points_versions().await?;
     let version = generate_new_id(&notes.iter().map(|v| v.version).collect::<Vec<u32>>());
     let note = CommitNote {
         version,
         note: "auto apply".to_string(),
         tree_id: format!("{mems_bee_id}_point_version"),
     };
-    add_points_version(token.clone(), note).await?;
-    let points = query_apply_points(token.clone(), version).await?;
-    apply_points_version(token, points).await?;
+    add_points_version(note).await?;
+    let points = query_apply_points(version).await?;
+    apply_points_version(points).await?;
     Ok(())
 }
 
-async fn query_points_versions(token: String) -> Result<Vec<CommitNote>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_POINTS_VERSION}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        if let Ok(notes) = response.json::<Vec<CommitNote>>().await {
-            Ok(notes)
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用查询所有测点版本API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn query_points_versions() -> Result<Vec<CommitNote>, AdapterErr> {
+    MEMS_CLIENT.get_json(URL_POINTS_VERSION, "调用查询所有测点版本API").await
 }
 
-async fn add_points_version(token: String, note: CommitNote) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_POINTS_VERSION}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .json(&note)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用测点版本添加API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn add_points_version(note: CommitNote) -> Result<(), AdapterErr> {
+    MEMS_CLIENT.send_json(Method::POST, URL_POINTS_VERSION, &note, "调用测点版本添加API").await
 }
 
-async fn query_apply_points(token: String, version: u32) -> Result<SysPoints, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_POINTS}/for_apply?version={version}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        if let Ok(points) = response.json::<SysPoints>().await {
-            Ok(points)
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用根据版本查询测点API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn query_apply_points(version: u32) -> Result<SysPoints, AdapterErr> {
+    let path = format!("{URL_POINTS}/for_apply?version={version}");
+    MEMS_CLIENT.get_json(&path, "调用根据版本查询测点API").await
 }
 
-async fn apply_points_version(token: String, points: SysPoints) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mems_server = env.get_mems_server();
-    let url = format!("{mems_server}/{URL_POINTS_APPLY}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .json(&points)
-        .headers(headers)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::MemsConnectErr,
-                msg: "调用测点应用API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::MemsConnectErr,
-            msg: "failed to connect MEMS".to_string(),
-        })
-    }
+async fn apply_points_version(points: SysPoints) -> Result<(), AdapterErr> {
+    MEMS_CLIENT.send_json(Method::POST, URL_POINTS_APPLY, &points, "调用测点应用API").await
 }
 
 /// 生成新的ID
@@ -1256,3 +799,48 @@ fn generate_new_id(current_keys: &[u32]) -> u32 {
         1
     }
 }
+
+#[test]
+fn test_diff_models() {
+    let old = vec![(1u64, "a"), (2, "b"), (3, "c")];
+    let new = vec![(1u64, "a"), (2, "bb"), (4, "d")];
+    let (to_delete, to_save, outcomes) = diff_models(&old, new, |v| v.0);
+    // 修改的模型不在删除列表中
+    assert_eq!(to_delete, vec![3]);
+    assert_eq!(to_save, vec![(2, "bb"), (4, "d")]);
+    assert_eq!(outcomes, vec![
+        (3, SyncOutcome::Deleted),
+        (1, SyncOutcome::Unchanged),
+        (2, SyncOutcome::Edited),
+        (4, SyncOutcome::Added),
+    ]);
+    let (to_delete, to_save, _) = diff_models(&old, old.clone(), |v| v.0);
+    assert!(to_delete.is_empty() && to_save.is_empty());
+}
+
+#[test]
+fn test_is_dff_reset_required() {
+    let mut dff = DffModel {
+        id: 1,
+        is_on: true,
+        name: "dff".to_string(),
+        trigger_type: DfTriggerType::SimpleRepeat(Duration::from_secs(60)),
+        nodes: vec![],
+        actions: vec![],
+        save_mode: crate::model::south::DfSaveMode::Never,
+        aoe_var: None,
+    };
+    assert!(!is_dff_reset_required(&dff));
+    dff.trigger_type = DfTriggerType::TimeDrive("0 0 * * * *".to_string());
+    assert!(!is_dff_reset_required(&dff));
+    dff.trigger_type = DfTriggerType::Manual;
+    assert!(!is_dff_reset_required(&dff));
+    // 写入策略变量
+    dff.aoe_var = Some((1, "x".to_string()));
+    assert!(is_dff_reset_required(&dff));
+    dff.aoe_var = None;
+    dff.trigger_type = DfTriggerType::DataSource;
+    assert!(is_dff_reset_required(&dff));
+    dff.trigger_type = DfTriggerType::EventDrive(Default::default());
+    assert!(is_dff_reset_required(&dff));
+}
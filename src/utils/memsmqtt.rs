@@ -4,22 +4,22 @@ use std::collections::{HashSet, HashMap};
 use chrono::{Local, TimeZone, FixedOffset, Duration as ChronoDuration};
 use tokio_cron_scheduler::{Job, JobScheduler};
 
-use crate::utils::memsapi::{do_query_dff_status, do_dff_action};
+use crate::utils::memsapi::{do_query_dff_status, do_dff_action, do_query_dff_results, do_trigger_manual_dffs, to_my_dff_result};
 use crate::utils::meter_data::export_meter_csv;
-use crate::utils::mqttclient::{mqtt_acquirer, mqtt_provider};
-use crate::{ADAPTER_NAME, AdapterErr, ErrCode, MODEL_FROZEN_METER};
+use crate::utils::mqttclient::{generate_token, mqtt_acquirer, mqtt_provider};
+use crate::{ADAPTER_NAME, AdapterErr, ApiResponse, ErrCode, MODEL_FROZEN_METER};
+use crate::model::{json_df_to_polars, polars_to_json_rows};
+use crate::model::revision::RevisionSource;
+use crate::parser::{job_api_response, submit_parser_job, JobInput};
 use crate::env::Env;
 use crate::model::datacenter::*;
 use crate::model::north::{MyDffModels, MyDffResult};
 use crate::model::south::FlowOperation;
 use crate::utils::localapi::query_dff_mapping;
+use crate::utils::supervisor::{supervise, RestartPolicy, TASK_MEMS_EVENT};
 
 pub async fn do_mems_event() -> Result<(), AdapterErr> {
-    tokio::spawn(async {
-        if let Err(e) = mems_event().await {
-            log::error!("do mems_event_dff error: {}", e.msg);
-        }
-    });
+    supervise(TASK_MEMS_EVENT, RestartPolicy::Always, mems_event);
     Ok(())
 }
 
@@ -42,6 +42,15 @@ pub async fn mems_event() -> Result<(), AdapterErr> {
                         },
                         MemsEventCmd::GetTgDFFStatus => {
                             do_get_dff_status(msg).await
+                        },
+                        MemsEventCmd::SetTgDFFConfig => {
+                            do_set_dff_config(msg).await
+                        },
+                        MemsEventCmd::TgDFFTrigger => {
+                            do_trigger_dff(msg).await
+                        },
+                        MemsEventCmd::GetTgDFFResult => {
+                            do_get_dff_result(msg).await
                         }
                     }
                 } else {
@@ -86,8 +95,11 @@ fn do_get_dff_config(mems_event: MemsEventRequest) -> MemsEventResponse {
         data: MemsEventResponseBody {
             dffs,
             dffs_status: None,
+            dffs_result: None,
             code: ErrCode::Success,
             msg: "".to_string(),
+            stage: None,
+            details: None,
         }
     }
 }
@@ -202,18 +214,141 @@ async fn do_get_dff_status(mems_event: MemsEventRequest) -> MemsEventResponse {
         data: MemsEventResponseBody {
             dffs: None,
             dffs_status,
+            dffs_result: None,
             code,
             msg,
+            stage: None,
+            details: None,
         },
     }
 }
 
+// 与HTTP上传相同，提交后台任务后立即返回任务信息
+async fn do_set_dff_config(mems_event: MemsEventRequest) -> MemsEventResponse {
+    let dffs = mems_event.body.as_ref().and_then(|body| body.dffs.clone());
+    let resp = if let Some(dffs) = dffs {
+        job_api_response(submit_parser_job(JobInput::UploadMems(None, Some(dffs), RevisionSource::CloudEvent)).await)
+    } else {
+        ApiResponse::from_err(AdapterErr {
+            code: ErrCode::DataJsonDeserializeErr,
+            msg: "body.dffs不能为空".to_string(),
+        })
+    };
+    if resp.code != ErrCode::Success {
+        log::warn!("下发报表配置失败，阶段：{:?}，{}", resp.stage, resp.msg);
+    }
+    let mut data = get_dff_status_body(None, resp.code, resp.msg);
+    data.stage = resp.stage;
+    data.details = resp.details;
+    MemsEventResponse {
+        token: mems_event.token,
+        request_id: mems_event.request_id,
+        time: generate_current_time(),
+        msg_info: "".to_string(),
+        data,
+    }
+}
+
+async fn do_trigger_dff(mems_event: MemsEventRequest) -> MemsEventResponse {
+    let data = 'result: {
+        let Some(dffs_id) = mems_event.body.as_ref().and_then(|body| body.dffs_id.clone()) else {
+            break 'result get_dff_status_body(None, ErrCode::DataJsonDeserializeErr, "body.dffs_id不能为空".to_string());
+        };
+        let dff_mapping = match query_dff_mapping().await {
+            Ok(m) => m,
+            Err(e) => break 'result get_dff_status_body(None, ErrCode::InternalErr, e.msg),
+        };
+        let south_ids = match to_south_dff_ids(&dffs_id, &dff_mapping) {
+            Ok(ids) => ids,
+            Err(e) => break 'result get_dff_status_body(None, e.code, e.msg),
+        };
+        match do_trigger_manual_dffs(south_ids).await {
+            Ok(_) => get_dff_status_body(None, ErrCode::Success, "".to_string()),
+            Err(e) => get_dff_status_body(None, e.code, e.msg),
+        }
+    };
+    MemsEventResponse {
+        token: mems_event.token,
+        request_id: mems_event.request_id,
+        time: generate_current_time(),
+        msg_info: "".to_string(),
+        data,
+    }
+}
+
+// 未指定报表时返回所有报表的最近结果
+async fn do_get_dff_result(mems_event: MemsEventRequest) -> MemsEventResponse {
+    let data = 'result: {
+        let dff_mapping = match query_dff_mapping().await {
+            Ok(m) => m,
+            Err(e) => break 'result get_dff_status_body(None, ErrCode::InternalErr, e.msg),
+        };
+        let south_ids = match mems_event.body.as_ref().and_then(|body| body.dffs_id.clone()) {
+            Some(dffs_id) => match to_south_dff_ids(&dffs_id, &dff_mapping) {
+                Ok(ids) => ids,
+                Err(e) => break 'result get_dff_status_body(None, e.code, e.msg),
+            },
+            None => dff_mapping.keys().copied().collect(),
+        };
+        match do_query_dff_results(south_ids).await {
+            Ok(results) => {
+                // 映射中找不到的报表无法转换为北向id，在msg和details中返回其南向id
+                let mut unmapped = vec![];
+                let mut dffs_result = Vec::with_capacity(results.len());
+                for r in &results {
+                    let Some(dff_id) = dff_mapping.get(&r.flow_id).copied() else {
+                        unmapped.push(r.flow_id);
+                        continue;
+                    };
+                    let my_result = to_my_dff_result(r, &dff_mapping);
+                    let df = json_df_to_polars(my_result.result);
+                    dffs_result.push(MemsEventDffResult {
+                        dff_id,
+                        start_time: my_result.start_time,
+                        end_time: my_result.end_time,
+                        rows: polars_to_json_rows(&df),
+                    });
+                }
+                let mut data = get_dff_status_body(None, ErrCode::Success, "".to_string());
+                if !unmapped.is_empty() {
+                    log::warn!("报表{unmapped:?}的结果没有对应的北向id");
+                    data.msg = format!("报表{unmapped:?}的结果没有对应的北向id，未返回");
+                    data.details = Some(serde_json::json!({ "unmapped_dff_ids": unmapped }));
+                }
+                data.dffs_result = Some(dffs_result);
+                data
+            }
+            Err(e) => get_dff_status_body(None, ErrCode::MemsActionErr, e.msg),
+        }
+    };
+    MemsEventResponse {
+        token: mems_event.token,
+        request_id: mems_event.request_id,
+        time: generate_current_time(),
+        msg_info: "".to_string(),
+        data,
+    }
+}
+
+// 北向报表id转换为南向id
+fn to_south_dff_ids(dffs_id: &[u64], dff_mapping: &HashMap<u64, u64>) -> Result<Vec<u64>, AdapterErr> {
+    dffs_id.iter().map(|nid| {
+        dff_mapping.iter().find_map(|(k, v)| if v == nid { Some(*k) } else { None }).ok_or(AdapterErr {
+            code: ErrCode::DffIdNotFound,
+            msg: format!("未找到北向报表id：{nid}"),
+        })
+    }).collect()
+}
+
 fn get_dff_status_body(dffs_status: Option<Vec<MemsEventDffStatus>>, code: ErrCode, msg: String) -> MemsEventResponseBody {
     MemsEventResponseBody {
         dffs: None,
         dffs_status,
+        dffs_result: None,
         code,
         msg,
+        stage: None,
+        details: None,
     }
 }
 
@@ -235,9 +370,9 @@ fn generate_history_data_time() -> (String, String) {
 }
 
 fn generate_query_meter_dev() -> QueryRegisterDev {
-    let time = Local::now().timestamp_millis();
+    let token = generate_token();
     QueryRegisterDev {
-        token: time.to_string(),
+        token,
         time: generate_current_time(),
         body: vec![MODEL_FROZEN_METER.to_string()],
     }
@@ -295,7 +430,7 @@ pub fn generate_dff_set(dff_result: Vec<MyDffResult>, model: String, dev: String
 }
 
 fn generate_query_meter_history(devs: Vec<String>) -> RequestHistory {
-    let time = Local::now().timestamp_millis();
+    let token = generate_token();
     let timestamp = generate_current_time();
     let (start_time, end_time) = generate_history_data_time();
     let body = devs.iter().map(|dev| RequestHistoryBody {
@@ -303,7 +438,7 @@ fn generate_query_meter_history(devs: Vec<String>) -> RequestHistory {
         body: vec!["tgSupWh".to_string()],
     }).collect();
     RequestHistory {
-        token: time.to_string(),
+        token,
         time: timestamp,
         choice: "1".to_string(),
         time_type: "timestartgather".to_string(),
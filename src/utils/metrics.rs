@@ -0,0 +1,159 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+
+use actix_web::{get, web, HttpResponse};
+use once_cell::sync::Lazy;
+
+use crate::*;
+use crate::db::jobstore::JOBS;
+use crate::db::outbox::OUTBOX;
+use crate::parser::parser_cf_sizes;
+
+pub const MQTT_MESSAGES_IN: &str = "adapter_mqtt_messages_in_total";
+pub const MQTT_MESSAGES_OUT: &str = "adapter_mqtt_messages_out_total";
+pub const MQTT_REQUEST_DURATION: &str = "adapter_mqtt_request_duration_seconds";
+pub const MQTT_REQUEST_TIMEOUTS: &str = "adapter_mqtt_request_timeouts_total";
+pub const HTTP_REQUEST_DURATION: &str = "adapter_http_request_duration_seconds";
+pub const HTTP_REQUEST_ERRORS: &str = "adapter_http_request_errors_total";
+pub const JOB_STAGE_DURATION: &str = "adapter_job_stage_duration_seconds";
+pub const JOBS_FINISHED: &str = "adapter_jobs_finished_total";
+pub const RESULTS_UPLOADED: &str = "adapter_results_uploaded_total";
+pub const CLOUD_EVENTS: &str = "adapter_cloud_events_total";
+const ROCKSDB_CF_SIZE: &str = "adapter_rocksdb_cf_size_bytes";
+
+// 名称、类型和说明，按此顺序输出
+const METRICS: &[(&str, &str, &str)] = &[
+    (MQTT_MESSAGES_IN, "counter", "MQTT messages received per topic"),
+    (MQTT_MESSAGES_OUT, "counter", "MQTT messages published per topic"),
+    (MQTT_REQUEST_DURATION, "histogram", "Latency of MQTT request/response calls"),
+    (MQTT_REQUEST_TIMEOUTS, "counter", "MQTT request/response calls that timed out"),
+    (HTTP_REQUEST_DURATION, "histogram", "Latency of PLCC/MEMS REST calls per URL"),
+    (HTTP_REQUEST_ERRORS, "counter", "Failed PLCC/MEMS REST calls per URL"),
+    (JOB_STAGE_DURATION, "histogram", "Duration of each stage of parser jobs"),
+    (JOBS_FINISHED, "counter", "Parser jobs finished per kind and status"),
+    (RESULTS_UPLOADED, "counter", "AOE/DFF results uploaded to the datacenter"),
+    (CLOUD_EVENTS, "counter", "Cloud events handled per command and result code"),
+];
+
+// 直方图的上边界，秒
+const BUCKETS: &[f64] = &[0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0];
+
+// 统计REST接口时按以下URL常量归类，去掉id和查询参数
+const URLS: &[&str] = &[
+    URL_LOGIN, URL_POINTS, URL_TRANSPORTS, URL_AOES, URL_AOE_RESULTS, URL_PLCC_RESET,
+    URL_UNRUN_AOES, URL_RUNNING_AOES, URL_AOE_CONTROL, URL_DFFS, URL_DFF_RESULTS,
+    URL_RUNNING_DFFS, URL_UNRUN_DFFS, URL_DFF_CONTROL, URL_MEMS_RESET, URL_DFF_START,
+    URL_POINT_CONTROL, URL_IMPORT_POINTS, URL_POINTS_VERSION, URL_POINTS_APPLY,
+    URL_AOES_APPLY, URL_AOES_VERSION,
+];
+
+#[derive(Default)]
+struct Histogram {
+    buckets: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+// 指标名称 -> 标签 -> 值，标签已按Prometheus格式拼接
+static COUNTERS: Lazy<Mutex<BTreeMap<&'static str, BTreeMap<String, f64>>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+static HISTOGRAMS: Lazy<Mutex<BTreeMap<&'static str, BTreeMap<String, Histogram>>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+pub fn inc_counter(name: &'static str, labels: &[(&str, &str)]) {
+    let mut counters = COUNTERS.lock().unwrap();
+    *counters.entry(name).or_default().entry(format_labels(labels)).or_default() += 1.0;
+}
+
+pub fn observe(name: &'static str, labels: &[(&str, &str)], secs: f64) {
+    let mut histograms = HISTOGRAMS.lock().unwrap();
+    let h = histograms.entry(name).or_default().entry(format_labels(labels)).or_default();
+    if h.buckets.is_empty() {
+        h.buckets = vec![0; BUCKETS.len()];
+    }
+    for (i, le) in BUCKETS.iter().enumerate() {
+        if secs <= *le {
+            h.buckets[i] += 1;
+        }
+    }
+    h.sum += secs;
+    h.count += 1;
+}
+
+/// 将请求路径归类为对应的URL常量
+pub fn url_label(path: &str) -> String {
+    let path = path.split('?').next().unwrap_or(path);
+    URLS.iter()
+        .filter(|u| path == **u || path.strip_prefix(**u).is_some_and(|rest| rest.starts_with('/')))
+        .max_by_key(|u| u.len())
+        .map(|u| u.to_string())
+        .unwrap_or_else(|| path.to_string())
+}
+
+fn format_labels(labels: &[(&str, &str)]) -> String {
+    labels.iter()
+        .map(|(k, v)| format!("{k}=\"{}\"", v.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+fn with_label(labels: &str, extra: &str) -> String {
+    if labels.is_empty() {
+        format!("{{{extra}}}")
+    } else {
+        format!("{{{labels},{extra}}}")
+    }
+}
+
+fn braced(labels: &str) -> String {
+    if labels.is_empty() { "".to_string() } else { format!("{{{labels}}}") }
+}
+
+fn render() -> String {
+    let mut out = String::new();
+    {
+        let counters = COUNTERS.lock().unwrap();
+        let histograms = HISTOGRAMS.lock().unwrap();
+        for (name, kind, help) in METRICS {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            if let Some(samples) = counters.get(name) {
+                for (labels, value) in samples {
+                    let _ = writeln!(out, "{name}{} {value}", braced(labels));
+                }
+            }
+            if let Some(samples) = histograms.get(name) {
+                for (labels, h) in samples {
+                    for (le, count) in BUCKETS.iter().zip(h.buckets.iter()) {
+                        let _ = writeln!(out, "{name}_bucket{} {count}", with_label(labels, &format!("le=\"{le}\"")));
+                    }
+                    let _ = writeln!(out, "{name}_bucket{} {}", with_label(labels, "le=\"+Inf\""), h.count);
+                    let _ = writeln!(out, "{name}_sum{} {}", braced(labels), h.sum);
+                    let _ = writeln!(out, "{name}_count{} {}", braced(labels), h.count);
+                }
+            }
+        }
+    }
+    // 解析服务的数据库在每次操作后更新缓存，其余数据库在抓取时查询
+    let mut cf_sizes = parser_cf_sizes().into_iter().map(|(cf, size)| ("parser", cf, size)).collect::<Vec<_>>();
+    if let Some(outbox) = OUTBOX.as_ref() {
+        cf_sizes.extend(outbox.cf_sizes().into_iter().map(|(cf, size)| ("outbox", cf, size)));
+    }
+    cf_sizes.extend(JOBS.cf_sizes().into_iter().map(|(cf, size)| ("job", cf, size)));
+    let _ = writeln!(out, "# HELP {ROCKSDB_CF_SIZE} Estimated live data size of each RocksDB column family");
+    let _ = writeln!(out, "# TYPE {ROCKSDB_CF_SIZE} gauge");
+    for (db, cf, size) in cf_sizes {
+        let _ = writeln!(out, "{ROCKSDB_CF_SIZE}{{{}}} {size}", format_labels(&[("db", db), ("cf", &cf)]));
+    }
+    out
+}
+
+#[get("/metrics")]
+async fn metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(render())
+}
+
+pub fn config_metrics_web_service(cfg: &mut web::ServiceConfig) {
+    cfg.service(metrics);
+}
@@ -3,12 +3,14 @@ pub mod tokenizer;
 pub mod shuntingyard;
 pub mod plccapi;
 pub mod memsapi;
+pub mod httpclient;
 pub mod localapi;
 pub mod appapi;
 pub mod exprparser;
 pub mod expr;
 pub mod context;
 pub mod mqttclient;
+pub mod mqttmanager;
 pub mod register_result;
 pub mod meter_data;
 pub mod parse;
@@ -17,8 +19,14 @@ pub mod jsonmodel;
 pub mod memsmqtt;
 pub mod plccmqtt;
 pub mod global;
+pub mod events;
+pub mod supervisor;
+pub mod health;
+pub mod metrics;
+pub mod config_reload;
 
 use regex::Regex;
+use serde::Serialize;
 
 use crate::{AdapterErr, ErrCode};
 
@@ -88,3 +96,11 @@ pub fn get_point_tag(input: &u64, points_mapping: &HashMap<u64, String>) -> Resu
         Err(format!("测点替换失败，找不到测点{input}对应的物模型路径"))
     }
 }
+
+/// 按序列化后的内容比较模型，从API获取的模型中不含serde(skip)的字段
+pub fn is_same_model<T: Serialize>(old: &T, new: &T) -> bool {
+    match (serde_json::to_value(old), serde_json::to_value(new)) {
+        (Ok(old), Ok(new)) => old == new,
+        _ => false,
+    }
+}
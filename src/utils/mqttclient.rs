@@ -1,37 +1,101 @@
 use std::pin::Pin;
-use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS};
+use std::sync::atomic::{AtomicI64, Ordering};
+use rumqttc::{AsyncClient, MqttOptions, QoS, TlsConfiguration, Transport};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
-use tokio::time::{timeout, Duration};
+use tokio::time::Duration;
 use chrono::Local;
-use tokio::sync::oneshot;
 
 use crate::{ADAPTER_NAME, AdapterErr, ErrCode};
 use crate::env::Env;
 use crate::model::datacenter::*;
+use crate::utils::mqttmanager::main_manager;
 
-pub fn get_mqttoptions(user_name: &str, mqtt_server: &str, mqtt_server_port: u16) -> MqttOptions {
-    let time = Local::now().timestamp_millis();
-    let mut mqttoptions = MqttOptions::new(format!("{user_name}_{time}"), mqtt_server, mqtt_server_port);
+static LAST_TOKEN: AtomicI64 = AtomicI64::new(0);
+
+/// 生成请求的token，取当前毫秒时间戳，同一毫秒内的多个请求依次加1，保证互不相同
+pub fn generate_token() -> String {
+    let now = Local::now().timestamp_millis();
+    let mut last = LAST_TOKEN.load(Ordering::Relaxed);
+    loop {
+        let next = if now > last { now } else { last + 1 };
+        match LAST_TOKEN.compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed) {
+            Ok(_) => return next.to_string(),
+            Err(v) => last = v,
+        }
+    }
+}
+
+pub fn get_mqttoptions(client_id: &str, mqtt_server: &str, mqtt_server_port: u16) -> MqttOptions {
+    let mut mqttoptions = MqttOptions::new(client_id, mqtt_server, mqtt_server_port);
     mqttoptions.set_max_packet_size(1024 * 1024 * 100, 1024 * 1024 * 100);
     mqttoptions.set_keep_alive(Duration::from_secs(5));
     mqttoptions
 }
 
-pub async fn client_subscribe(client: &AsyncClient, topic: &str) -> Result<(), AdapterErr> {
-    match client.subscribe(topic, QoS::AtMostOnce).await {
-        Ok(_) => Ok(()),
-        Err(v) => {
-            Err(AdapterErr {
-                code: ErrCode::MqttConnectErr,
-                msg: v.to_string(),
-            })
-        }
+/// 数据中心MQTT的连接参数，按配置设置用户名密码以及SSL
+pub fn get_datacenter_mqttoptions(client_id: &str, mqtt_server: &str, mqtt_server_port: u16) -> Result<MqttOptions, AdapterErr> {
+    let env = Env::get_env(ADAPTER_NAME);
+    let mut mqttoptions = get_mqttoptions(client_id, mqtt_server, mqtt_server_port);
+    if let Some((user_name, password)) = env.get_mqtt_credential() {
+        mqttoptions.set_credentials(user_name, password);
     }
+    if env.get_is_use_ssl() {
+        let ca = read_cert_file(&env.get_mqtt_ssl_ca_file_path(), "CA证书")?;
+        let cert_path = env.get_ssl_cert_file_path();
+        let key_path = env.get_ssl_key_file_path();
+        // 证书和私钥都配置时才启用双向认证
+        let client_auth = if !cert_path.is_empty() && !key_path.is_empty() {
+            Some((read_cert_file(&cert_path, "客户端证书")?, read_cert_file(&key_path, "客户端私钥")?))
+        } else {
+            None
+        };
+        mqttoptions.set_transport(Transport::tls_with_config(TlsConfiguration::Simple {
+            ca,
+            alpn: None,
+            client_auth,
+        }));
+    }
+    Ok(mqttoptions)
+}
+
+fn read_cert_file(path: &str, name: &str) -> Result<Vec<u8>, AdapterErr> {
+    if path.is_empty() {
+        return Err(AdapterErr {
+            code: ErrCode::MqttConnectErr,
+            msg: format!("MQTT开启SSL但未配置{name}"),
+        });
+    }
+    std::fs::read(path).map_err(|e| AdapterErr {
+        code: ErrCode::IoErr,
+        msg: format!("读取MQTT{name}{path}失败：{e}"),
+    })
+}
+
+/// 按主题后缀确定数据中心主题的服务质量，控制类主题默认QoS 1
+pub fn topic_qos(topic: &str) -> QoS {
+    let env = Env::get_env(ADAPTER_NAME);
+    let is_control = env.get_mqtt_control_topics().iter().any(|suffix| topic.ends_with(suffix.as_str()));
+    let qos = if is_control {
+        env.get_mqtt_control_qos()
+    } else {
+        env.get_mqtt_qos()
+    };
+    match qos {
+        0 => QoS::AtMostOnce,
+        1 => QoS::AtLeastOnce,
+        _ => QoS::ExactlyOnce,
+    }
+}
+
+/// 数据中心主题是否保留消息
+pub fn topic_retain(topic: &str) -> bool {
+    let env = Env::get_env(ADAPTER_NAME);
+    env.get_mqtt_retain_topics().iter().any(|suffix| topic.ends_with(suffix.as_str()))
 }
 
 pub async fn client_publish(client: &AsyncClient, topic: &str, payload: &str) -> Result<(), AdapterErr> {
-    match client.publish(topic, QoS::AtMostOnce, false, payload).await {
+    match client.publish(topic, topic_qos(topic), topic_retain(topic), payload).await {
         Ok(_) => Ok(()),
         Err(v) => {
             Err(AdapterErr {
@@ -43,7 +107,7 @@ pub async fn client_publish(client: &AsyncClient, topic: &str, payload: &str) ->
 }
 
 pub async fn mqtt_acquirer<T, R>(
-    mqtt_usr_name: String,
+    name: String,
     topic_request: String,
     topic_response: String,
     body: T,
@@ -52,66 +116,14 @@ where
     T: Serialize + HasToken,
     R: DeserializeOwned + HasToken + Send + 'static,
 {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mqtt_server = env.get_mqtt_server();
-    let mqtt_server_port = env.get_mqtt_server_port();
-    let mqtt_timeout = env.get_mqtt_timeout();
-    let mqttoptions = get_mqttoptions(&mqtt_usr_name, &mqtt_server, mqtt_server_port);
-    let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
-    let payload = serde_json::to_string(&body).unwrap();
-    client_subscribe(&client, &topic_response).await?;
-    client_publish(&client, &topic_request, &payload).await?;
-    let (tx, rx) = oneshot::channel::<Result<R, AdapterErr>>();
-    let token = body.token();
-    tokio::spawn(async move {
-        loop {
-            match eventloop.poll().await {
-                Ok(Event::Incoming(Incoming::Publish(p))) => {
-                    if p.topic == topic_response {
-                        let result = match serde_json::from_slice::<R>(&p.payload) {
-                            Ok(r) => {
-                                if r.token() == token {
-                                    // todo 只有相同token才能认定是当前消息响应
-                                }
-                                tx.send(Ok(r))
-                            }
-                            Err(e) => tx.send(Err(AdapterErr {
-                                code: ErrCode::DataJsonDeserializeErr,
-                                msg: format!("解析MQTT返回字符串失败: {e:?}"),
-                            })),
-                        };
-                        if result.is_err() {
-                            log::error!("MQTT响应发送失败");
-                        }
-                        break;
-                    }
-                }
-                Ok(_) => {}
-                Err(e) => {
-                    let _ = tx.send(Err(AdapterErr {
-                        code: ErrCode::MqttConnectErr,
-                        msg: format!("MQTT连接发生错误: {e:?}"),
-                    }));
-                    break;
-                }
-            }
-        }
-    });
-    match timeout(Duration::from_secs(mqtt_timeout), rx).await {
-        Ok(Ok(result)) => result,
-        Ok(Err(_)) => Err(AdapterErr {
-            code: ErrCode::MqttTimeoutErr,
-            msg: "等待响应时发生错误".to_string(),
-        }),
-        Err(_) => Err(AdapterErr {
-            code: ErrCode::MqttTimeoutErr,
-            msg: "MQTT响应超时".to_string(),
-        }),
-    }
+    main_manager()?.request(&topic_request, &topic_response, &body).await.map_err(|e| {
+        log::debug!("do {name} error: {}", e.msg);
+        e
+    })
 }
 
 pub async fn mqtt_provider<F, Resp>(
-    mqtt_usr_name: String,
+    name: String,
     topic_request: String,
     topic_response: String,
     callback: F,
@@ -124,70 +136,44 @@ where
         + Sync
         + 'static,
 {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mqtt_server = env.get_mqtt_server();
-    let mqtt_server_port = env.get_mqtt_server_port();
-    let mqttoptions = get_mqttoptions(&mqtt_usr_name, &mqtt_server, mqtt_server_port);
-    let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
-    client_subscribe(&client, &topic_response).await?;
-    tokio::spawn(async move {
-        loop {
-            match eventloop.poll().await {
-                Ok(Event::Incoming(Incoming::Publish(p))) => {
-                    if p.topic == topic_response {
-                        let data = callback(p.payload).await;
-                        let response = serde_json::to_string(&data).unwrap();
-                        let _ = client_publish(&client, &topic_request, &response).await;
-                    }
-                }
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("do mqtt_provider error: {e:?}");
-                    actix_rt::time::sleep(Duration::from_millis(5000)).await;
-                }
+    let manager = main_manager()?;
+    let rx = manager.subscribe(&topic_response).await?;
+    // 持续处理请求，由调用方监管，通道关闭时返回错误以便重启
+    // 每个请求单独执行，下发配置等耗时请求不阻塞同一主题的其他请求，处理完成后再回复
+    while let Ok(payload) = rx.recv().await {
+        let data = callback(payload);
+        let (manager, name, topic_request) = (manager.clone(), name.clone(), topic_request.clone());
+        tokio::spawn(async move {
+            let response = serde_json::to_string(&data.await).unwrap();
+            if let Err(e) = manager.publish(&topic_request, &response).await {
+                log::error!("do {name} error: {}", e.msg);
             }
-        }
-    });
-    Ok(())
+        });
+    }
+    Err(AdapterErr {
+        code: ErrCode::MqttConnectErr,
+        msg: format!("{name}的订阅已关闭"),
+    })
 }
 
 pub async fn mqtt_push_only<T>(
-    mqtt_usr_name: String,
+    name: String,
     topic_request: String,
-    topic_response: String,
     body: T,
 ) -> Result<(), AdapterErr>
 where
     T: Serialize + HasToken,
 {
-    let env = Env::get_env(ADAPTER_NAME);
-    let mqtt_server = env.get_mqtt_server();
-    let mqtt_server_port = env.get_mqtt_server_port();
-    let mqttoptions = get_mqttoptions(&mqtt_usr_name, &mqtt_server, mqtt_server_port);
-    let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
     let payload = serde_json::to_string(&body).unwrap();
-    client_subscribe(&client, &topic_response).await?;
-    client_publish(&client, &topic_request, &payload).await?;
-    tokio::spawn(async move {
-        loop {
-            match eventloop.poll().await {
-                Ok(Event::Incoming(Incoming::Publish(p))) => {
-                    if p.topic == topic_response {
-                        break;
-                    }
-                }
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("do mqtt_push_only error: {e:?}");
-                    break;
-                }
-            }
-        }
-    });
-    Ok(())
+    main_manager()?.publish(&topic_request, &payload).await.map_err(|e| {
+        log::debug!("do {name} error: {}", e.msg);
+        e
+    })
 }
 
+// 需要连接MQTT服务器并一直运行，手动执行；响应的token为空，按token匹配响应由mqttmanager中的test_match_response覆盖
 #[tokio::test]
+#[ignore]
 async fn test_mqtt_response() {
     Env::init(ADAPTER_NAME);
     let env = Env::get_env(ADAPTER_NAME);
@@ -352,6 +338,8 @@ async fn test_mqtt_response() {
                 }]),
                 code: ErrCode::Success,
                 msg: "".to_string(),
+                stage: None,
+                details: None,
             },
         }).unwrap();
         let _ = client_publish(&client, &tp, &body).await;
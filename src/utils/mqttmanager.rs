@@ -0,0 +1,400 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+use actix_web::web::Bytes;
+use async_channel::{Receiver, Sender, TrySendError};
+use once_cell::sync::Lazy;
+use rumqttc::{AsyncClient, Event, EventLoop, Incoming, Outgoing, QoS};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use tokio::sync::oneshot;
+use tokio::time::{timeout, Duration};
+
+use crate::{ADAPTER_NAME, AdapterErr, ErrCode};
+use crate::env::Env;
+use crate::model::datacenter::HasToken;
+use crate::utils::events::{publish, AdapterEvent};
+use crate::utils::metrics::{inc_counter, observe, MQTT_MESSAGES_IN, MQTT_MESSAGES_OUT, MQTT_REQUEST_DURATION, MQTT_REQUEST_TIMEOUTS};
+use crate::utils::mqttclient::{get_datacenter_mqttoptions, get_mqttoptions, topic_qos, topic_retain};
+
+// 每个订阅者缓存的消息数量，超出后丢弃新消息
+const HANDLER_CAPACITY: usize = 100;
+const CLIENT_CAPACITY: usize = 100;
+const RECONNECT_MAX_SECS: u64 = 30;
+// 连接用途
+pub const ROLE_MAIN: &str = "main";
+pub const ROLE_PLCC: &str = "plcc";
+
+static MANAGERS: Lazy<Mutex<HashMap<String, Arc<MqttManager>>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// MQTT连接状态，用于健康检查
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MqttConnState {
+    /// 连接用途，数据中心为main，本机PLCC为plcc
+    pub role: String,
+    pub broker: String,
+    pub connected: bool,
+    pub reconnect_count: u64,
+    pub last_error: Option<String>,
+}
+
+/// 单个broker的长连接，负责事件循环、按主题分发消息以及断线重连后重新订阅
+pub struct MqttManager {
+    role: &'static str,
+    broker: String,
+    // 配置的QoS和保留消息只用于数据中心的主题
+    is_datacenter: bool,
+    client: AsyncClient,
+    handlers: RwLock<HashMap<String, Vec<Sender<Bytes>>>>,
+    connected: AtomicBool,
+    // 配置变化后关闭，事件循环随之退出
+    closed: AtomicBool,
+    reconnect_count: AtomicU64,
+    last_error: RwLock<Option<String>>,
+    // QoS 1/2消息的确认等待者，发布时按顺序登记，事件循环发出时与packet id对应
+    acks: Mutex<AckWaiters>,
+    // 保证登记顺序与发布顺序相同
+    publish_lock: tokio::sync::Mutex<()>,
+}
+
+#[derive(Default)]
+struct AckWaiters {
+    queued: VecDeque<Option<oneshot::Sender<()>>>,
+    inflight: HashMap<u16, Option<oneshot::Sender<()>>>,
+}
+
+/// 数据中心的MQTT服务，使用配置的用户名密码和SSL
+pub fn main_manager() -> Result<Arc<MqttManager>, AdapterErr> {
+    let env = Env::get_env(ADAPTER_NAME);
+    get_manager(&env.get_mqtt_server(), env.get_mqtt_server_port(), true)
+}
+
+/// 本机PLCC的MQTT服务
+pub fn plcc_manager() -> Result<Arc<MqttManager>, AdapterErr> {
+    let env = Env::get_env(ADAPTER_NAME);
+    get_manager("127.0.0.1", env.get_plcc_mqtt_port(), false)
+}
+
+// 数据中心和PLCC使用同一个broker时（如IS_LOCAL_MQTT）也分别建立连接，互不影响
+fn manager_role(is_datacenter: bool) -> &'static str {
+    if is_datacenter { ROLE_MAIN } else { ROLE_PLCC }
+}
+
+pub fn get_manager(mqtt_server: &str, mqtt_server_port: u16, is_datacenter: bool) -> Result<Arc<MqttManager>, AdapterErr> {
+    let role = manager_role(is_datacenter);
+    let broker = format!("{mqtt_server}:{mqtt_server_port}");
+    let key = format!("{role}@{broker}");
+    let mut managers = MANAGERS.lock().unwrap();
+    if let Some(manager) = managers.get(&key) {
+        return Ok(manager.clone());
+    }
+    let app_name = Env::get_env(ADAPTER_NAME).get_app_name();
+    let client_id = format!("adapter_{app_name}_{role}_{mqtt_server_port}");
+    let mqttoptions = if is_datacenter {
+        get_datacenter_mqttoptions(&client_id, mqtt_server, mqtt_server_port)?
+    } else {
+        get_mqttoptions(&client_id, mqtt_server, mqtt_server_port)
+    };
+    let (client, eventloop) = AsyncClient::new(mqttoptions, CLIENT_CAPACITY);
+    let manager = Arc::new(MqttManager {
+        role,
+        broker,
+        is_datacenter,
+        client,
+        handlers: RwLock::new(HashMap::new()),
+        connected: AtomicBool::new(false),
+        closed: AtomicBool::new(false),
+        reconnect_count: AtomicU64::new(0),
+        last_error: RwLock::new(None),
+        acks: Mutex::new(AckWaiters::default()),
+        publish_lock: tokio::sync::Mutex::new(()),
+    });
+    tokio::spawn(run_eventloop(manager.clone(), eventloop));
+    managers.insert(key, manager.clone());
+    Ok(manager)
+}
+
+/// 断开并移除指定用途到指定broker的连接，之后获取的manager使用新的配置重新连接，返回是否存在该连接
+pub fn close_manager(mqtt_server: &str, mqtt_server_port: u16, is_datacenter: bool) -> bool {
+    let key = format!("{}@{mqtt_server}:{mqtt_server_port}", manager_role(is_datacenter));
+    let Some(manager) = MANAGERS.lock().unwrap().remove(&key) else {
+        return false;
+    };
+    manager.closed.store(true, Ordering::Relaxed);
+    if let Err(e) = manager.client.try_disconnect() {
+        log::warn!("断开MQTT {key} 失败: {e:?}");
+    }
+    log::info!("MQTT {key} 连接已关闭");
+    true
+}
+
+/// 所有已建立连接的状态
+pub fn mqtt_states() -> Vec<MqttConnState> {
+    let managers = MANAGERS.lock().unwrap();
+    let mut states = managers.values().map(|m| m.state()).collect::<Vec<MqttConnState>>();
+    states.sort_by(|a, b| (&a.role, &a.broker).cmp(&(&b.role, &b.broker)));
+    states
+}
+
+async fn run_eventloop(manager: Arc<MqttManager>, mut eventloop: EventLoop) {
+    let mut fail_count = 0;
+    while !manager.closed.load(Ordering::Relaxed) {
+        match eventloop.poll().await {
+            Ok(Event::Incoming(Incoming::ConnAck(_))) => {
+                if fail_count > 0 {
+                    manager.reconnect_count.fetch_add(1, Ordering::Relaxed);
+                    log::info!("MQTT {} 重连成功", manager.broker);
+                }
+                fail_count = 0;
+                manager.connected.store(true, Ordering::Relaxed);
+                manager.resubscribe();
+                publish(AdapterEvent::MqttConnection {
+                    broker: manager.broker.clone(),
+                    connected: true,
+                    error: None,
+                });
+            }
+            Ok(Event::Incoming(Incoming::Publish(p))) => {
+                manager.dispatch(&p.topic, p.payload);
+            }
+            Ok(Event::Outgoing(Outgoing::Publish(pkid))) if pkid != 0 => {
+                manager.on_outgoing_publish(pkid);
+            }
+            Ok(Event::Incoming(Incoming::PubAck(ack))) => {
+                manager.on_ack(ack.pkid);
+            }
+            Ok(Event::Incoming(Incoming::PubComp(comp))) => {
+                manager.on_ack(comp.pkid);
+            }
+            Ok(_) => {}
+            Err(e) => {
+                let was_connected = manager.connected.swap(false, Ordering::Relaxed);
+                if manager.closed.load(Ordering::Relaxed) {
+                    break;
+                }
+                *manager.last_error.write().unwrap() = Some(e.to_string());
+                if was_connected {
+                    publish(AdapterEvent::MqttConnection {
+                        broker: manager.broker.clone(),
+                        connected: false,
+                        error: Some(e.to_string()),
+                    });
+                }
+                let secs = (1u64 << fail_count.min(5)).min(RECONNECT_MAX_SECS);
+                fail_count += 1;
+                log::error!("MQTT {} 连接错误: {e:?}，{secs}秒后重连", manager.broker);
+                actix_rt::time::sleep(Duration::from_secs(secs)).await;
+            }
+        }
+    }
+}
+
+impl MqttManager {
+    pub fn state(&self) -> MqttConnState {
+        MqttConnState {
+            role: self.role.to_string(),
+            broker: self.broker.clone(),
+            connected: self.connected.load(Ordering::Relaxed),
+            reconnect_count: self.reconnect_count.load(Ordering::Relaxed),
+            last_error: self.last_error.read().unwrap().clone(),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.connected.load(Ordering::Relaxed)
+    }
+
+    /// 注册主题处理者，返回接收该主题消息的通道，通道被丢弃后自动注销
+    pub async fn subscribe(&self, topic: &str) -> Result<Receiver<Bytes>, AdapterErr> {
+        let (tx, rx) = async_channel::bounded(HANDLER_CAPACITY);
+        let first = {
+            let mut handlers = self.handlers.write().unwrap();
+            let senders = handlers.entry(topic.to_string()).or_default();
+            senders.retain(|s| !s.is_closed());
+            senders.push(tx);
+            senders.len() == 1
+        };
+        if first {
+            self.client.subscribe(topic, self.qos(topic)).await.map_err(mqtt_err)?;
+        }
+        Ok(rx)
+    }
+
+    /// 发布消息，返回时消息只是进入发送队列
+    pub async fn publish(&self, topic: &str, payload: &str) -> Result<(), AdapterErr> {
+        self.publish_with(topic, payload, self.qos(topic), None).await
+    }
+
+    /// 以至少QoS 1发布并等待broker确认，超时或连接关闭时返回错误
+    pub async fn publish_confirmed(&self, topic: &str, payload: &str) -> Result<(), AdapterErr> {
+        let qos = match self.qos(topic) {
+            QoS::AtMostOnce => QoS::AtLeastOnce,
+            qos => qos,
+        };
+        let (tx, rx) = oneshot::channel();
+        self.publish_with(topic, payload, qos, Some(tx)).await?;
+        let mqtt_timeout = Env::get_env(ADAPTER_NAME).get_mqtt_timeout();
+        match timeout(Duration::from_secs(mqtt_timeout), rx).await {
+            Ok(Ok(())) => Ok(()),
+            _ => Err(AdapterErr {
+                code: ErrCode::MqttTimeoutErr,
+                msg: format!("等待{topic}的发布确认超时"),
+            }),
+        }
+    }
+
+    async fn publish_with(&self, topic: &str, payload: &str, qos: QoS, ack: Option<oneshot::Sender<()>>) -> Result<(), AdapterErr> {
+        let _guard = self.publish_lock.lock().await;
+        // 所有QoS 1/2消息都要登记，否则与事件循环分配的packet id对应不上
+        if qos != QoS::AtMostOnce {
+            self.acks.lock().unwrap().queued.push_back(ack);
+        }
+        if let Err(e) = self.client.publish(topic, qos, self.retain(topic), payload).await {
+            if qos != QoS::AtMostOnce {
+                self.acks.lock().unwrap().queued.pop_back();
+            }
+            return Err(mqtt_err(e));
+        }
+        inc_counter(MQTT_MESSAGES_OUT, &[("broker", &self.broker), ("topic", topic)]);
+        Ok(())
+    }
+
+    // 重连后重发的消息沿用原packet id，已登记过的不再对应新的等待者
+    fn on_outgoing_publish(&self, pkid: u16) {
+        let mut acks = self.acks.lock().unwrap();
+        if acks.inflight.contains_key(&pkid) {
+            return;
+        }
+        if let Some(waiter) = acks.queued.pop_front() {
+            acks.inflight.insert(pkid, waiter);
+        }
+    }
+
+    fn on_ack(&self, pkid: u16) {
+        if let Some(Some(waiter)) = self.acks.lock().unwrap().inflight.remove(&pkid) {
+            let _ = waiter.send(());
+        }
+    }
+
+    /// 发布请求并等待token相同的响应，其他请求的响应丢弃后继续等待
+    pub async fn request<T, R>(&self, topic_request: &str, topic_response: &str, body: &T) -> Result<R, AdapterErr>
+    where
+        T: Serialize + HasToken,
+        R: DeserializeOwned + HasToken,
+    {
+        let mqtt_timeout = Env::get_env(ADAPTER_NAME).get_mqtt_timeout();
+        let payload = serde_json::to_string(body).unwrap();
+        let token = body.token();
+        let rx = self.subscribe(topic_response).await?;
+        let start = std::time::Instant::now();
+        let wait = async {
+            self.publish(topic_request, &payload).await?;
+            loop {
+                let bytes = rx.recv().await.map_err(|_| AdapterErr {
+                    code: ErrCode::MqttTimeoutErr,
+                    msg: "等待响应时发生错误".to_string(),
+                })?;
+                if let Some(r) = match_response::<R>(&bytes, &token, topic_response) {
+                    return Ok(r);
+                }
+            }
+        };
+        let labels = [("topic", topic_request)];
+        match timeout(Duration::from_secs(mqtt_timeout), wait).await {
+            Ok(result) => {
+                observe(MQTT_REQUEST_DURATION, &labels, start.elapsed().as_secs_f64());
+                result
+            }
+            Err(_) => {
+                inc_counter(MQTT_REQUEST_TIMEOUTS, &labels);
+                Err(AdapterErr {
+                    code: ErrCode::MqttTimeoutErr,
+                    msg: "MQTT响应超时".to_string(),
+                })
+            }
+        }
+    }
+
+    // PLCC的broker不使用数据中心的QoS和保留消息配置
+    fn qos(&self, topic: &str) -> QoS {
+        if self.is_datacenter { topic_qos(topic) } else { QoS::AtMostOnce }
+    }
+
+    fn retain(&self, topic: &str) -> bool {
+        self.is_datacenter && topic_retain(topic)
+    }
+
+    fn dispatch(&self, topic: &str, payload: Bytes) {
+        inc_counter(MQTT_MESSAGES_IN, &[("broker", &self.broker), ("topic", topic)]);
+        let mut handlers = self.handlers.write().unwrap();
+        let Some(senders) = handlers.get_mut(topic) else {
+            return;
+        };
+        senders.retain(|s| match s.try_send(payload.clone()) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => {
+                log::warn!("MQTT主题{topic}的处理者繁忙，丢弃消息");
+                true
+            }
+            Err(TrySendError::Closed(_)) => false,
+        });
+        if senders.is_empty() {
+            handlers.remove(topic);
+            if let Err(e) = self.client.try_unsubscribe(topic) {
+                log::warn!("取消订阅{topic}失败: {e:?}");
+            }
+        }
+    }
+
+    // 重连后会话已清空，需要重新订阅所有主题
+    fn resubscribe(&self) {
+        let handlers = self.handlers.read().unwrap();
+        for topic in handlers.keys() {
+            if let Err(e) = self.client.try_subscribe(topic, self.qos(topic)) {
+                log::error!("重新订阅{topic}失败: {e:?}");
+            }
+        }
+    }
+}
+
+// 解析响应，token与请求不同或解析失败时返回None，继续等待
+fn match_response<R: DeserializeOwned + HasToken>(bytes: &[u8], token: &str, topic_response: &str) -> Option<R> {
+    match serde_json::from_slice::<R>(bytes) {
+        Ok(r) if r.token() != token => {
+            log::debug!("discard mqtt response on {topic_response}, token {} not match {token}", r.token());
+            None
+        }
+        Ok(r) => Some(r),
+        Err(e) => {
+            log::warn!("解析MQTT返回字符串失败: {e:?}");
+            None
+        }
+    }
+}
+
+fn mqtt_err(e: rumqttc::ClientError) -> AdapterErr {
+    AdapterErr {
+        code: ErrCode::MqttConnectErr,
+        msg: e.to_string(),
+    }
+}
+
+#[test]
+fn test_match_response() {
+    use crate::model::datacenter::RegisterResponse;
+    let topic = "/app/sys.dbc/S-dataservice/F-Register";
+    let response = |token: &str| serde_json::to_vec(&RegisterResponse {
+        token: token.to_string(),
+        time: "".to_string(),
+        ack: "true".to_string(),
+    }).unwrap();
+    // token相同的响应返回
+    let r = match_response::<RegisterResponse>(&response("1001"), "1001", topic);
+    assert_eq!(r.map(|r| r.token), Some("1001".to_string()));
+    // 其他请求的响应和空token的响应被跳过
+    assert!(match_response::<RegisterResponse>(&response("1002"), "1001", topic).is_none());
+    assert!(match_response::<RegisterResponse>(&response(""), "1001", topic).is_none());
+    // 无法解析的响应被跳过
+    assert!(match_response::<RegisterResponse>(b"not json", "1001", topic).is_none());
+}
@@ -1,39 +1,139 @@
-use reqwest::{Client, StatusCode};
-use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
-use serde_json::json;
-use base64::{Engine, engine::general_purpose::STANDARD as b64_standard};
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
+use std::collections::{HashMap, HashSet};
+use reqwest::Method;
+use serde::Serialize;
 use tokio::time::Duration;
 use crate::model::south::{Measurement, PointControl, Transport};
 use crate::utils::global::PLCC_LAST_RESET_TIME;
-use crate::{ADAPTER_NAME, AdapterErr, ErrCode, URL_LOGIN, URL_POINTS,
-    URL_PLCC_RESET, URL_TRANSPORTS, URL_POINT_CONTROL};
-use crate::env::Env;
-
-const PASSWORD_V_KEY: &[u8] = b"zju-plcc";
-const HEADER_TOKEN: &str = "access-token";
-
-type HmacSha256 = Hmac<Sha256>;
-
-pub async fn update_points(points: Vec<Measurement>) -> Result<(), AdapterErr> {
-    let token = login().await?;
-    let old_points = query_points(token.clone()).await?;
-    let pids = old_points.iter().map(|v| v.point_id).collect::<Vec<u64>>();
+use crate::utils::httpclient::PLCC_CLIENT;
+use crate::utils::is_same_model;
+use crate::{AdapterErr, ErrCode, URL_POINTS, URL_PLCC_RESET, URL_TRANSPORTS, URL_POINT_CONTROL};
+
+/// 与PLCC中已有的测点比较，只删除、新增和修改有变化的测点，返回是否需要reset
+/// PLCC没有修改接口，修改的测点按相同ID重新新增覆盖，先新增再删除，删除失败时不会丢失测点。
+/// PLCC的接口没有说明新增已存在的ID时是否覆盖，保存后重新查询，确认已覆盖且没有重复的ID
+pub async fn update_points(points: Vec<Measurement>) -> Result<bool, AdapterErr> {
+    let old_points = query_points().await?;
+    let old_map = old_points.iter().map(|v| (v.point_id, v)).collect::<HashMap<u64, &Measurement>>();
+    let new_ids = points.iter().map(|v| v.point_id).collect::<HashSet<u64>>();
+    let mut need_reset = false;
+    let pids = old_points.iter()
+        .filter(|v| !new_ids.contains(&v.point_id))
+        .map(|v| v.point_id)
+        .collect::<Vec<u64>>();
     if !pids.is_empty() {
-        delete_points(token.clone(), pids).await?;
+        need_reset = true;
+    }
+    let mut to_save = Vec::new();
+    for p in points {
+        match old_map.get(&p.point_id) {
+            Some(old) => {
+                if !is_same_model(*old, &p) {
+                    need_reset = need_reset || is_point_structural_changed(old, &p);
+                    to_save.push(p);
+                }
+            }
+            None => {
+                need_reset = true;
+                to_save.push(p);
+            }
+        }
     }
-    save_points(token, points).await
+    if !to_save.is_empty() {
+        save_points(&to_save).await?;
+        check_saved(&to_save, &query_points().await?, |v| v.point_id, "测点")?;
+    }
+    if !pids.is_empty() {
+        delete_points(pids).await?;
+    }
+    Ok(need_reset)
 }
 
-pub async fn update_transports(transports: Vec<Transport>) -> Result<(), AdapterErr> {
-    let token = login().await?;
-    let old_transports = query_transports(token.clone()).await?;
-    let tids = old_transports.iter().map(|v| v.id()).collect::<Vec<u64>>();
+/// 与PLCC中已有的通道比较，只删除、新增和修改有变化的通道，返回是否需要reset
+/// 修改的通道同样按相同ID重新新增覆盖，先新增再删除，保存后重新查询确认已覆盖
+pub async fn update_transports(transports: Vec<Transport>) -> Result<bool, AdapterErr> {
+    let old_transports = query_transports().await?;
+    let old_map = old_transports.iter().map(|v| (v.id(), v)).collect::<HashMap<u64, &Transport>>();
+    let new_ids = transports.iter().map(|v| v.id()).collect::<HashSet<u64>>();
+    let mut need_reset = false;
+    let tids = old_transports.iter()
+        .filter(|v| !new_ids.contains(&v.id()))
+        .map(|v| v.id())
+        .collect::<Vec<u64>>();
+    if !tids.is_empty() {
+        need_reset = true;
+    }
+    let mut to_save = Vec::new();
+    for t in transports {
+        match old_map.get(&t.id()) {
+            Some(old) => {
+                if !is_same_model(*old, &t) {
+                    need_reset = need_reset || is_transport_structural_changed(old, &t);
+                    to_save.push(t);
+                }
+            }
+            None => {
+                need_reset = true;
+                to_save.push(t);
+            }
+        }
+    }
+    if !to_save.is_empty() {
+        save_transports(&to_save).await?;
+        check_saved(&to_save, &query_transports().await?, |v| v.id(), "通道")?;
+    }
     if !tids.is_empty() {
-        delete_transports(token.clone(), tids).await?;
+        delete_transports(tids).await?;
     }
-    save_transports(token, transports).await
+    Ok(need_reset)
+}
+
+// 保存的每一项在PLCC中只能有一个，且与保存的内容相同，否则说明PLCC没有按ID覆盖
+fn check_saved<T: Serialize>(saved: &[T], current: &[T], id: fn(&T) -> u64, name: &str) -> Result<(), AdapterErr> {
+    let mut current_map = HashMap::with_capacity(current.len());
+    for v in current {
+        current_map.entry(id(v)).or_insert_with(Vec::new).push(v);
+    }
+    for v in saved {
+        let overwritten = match current_map.get(&id(v)) {
+            Some(items) => items.len() == 1 && is_same_model(items[0], v),
+            None => false,
+        };
+        if !overwritten {
+            return Err(AdapterErr {
+                code: ErrCode::PlccActionErr,
+                msg: format!("PLCC中的{name}{}与保存的内容不一致", id(v)),
+            });
+        }
+    }
+    Ok(())
+}
+
+// 名称、描述和上下限的修改不影响采集和计算，不需要reset
+fn is_point_structural_changed(old: &Measurement, new: &Measurement) -> bool {
+    let mut new = new.clone();
+    new.point_name = old.point_name.clone();
+    new.desc = old.desc.clone();
+    new.upper_limit = old.upper_limit;
+    new.lower_limit = old.lower_limit;
+    !is_same_model(old, &new)
+}
+
+// 只修改通道名称时不需要reset
+fn is_transport_structural_changed(old: &Transport, new: &Transport) -> bool {
+    match (old, new) {
+        (Transport::Mqtt(old), Transport::Mqtt(new)) => {
+            let mut new = new.clone();
+            new.name = old.name.clone();
+            *old != new
+        }
+    }
+}
+
+/// 获取PLCC当前的测点和通道，用于更新失败时回滚
+pub async fn query_plcc_models() -> Result<(Vec<Measurement>, Vec<Transport>), AdapterErr> {
+    let points = query_points().await?;
+    let transports = query_transports().await?;
+    Ok((points, transports))
 }
 
 pub async fn do_reset_plcc() -> Result<(), AdapterErr> {
@@ -47,287 +147,97 @@ pub async fn do_reset_plcc() -> Result<(), AdapterErr> {
             break;
         }
     }
-    let token = login().await?;
-    reset(token).await
+    reset().await
 }
 
-async fn delete_points(token: String, ids: Vec<u64>) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let plcc_server = env.get_plcc_server();
-    let url = format!("{plcc_server}/{URL_POINTS}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .delete(&url)
-        .headers(headers)
-        .json(&ids)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::PlccConnectErr,
-                msg: "调用测点API删除测点失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::PlccConnectErr,
-            msg: "failed to connect PLCC".to_string(),
-        })
-    }
+async fn delete_points(ids: Vec<u64>) -> Result<(), AdapterErr> {
+    PLCC_CLIENT.send_json(Method::DELETE, URL_POINTS, &ids, "调用测点API删除测点").await
 }
 
-async fn query_points(token: String) -> Result<Vec<Measurement>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let plcc_server = env.get_plcc_server();
-    let url = format!("{plcc_server}/{URL_POINTS}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        if let Ok(points) = response.json::<Vec<Measurement>>().await {
-            Ok(points)
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::PlccConnectErr,
-                msg: "调用测点API获取测点失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::PlccConnectErr,
-            msg: "failed to connect PLCC".to_string(),
-        })
-    }
+async fn query_points() -> Result<Vec<Measurement>, AdapterErr> {
+    PLCC_CLIENT.get_json(URL_POINTS, "调用测点API获取测点").await
 }
 
-async fn save_points(token: String, points: Vec<Measurement>) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let plcc_server = env.get_plcc_server();
-    let url = format!("{plcc_server}/{URL_POINTS}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .json(&points)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::PlccConnectErr,
-                msg: "调用测点API新增测点失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::PlccConnectErr,
-            msg: "failed to connect PLCC".to_string(),
-        })
-    }
+async fn save_points(points: &[Measurement]) -> Result<(), AdapterErr> {
+    PLCC_CLIENT.send_json(Method::POST, URL_POINTS, points, "调用测点API新增或覆盖测点").await
 }
 
-async fn delete_transports(token: String, ids: Vec<u64>) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let plcc_server = env.get_plcc_server();
+async fn delete_transports(ids: Vec<u64>) -> Result<(), AdapterErr> {
     let ids = ids.iter()
         .map(|n| n.to_string())
         .collect::<Vec<_>>()
         .join(",");
-    let url = format!("{plcc_server}/{URL_TRANSPORTS}/{ids}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .delete(&url)
-        .headers(headers)
-        .json(&ids)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::PlccConnectErr,
-                msg: "调用通道API删除通道失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::PlccConnectErr,
-            msg: "failed to connect PLCC".to_string(),
-        })
-    }
+    let path = format!("{URL_TRANSPORTS}/{ids}");
+    PLCC_CLIENT.send_json(Method::DELETE, &path, &ids, "调用通道API删除通道").await
 }
 
-async fn query_transports(token: String) -> Result<Vec<Transport>, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let plcc_server = env.get_plcc_server();
-    let url = format!("{plcc_server}/{URL_TRANSPORTS}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .get(&url)
-        .headers(headers)
-        .send().await {
-        if let Ok(transports) = response.json::<Vec<Transport>>().await {
-            Ok(transports)
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::PlccConnectErr,
-                msg: "调用通道API获取通道失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::PlccConnectErr,
-            msg: "failed to connect PLCC".to_string(),
-        })
-    }
+async fn query_transports() -> Result<Vec<Transport>, AdapterErr> {
+    PLCC_CLIENT.get_json(URL_TRANSPORTS, "调用通道API获取通道").await
 }
 
-async fn save_transports(token: String, transports: Vec<Transport>) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let plcc_server = env.get_plcc_server();
-    let url = format!("{plcc_server}/{URL_TRANSPORTS}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .json(&transports)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::PlccConnectErr,
-                msg: "调用通道API新增通道失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::PlccConnectErr,
-            msg: "failed to connect PLCC".to_string(),
-        })
-    }
+async fn save_transports(transports: &[Transport]) -> Result<(), AdapterErr> {
+    PLCC_CLIENT.send_json(Method::POST, URL_TRANSPORTS, transports, "调用通道API新增或覆盖通道").await
 }
 
-async fn login() -> Result<String, AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let plcc_user = env.get_plcc_user();
-    let plcc_pwd = env.get_plcc_pwd();
-    let plcc_server = env.get_plcc_server();
-    let login_url = format!("{plcc_server}/{URL_LOGIN}");
-    let body = json!((plcc_user, password_v_encode(plcc_pwd)));
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&login_url)
-        .json(&body)
-        .send().await {
-        let (token, user_id, _user_name) = response.json::<(String, u16, String)>().await.unwrap();
-        if user_id == 0 {
-            let error = match token.as_str() {
-                "password_error" => {
-                    "密码错误".to_string()
-                },
-                "login_count_exceeded" => {
-                    "密码连续错误超过5次，请5分钟后再试".to_string()
-                },
-                "user_expired" => {
-                    "此用户账号已过期".to_string()
-                },
-                _ => {
-                    "未知错误".to_string()
-                }
-            };
-            Err(AdapterErr {
-                code: ErrCode::PlccConnectErr,
-                msg: error,
-            })
-        } else {
-            Ok(token)
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::PlccConnectErr,
-            msg: "PLCC登录失败".to_string(),
-        })
-    }
+async fn reset() -> Result<(), AdapterErr> {
+    PLCC_CLIENT.send_bytes(Method::POST, URL_PLCC_RESET, None, "调用重置API").await
 }
 
-// 前端加密
-fn password_v_encode(password: String) -> String {
-    let mut mac = HmacSha256::new_from_slice(PASSWORD_V_KEY).expect("HMAC can take key of any size");
-    mac.update(password.as_bytes());
-    //加密算法
-    let result = b64_standard.encode(mac.finalize().into_bytes());
-    result
+pub async fn do_point_action(point_control: PointControl) -> Result<(), AdapterErr> {
+    point_action(point_control).await
 }
 
-fn get_header(token: String) -> HeaderMap {
-    let mut headers = HeaderMap::new();
-    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
-    headers.insert(HEADER_TOKEN, HeaderValue::from_str(&token).unwrap());
-    headers
+async fn point_action(point_control: PointControl) -> Result<(), AdapterErr> {
+    PLCC_CLIENT.send_json(Method::POST, URL_POINT_CONTROL, &point_control, "调用测点指令API").await
 }
 
-async fn reset(token: String) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let plcc_server = env.get_plcc_server();
-    let url = format!("{plcc_server}/{URL_PLCC_RESET}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::PlccConnectErr,
-                msg: "调用重置API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::PlccConnectErr,
-            msg: "failed to connect PLCC".to_string(),
-        })
-    }
+#[cfg(test)]
+fn test_measurement(point_id: u64) -> Measurement {
+    serde_json::from_value(serde_json::json!({
+        "point_id": point_id, "point_name": format!("p{point_id}"), "alias_id": "", "is_discrete": false,
+        "is_computing_point": false, "expression": "", "trans_expr": "", "inv_trans_expr": "",
+        "change_expr": "", "zero_expr": "", "data_unit": "", "upper_limit": 100.0, "lower_limit": 0.0,
+        "alarm_level1_expr": "", "alarm_level2_expr": "", "is_realtime": false, "is_soe": false,
+        "init_value": 0, "desc": ""
+    })).unwrap()
 }
 
-pub async fn do_point_action(point_control: PointControl) -> Result<(), AdapterErr> {
-    let token = login().await?;
-    point_action(token, point_control).await
+#[test]
+fn test_is_point_structural_changed() {
+    let old = test_measurement(1);
+    let mut new = old.clone();
+    new.point_name = "p".to_string();
+    new.desc = "desc".to_string();
+    new.upper_limit = 200.0;
+    new.lower_limit = -100.0;
+    assert!(!is_point_structural_changed(&old, &new));
+    new.trans_expr = "x*2".to_string();
+    assert!(is_point_structural_changed(&old, &new));
+    let mut new = old.clone();
+    new.is_discrete = true;
+    assert!(is_point_structural_changed(&old, &new));
 }
 
-async fn point_action(token: String, point_control: PointControl) -> Result<(), AdapterErr> {
-    let env = Env::get_env(ADAPTER_NAME);
-    let plcc_server = env.get_plcc_server();
-    let url = format!("{plcc_server}/{URL_POINT_CONTROL}");
-    let headers = get_header(token);
-    let client = Client::new();
-    if let Ok(response) = client
-        .post(&url)
-        .headers(headers)
-        .json(&point_control)
-        .send().await {
-        if response.status() == StatusCode::OK {
-            Ok(())
-        } else {
-            Err(AdapterErr {
-                code: ErrCode::PlccConnectErr,
-                msg: "调用测点指令API失败".to_string(),
-            })
-        }
-    } else {
-        Err(AdapterErr {
-            code: ErrCode::PlccConnectErr,
-            msg: "failed to connect PLCC".to_string(),
-        })
-    }
+#[test]
+fn test_is_transport_structural_changed() {
+    let old = crate::model::south::MqttTransport { id: 1, name: "t1".to_string(), point_ids: vec![(1, false)], ..Default::default() };
+    let mut new = old.clone();
+    new.name = "t2".to_string();
+    assert!(!is_transport_structural_changed(&Transport::Mqtt(old.clone()), &Transport::Mqtt(new.clone())));
+    new.point_ids.push((2, true));
+    assert!(is_transport_structural_changed(&Transport::Mqtt(old), &Transport::Mqtt(new)));
+}
+
+#[test]
+fn test_check_saved() {
+    let saved = vec![test_measurement(1), test_measurement(2)];
+    assert!(check_saved(&saved, &saved, |v| v.point_id, "测点").is_ok());
+    // 没有覆盖原有的测点
+    let mut old = test_measurement(2);
+    old.desc = "old".to_string();
+    assert!(check_saved(&saved, &[test_measurement(1), old], |v| v.point_id, "测点").is_err());
+    // 新增了重复的ID
+    let current = vec![test_measurement(1), test_measurement(2), test_measurement(2)];
+    assert!(check_saved(&saved, &current, |v| v.point_id, "测点").is_err());
+    assert!(check_saved(&saved, &saved[..1], |v| v.point_id, "测点").is_err());
 }
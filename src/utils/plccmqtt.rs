@@ -5,21 +5,26 @@ use std::str::FromStr;
 use eig_domain::topics::set_points_result;
 use eig_domain::{PbSetPointResults, SetIntValue};
 use protobuf::Message;
-use tokio::time::Duration;
 use chrono::{Local, TimeZone};
-use rumqttc::{AsyncClient, Event, Incoming};
 
 use crate::utils::appapi::do_get_number_array;
 use crate::utils::global::APP_API_PARAM_MAP;
-use crate::utils::mqttclient::{client_subscribe, get_mqttoptions, mqtt_acquirer, mqtt_provider, mqtt_push_only};
-use crate::{ADAPTER_NAME, AdapterErr, ErrCode, MODEL_FROZEN};
+use crate::utils::mqttclient::{generate_token, mqtt_acquirer, mqtt_provider, mqtt_push_only};
+use crate::utils::mqttmanager::plcc_manager;
+use crate::{ADAPTER_NAME, AdapterErr, ApiResponse, ErrCode, MODEL_FROZEN};
+use crate::model::revision::RevisionSource;
+use crate::parser::{job_api_response, submit_parser_job, JobInput};
 use crate::env::Env;
 use crate::model::datacenter::*;
-use crate::model::north::{AppApiResultType, MyAoes, MyPbAoeResult, MyPoints, MyTransport, MyTransports};
+use crate::model::north::{AppApiParam, AppApiResultType, MyAoes, MyPbAoeResult, MyPoints, MyTransport, MyTransports};
 use crate::model::south::{AoeAction, AoeControl, Expr, PointControl};
 use crate::utils::{get_point_attr, register_result};
 use crate::utils::localapi::{query_aoe_mapping, query_app_api_mapping, query_dev_mapping};
 use crate::utils::plccapi::do_point_action;
+use crate::utils::events::{publish, AdapterEvent};
+use crate::utils::metrics::{inc_counter, CLOUD_EVENTS};
+use crate::utils::health::record_data_query;
+use crate::utils::supervisor::*;
 use crate::utils::memsapi::{do_aoe_action, do_query_aoe_status, do_query_aoes};
 
 pub async fn do_query_dev(transports: &Vec<MyTransport>) -> Result<Vec<QueryDevResponseBody>, AdapterErr> {
@@ -62,21 +67,25 @@ pub async fn do_query_dev(transports: &Vec<MyTransport>) -> Result<Vec<QueryDevR
 }
 
 pub async fn do_register() -> Result<(), AdapterErr> {
-    tokio::spawn(async {
-        let mut result = true;
-        if let Err(err) = do_register_model().await {
-            result = false;
-            log::error!("{}", err.msg);
-        }
-        if let Err(err) = do_register_app().await {
-            result = false;
-            log::error!("{}", err.msg);
-        }
-        register_result::set_result(result);
-    });
+    supervise(TASK_REGISTER, RestartPolicy::OnFailure, register);
     Ok(())
 }
 
+// 注册失败时返回最后一个错误，由监管任务重试
+async fn register() -> Result<(), AdapterErr> {
+    let mut result = Ok(());
+    if let Err(err) = do_register_model().await {
+        log::error!("{}", err.msg);
+        result = Err(err);
+    }
+    if let Err(err) = do_register_app().await {
+        log::error!("{}", err.msg);
+        result = Err(err);
+    }
+    register_result::set_result(result.is_ok());
+    result
+}
+
 pub async fn do_register_sync() -> Result<(), AdapterErr> {
     let _ = do_register_model().await?;
     let _ = do_register_app().await?;
@@ -214,13 +223,7 @@ async fn get_has_app_registered() -> Result<bool, AdapterErr> {
 }
 
 pub async fn do_data_query() -> Result<(), AdapterErr> {
-    tokio::spawn(async {
-        // 等待5秒，避免因为API还没启动或者数据还未写入完成，查询失败
-        actix_rt::time::sleep(Duration::from_millis(5000)).await;
-        if let Err(e) = data_query().await {
-            log::error!("do data_query error: {}", e.msg);
-        }
-    });
+    supervise(TASK_DATA_QUERY, RestartPolicy::OnFailure, data_query);
     Ok(())
 }
 
@@ -233,20 +236,17 @@ pub async fn data_query() -> Result<(), AdapterErr> {
         mqtt_push_only(
             "plcc_data_query".to_string(),
             format!("/sys.dbc/{app_name}/S-dataservice/F-GetRealData"),
-            format!("/{app_name}/sys.dbc/S-dataservice/F-GetRealData"),
             body,
-        ).await
+        ).await?;
+        record_data_query();
+        Ok(())
     } else {
         Ok(())
     }
 }
 
 pub async fn do_keep_alive() -> Result<(), AdapterErr> {
-    tokio::spawn(async {
-        if let Err(e) = keep_alive().await {
-            log::error!("do keep_alive error: {}", e.msg);
-        }
-    });
+    supervise(TASK_KEEP_ALIVE, RestartPolicy::Always, keep_alive);
     Ok(())
 }
 
@@ -304,11 +304,7 @@ pub async fn query_register_dev() -> Result<String, AdapterErr> {
 }
 
 pub async fn do_cloud_event() -> Result<(), AdapterErr> {
-    tokio::spawn(async {
-        if let Err(e) = cloud_event().await {
-            log::error!("do cloud_event error: {}", e.msg);
-        }
-    });
+    supervise(TASK_CLOUD_EVENT, RestartPolicy::Always, cloud_event);
     Ok(())
 }
 
@@ -322,7 +318,13 @@ pub async fn cloud_event() -> Result<(), AdapterErr> {
         move |payload| {
             Box::pin(async move {
                 if let Ok(msg) = serde_json::from_slice::<CloudEventRequest>(&payload) {
-                    match msg.cmd {
+                    let cmd = msg.cmd.clone();
+                    publish(AdapterEvent::CloudEventRequest {
+                        token: msg.token.clone(),
+                        request_id: msg.request_id.clone(),
+                        cmd: cmd.clone(),
+                    });
+                    let resp = match msg.cmd {
                         CloudEventCmd::GetTgPLCCConfig => {
                             do_get_plcc_config(msg)
                         },
@@ -331,25 +333,48 @@ pub async fn cloud_event() -> Result<(), AdapterErr> {
                         },
                         CloudEventCmd::GetTgAOEStatus => {
                             do_get_aoe_status(msg).await
+                        },
+                        CloudEventCmd::SetTgPLCCConfig => {
+                            do_set_plcc_config(msg).await
+                        },
+                        CloudEventCmd::SetTgAOEConfig => {
+                            do_set_aoe_config(msg).await
                         }
-                    }
+                    };
+                    publish_cloud_response(Some(cmd), &resp);
+                    resp
                 } else {
                     log::error!("do cloud_event 序列化错误: {payload:?}");
                     let time = Local::now().timestamp_millis();
                     let data = get_aoe_status_body(None, ErrCode::DataJsonDeserializeErr, "Json格式错误".to_string());
-                    CloudEventResponse {
+                    let resp = CloudEventResponse {
                         token: time.to_string(),
                         request_id: time.to_string(),
                         time: generate_current_time(),
                         msg_info: "".to_string(),
                         data,
-                    }
+                    };
+                    publish_cloud_response(None, &resp);
+                    resp
                 }
             })
         },
     ).await
 }
 
+fn publish_cloud_response(cmd: Option<CloudEventCmd>, resp: &CloudEventResponse) {
+    let cmd_label = cmd.as_ref().map(|c| format!("{c:?}")).unwrap_or_else(|| "Unknown".to_string());
+    inc_counter(CLOUD_EVENTS, &[("cmd", &cmd_label), ("code", &resp.data.code.name())]);
+    publish(AdapterEvent::CloudEventResponse {
+        token: resp.token.clone(),
+        request_id: resp.request_id.clone(),
+        cmd,
+        code: resp.data.code.clone(),
+        msg: resp.data.msg.clone(),
+        stage: resp.data.stage.clone(),
+    });
+}
+
 fn do_get_plcc_config(cloud_event: CloudEventRequest) -> CloudEventResponse {
     let env = Env::get_env(ADAPTER_NAME);
     let result_dir = env.get_result_dir();
@@ -403,10 +428,65 @@ fn do_get_plcc_config(cloud_event: CloudEventRequest) -> CloudEventResponse {
             aoes_status: None,
             code: ErrCode::Success,
             msg: "".to_string(),
+            stage: None,
+            details: None,
         }
     }
 }
 
+// 与HTTP上传相同，提交后台任务后立即返回任务信息
+async fn do_set_plcc_config(cloud_event: CloudEventRequest) -> CloudEventResponse {
+    let (points, transports) = match &cloud_event.body {
+        Some(body) => (body.points.clone(), body.transports.clone()),
+        None => (None, None),
+    };
+    let resp = if points.is_none() && transports.is_none() {
+        ApiResponse::from_err(AdapterErr {
+            code: ErrCode::DataJsonDeserializeErr,
+            msg: "未包含测点或通道配置".to_string(),
+        })
+    } else {
+        job_api_response(submit_parser_job(JobInput::UploadPlcc(points, transports, RevisionSource::CloudEvent)).await)
+    };
+    set_config_response(cloud_event, resp)
+}
+
+// 提交后台任务，同步结果通过任务状态查询
+async fn do_set_aoe_config(cloud_event: CloudEventRequest) -> CloudEventResponse {
+    let aoes = cloud_event.body.as_ref().and_then(|body| body.aoes.clone());
+    let resp = if let Some(aoes) = aoes {
+        job_api_response(submit_parser_job(JobInput::UploadMems(Some(aoes), None, RevisionSource::CloudEvent)).await)
+    } else {
+        ApiResponse::from_err(AdapterErr {
+            code: ErrCode::DataJsonDeserializeErr,
+            msg: "未包含策略配置".to_string(),
+        })
+    };
+    set_config_response(cloud_event, resp)
+}
+
+fn set_config_response(cloud_event: CloudEventRequest, resp: ApiResponse) -> CloudEventResponse {
+    if resp.code != ErrCode::Success {
+        log::warn!("下发配置失败，阶段：{:?}，{}", resp.stage, resp.msg);
+    }
+    CloudEventResponse {
+        token: cloud_event.token,
+        request_id: cloud_event.request_id,
+        time: generate_current_time(),
+        msg_info: "".to_string(),
+        data: CloudEventResponseBody {
+            points: None,
+            transports: None,
+            aoes: None,
+            aoes_status: None,
+            code: resp.code,
+            msg: resp.msg,
+            stage: resp.stage,
+            details: resp.details,
+        },
+    }
+}
+
 async fn do_aoe_control(cloud_event: CloudEventRequest) -> CloudEventResponse {
     let data = 'result: {
         if let Some(body) = cloud_event.body {
@@ -501,53 +581,36 @@ async fn do_get_aoe_status(cloud_event: CloudEventRequest) -> CloudEventResponse
             aoes_status,
             code,
             msg,
+            stage: None,
+            details: None,
         },
     }
 }
 
 pub async fn do_app_api_event() -> Result<(), AdapterErr> {
-    tokio::spawn(async {
-        if let Err(e) = app_api_event().await {
-            log::error!("do app_api_event error: {}", e.msg);
-        }
-    });
+    supervise(TASK_APP_API_EVENT, RestartPolicy::Always, app_api_event);
     Ok(())
 }
 
 pub async fn app_api_event() -> Result<(), AdapterErr> {
     let env = Env::get_env(ADAPTER_NAME);
-    let user_name = "adapter";
-    let mqtt_server = "127.0.0.1";
-    let mqtt_server_port = env.get_plcc_mqtt_port();
     let beeid = env.get_plcc_beeid();
     let topic_response = set_points_result(&beeid);
-    let mqttoptions = get_mqttoptions(user_name, mqtt_server, mqtt_server_port);
-    let (client, mut eventloop) = AsyncClient::new(mqttoptions, 10);
-    client_subscribe(&client, &topic_response).await?;
-    tokio::spawn(async move {
-        loop {
-            match eventloop.poll().await {
-                Ok(Event::Incoming(Incoming::Publish(p))) => {
-                    if p.topic == topic_response {
-                        let mut results = PbSetPointResults::new();
-                        if let Ok(()) = results.merge_from_bytes(&p.payload) {
-                            if let Err(e) = app_api_request(results).await {
-                                log::error!("do app_api_request error: {}", e.msg);
-                            }
-                        } else {
-                            log::warn!("!!Failed to parse bytes to Vec<SetPointResult>");
-                        }
-                    }
-                }
-                Ok(_) => {}
-                Err(e) => {
-                    log::error!("do app_api_event error: {e:?}");
-                    actix_rt::time::sleep(Duration::from_millis(5000)).await;
-                }
+    let rx = plcc_manager()?.subscribe(&topic_response).await?;
+    while let Ok(payload) = rx.recv().await {
+        let mut results = PbSetPointResults::new();
+        if let Ok(()) = results.merge_from_bytes(&payload) {
+            if let Err(e) = app_api_request(results).await {
+                log::error!("do app_api_request error: {}", e.msg);
             }
+        } else {
+            log::warn!("!!Failed to parse bytes to Vec<SetPointResult>");
         }
-    });
-    Ok(())
+    }
+    Err(AdapterErr {
+        code: ErrCode::MqttConnectErr,
+        msg: "设置测点结果的订阅已关闭".to_string(),
+    })
 }
 
 async fn app_api_request(results: PbSetPointResults) -> Result<(), AdapterErr> {
@@ -569,41 +632,19 @@ async fn app_api_request(results: PbSetPointResults) -> Result<(), AdapterErr> {
                 match param.result_type {
                     AppApiResultType::NumberArray => {
                         log::info!("开始调用第三方{}", param.app_url);
-                        let mut aoe_action = vec![];
-                        let number_array = do_get_number_array(&param.app_url).await?;
-                        let mut aoes = do_query_aoes().await?;
-                        for aoe in aoes.iter_mut() {
-                            let mut updated = false;
-                            for (k, v) in aoe.variables.iter_mut() {
-                                if *k == param.aoe_variable {
-                                    if let Ok(e) = Expr::from_str(&format!("{number_array:?}")) {
-                                        *v = e;
-                                        updated = true;
-                                    }
-                                }
-                            }
-                            if updated {
-                                aoe_action.push(AoeAction::UpdateAoe(aoe.clone()));
-                            }
-                        }
-                        // 将虚拟测点值重置设置为0
-                        match do_aoe_action(AoeControl { AoeActions: aoe_action }).await {
-                            Ok(_) => {
-                                let cmd = PointControl {
-                                    discretes: vec![SetIntValue {
-                                        sender_id: 1,
-                                        point_id: param.point_id,
-                                        yk_command: 0,
-                                        timestamp: 0,
-                                    }],
-                                    analogs: vec![],
-                                };
-                                do_point_action(cmd).await?;
-                            }
-                            Err(e) => {
-                                return Err(e);
-                            }
-                        }
+                        let result = invoke_number_array_api(param).await;
+                        let (code, msg) = match &result {
+                            Ok(()) => (ErrCode::Success, "".to_string()),
+                            Err(e) => (e.code.clone(), e.msg.clone()),
+                        };
+                        publish(AdapterEvent::AppApiInvoked {
+                            point_id: param.point_id,
+                            app_url: param.app_url.clone(),
+                            aoe_variable: param.aoe_variable.clone(),
+                            code,
+                            msg,
+                        });
+                        result?;
                     }
                 }
             }
@@ -612,6 +653,46 @@ async fn app_api_request(results: PbSetPointResults) -> Result<(), AdapterErr> {
     Ok(())
 }
 
+// 获取第三方APP返回的数组，写入引用该变量的策略后将虚拟测点复位
+async fn invoke_number_array_api(param: &AppApiParam) -> Result<(), AdapterErr> {
+    let mut aoe_action = vec![];
+    let number_array = do_get_number_array(&param.app_url).await?;
+    let mut aoes = do_query_aoes().await?;
+    for aoe in aoes.iter_mut() {
+        let mut updated = false;
+        for (k, v) in aoe.variables.iter_mut() {
+            if *k == param.aoe_variable {
+                if let Ok(e) = Expr::from_str(&format!("{number_array:?}")) {
+                    *v = e;
+                    updated = true;
+                }
+            }
+        }
+        if updated {
+            aoe_action.push(AoeAction::UpdateAoe(aoe.clone()));
+        }
+    }
+    // 将虚拟测点值重置设置为0
+    match do_aoe_action(AoeControl { AoeActions: aoe_action }).await {
+        Ok(_) => {
+            let cmd = PointControl {
+                discretes: vec![SetIntValue {
+                    sender_id: 1,
+                    point_id: param.point_id,
+                    yk_command: 0,
+                    timestamp: 0,
+                }],
+                analogs: vec![],
+            };
+            do_point_action(cmd).await?;
+        }
+        Err(e) => {
+            return Err(e);
+        }
+    }
+    Ok(())
+}
+
 fn get_aoe_status_body(aoes_status: Option<Vec<CloudEventAoeStatus>>, code: ErrCode, msg: String) -> CloudEventResponseBody {
     CloudEventResponseBody {
         points: None,
@@ -620,22 +701,24 @@ fn get_aoe_status_body(aoes_status: Option<Vec<CloudEventAoeStatus>>, code: ErrC
         aoes_status,
         code,
         msg,
+        stage: None,
+        details: None,
     }
 }
 
 fn generate_get_app_register(model: String) -> QueryRegisterDev {
-    let time = Local::now().timestamp_millis();
+    let token = generate_token();
     QueryRegisterDev {
-        token: time.to_string(),
+        token,
         time: generate_current_time(),
         body: vec![model],
     }
 }
 
 fn generate_get_model_register(model: String) -> GetModel {
-    let time = Local::now().timestamp_millis();
+    let token = generate_token();
     GetModel {
-        token: time.to_string(),
+        token,
         time: generate_current_time(),
         body: vec![model],
     }
@@ -654,9 +737,9 @@ fn generate_register_model_body() -> RegisterModelBody {
 }
 
 fn generate_register_model(model: String) -> RegisterModel {
-    let time = Local::now().timestamp_millis();
+    let token = generate_token();
     RegisterModel {
-        token: time.to_string(),
+        token,
         time: generate_current_time(),
         model,
         body: vec![generate_register_model_body()],
@@ -680,9 +763,9 @@ fn generate_register_app_body(model: String) -> RegisterAPPBody {
 }
 
 fn generate_register_app(model: String) -> RegisterApp {
-    let time = Local::now().timestamp_millis();
+    let token = generate_token();
     RegisterApp {
-        token: time.to_string(),
+        token,
         time: generate_current_time(),
         body: vec![generate_register_app_body(model)],
     }
@@ -694,7 +777,7 @@ fn generate_current_time() -> String {
 }
 
 fn generate_query_data(devs: &Vec<QueryDevResponseBody>) -> DataQuery {
-    let time = Local::now().timestamp_millis();
+    let token = generate_token();
     let body = devs.iter().flat_map(|dev|
         dev.devs.iter().map(|v|{
             DataQueryBody {
@@ -705,25 +788,25 @@ fn generate_query_data(devs: &Vec<QueryDevResponseBody>) -> DataQuery {
         }).collect::<Vec<DataQueryBody>>()
     ).collect::<Vec<DataQueryBody>>();
     DataQuery {
-        token: time.to_string(),
+        token,
         time: generate_current_time(),
         body,
     }
 }
 
 fn generate_query_register_dev() -> QueryRegisterDev {
-    let time = Local::now().timestamp_millis();
+    let token = generate_token();
     QueryRegisterDev {
-        token: time.to_string(),
+        token,
         time: generate_current_time(),
         body: vec![MODEL_FROZEN.to_string()],
     }
 }
 
 fn generate_query_dev(query_dev_bodys: Vec<QueryDevBody>) -> QueryDev {
-    let time = Local::now().timestamp_millis();
+    let token = generate_token();
     QueryDev {
-        token: time.to_string(),
+        token,
         time: generate_current_time(),
         devices: query_dev_bodys,
     }
@@ -0,0 +1,283 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::sync::{Mutex, RwLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use chrono::Local;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use tokio::sync::watch;
+use tokio::time::Duration;
+
+use crate::AdapterErr;
+
+// 常驻后台任务的名称
+pub const TASK_PARSER: &str = "parser";
+pub const TASK_REGISTER: &str = "register";
+pub const TASK_DATA_QUERY: &str = "plcc_data_query";
+pub const TASK_AOE_UPLOAD: &str = "aoe_result_upload";
+pub const TASK_DFF_UPLOAD: &str = "dff_result_upload";
+pub const TASK_KEEP_ALIVE: &str = "plcc_keep_alive";
+pub const TASK_CLOUD_EVENT: &str = "plcc_event";
+pub const TASK_APP_API_EVENT: &str = "app_api_event";
+pub const TASK_MEMS_EVENT: &str = "mems_event";
+pub const TASK_CONFIG_WATCH: &str = "config_watch";
+
+// 重启间隔从1秒开始翻倍，最长60秒
+const RESTART_MAX_SECS: u64 = 60;
+// 运行超过该时间后退出的，重新从最短间隔开始重启
+const STABLE_SECS: i64 = 60;
+// 连续失败超过该次数时健康检查报告异常
+const MAX_CONSECUTIVE_FAILURES: u64 = 5;
+
+static TASKS: Lazy<RwLock<BTreeMap<String, TaskState>>> = Lazy::new(|| RwLock::new(BTreeMap::new()));
+static SHUTDOWN: Lazy<watch::Sender<bool>> = Lazy::new(|| watch::channel(false).0);
+// 正在运行的受监管任务的监管序号和重启通知
+type RestartEntry = (u64, watch::Sender<u64>);
+static RESTARTS: Lazy<Mutex<HashMap<String, RestartEntry>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Debug, Clone)]
+pub struct TaskState {
+    pub name: String,
+    pub alive: bool,
+    /// 退出后是否会自动重启
+    pub supervised: bool,
+    /// 一次性任务已成功完成
+    pub completed: bool,
+    pub restart_count: u64,
+    /// 连续失败的次数，运行稳定后清零
+    pub consecutive_failures: u64,
+    pub last_error: Option<String>,
+    pub started_at: Option<i64>,
+    pub stopped_at: Option<i64>,
+}
+
+impl TaskState {
+    fn new(name: &str) -> Self {
+        TaskState {
+            name: name.to_string(),
+            alive: false,
+            supervised: false,
+            completed: false,
+            restart_count: 0,
+            consecutive_failures: 0,
+            last_error: None,
+            started_at: None,
+            stopped_at: None,
+        }
+    }
+
+    /// 正在运行、已完成或者仍在重启中
+    pub fn is_ok(&self) -> bool {
+        self.alive || self.completed || (self.supervised && self.consecutive_failures < MAX_CONSECUTIVE_FAILURES)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RestartPolicy {
+    /// 常驻任务，无论以何种方式结束都重启
+    Always,
+    /// 一次性任务，失败时重试直到成功
+    OnFailure,
+}
+
+/// 所有已启动的任务，未出现的任务没有启动过
+pub fn task_states() -> BTreeMap<String, TaskState> {
+    TASKS.read().unwrap().clone()
+}
+
+pub fn is_shutting_down() -> bool {
+    *SHUTDOWN.borrow()
+}
+
+/// 通知所有受监管的任务退出
+pub fn shutdown() {
+    SHUTDOWN.send_replace(true);
+}
+
+/// 立即重启受监管的任务，不计入失败次数，任务未在运行时返回false
+pub fn restart_task(name: &str) -> bool {
+    match RESTARTS.lock().unwrap().get(name) {
+        Some((_, tx)) => {
+            tx.send_modify(|n| *n += 1);
+            true
+        }
+        None => false,
+    }
+}
+
+/// 等待SIGINT或SIGTERM
+pub async fn wait_exit_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => log::info!("收到SIGINT"),
+                    _ = term.recv() => log::info!("收到SIGTERM"),
+                }
+            }
+            Err(e) => {
+                log::error!("注册SIGTERM处理失败: {e}");
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        log::info!("收到Ctrl-C");
+    }
+}
+
+async fn wait_shutdown() {
+    let mut rx = SHUTDOWN.subscribe();
+    let _ = rx.wait_for(|stop| *stop).await;
+}
+
+fn update_task(name: &str, f: impl FnOnce(&mut TaskState)) {
+    let mut tasks = TASKS.write().unwrap();
+    f(tasks.entry(name.to_string()).or_insert_with(|| TaskState::new(name)));
+}
+
+/// 启动受监管的后台任务，任务出错或结束后按策略延时重启，收到退出通知时停止；
+/// 同名任务仍在监管中时只重启该任务，不重复启动。
+/// release配置了panic = "abort"，panic会直接退出进程，只有debug构建中panic后才会重启
+pub fn supervise<F, Fut>(name: &'static str, policy: RestartPolicy, factory: F)
+where
+    F: Fn() -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), AdapterErr>> + Send + 'static,
+{
+    let (guard, mut restart_rx) = {
+        let mut restarts = RESTARTS.lock().unwrap();
+        if let Some((_, tx)) = restarts.get(name) {
+            tx.send_modify(|n| *n += 1);
+            return;
+        }
+        let generation = GENERATION.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = watch::channel(0);
+        restarts.insert(name.to_string(), (generation, tx));
+        (RestartGuard { name, generation }, rx)
+    };
+    tokio::spawn(async move {
+        let _guard = guard;
+        let mut fail_count = 0;
+        while !is_shutting_down() {
+            let started_at = Local::now().timestamp_millis();
+            update_task(name, |t| {
+                t.alive = true;
+                t.supervised = true;
+                t.completed = false;
+                t.started_at = Some(started_at);
+                t.stopped_at = None;
+            });
+            // 等待重启期间收到的通知已经没有意义
+            restart_rx.borrow_and_update();
+            // 在独立的任务中运行，debug构建中panic时也能继续重启；release中panic会终止进程
+            let handle = tokio::spawn(factory());
+            let abort = handle.abort_handle();
+            let result = tokio::select! {
+                r = handle => r,
+                _ = wait_shutdown() => {
+                    abort.abort();
+                    update_task(name, |t| {
+                        t.alive = false;
+                        t.stopped_at = Some(Local::now().timestamp_millis());
+                    });
+                    break;
+                }
+                Ok(()) = restart_rx.changed() => {
+                    abort.abort();
+                    log::info!("后台任务{name}按要求重启");
+                    fail_count = 0;
+                    update_task(name, |t| {
+                        t.alive = false;
+                        t.restart_count += 1;
+                        t.consecutive_failures = 0;
+                        t.stopped_at = Some(Local::now().timestamp_millis());
+                    });
+                    continue;
+                }
+            };
+            let error = match result {
+                Ok(Ok(())) if policy == RestartPolicy::OnFailure => {
+                    update_task(name, |t| {
+                        t.alive = false;
+                        t.completed = true;
+                        t.stopped_at = Some(Local::now().timestamp_millis());
+                    });
+                    return;
+                }
+                Ok(Ok(())) => "任务意外结束".to_string(),
+                Ok(Err(e)) => e.msg,
+                Err(e) => format!("任务panic：{e}"),
+            };
+            if Local::now().timestamp_millis() - started_at > STABLE_SECS * 1000 {
+                fail_count = 0;
+            }
+            let secs = (1u64 << fail_count.min(6)).min(RESTART_MAX_SECS);
+            fail_count += 1;
+            log::error!("后台任务{name}退出：{error}，{secs}秒后重启");
+            update_task(name, |t| {
+                t.alive = false;
+                t.restart_count += 1;
+                t.consecutive_failures = fail_count;
+                t.last_error = Some(error);
+                t.stopped_at = Some(Local::now().timestamp_millis());
+            });
+            tokio::select! {
+                _ = actix_rt::time::sleep(Duration::from_secs(secs)) => {}
+                Ok(()) = restart_rx.changed() => {}
+                _ = wait_shutdown() => break,
+            }
+        }
+    });
+}
+
+/// 启动不重启的后台任务，只记录存活状态，如解析服务需要在退出时自行处理完队列
+pub fn spawn_task<F>(name: &str, fut: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    update_task(name, |t| {
+        t.alive = true;
+        t.started_at = Some(Local::now().timestamp_millis());
+    });
+    let guard = TaskGuard(name.to_string());
+    tokio::spawn(async move {
+        let _guard = guard;
+        fut.await;
+    });
+}
+
+// 受监管的任务不再重启时注销重启通知，只注销本次监管登记的，不影响之后同名的任务
+struct RestartGuard {
+    name: &'static str,
+    generation: u64,
+}
+
+impl Drop for RestartGuard {
+    fn drop(&mut self) {
+        let mut restarts = RESTARTS.lock().unwrap();
+        if restarts.get(self.name).is_some_and(|(generation, _)| *generation == self.generation) {
+            restarts.remove(self.name);
+        }
+    }
+}
+
+// 任务结束或panic时都会被丢弃，标记任务已退出
+struct TaskGuard(String);
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        update_task(&self.0, |t| {
+            t.alive = false;
+            t.stopped_at = Some(Local::now().timestamp_millis());
+        });
+        if !is_shutting_down() {
+            log::warn!("后台任务{}已退出", self.0);
+        }
+    }
+}